@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "college")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub ipedsid: String,
+    pub name: String,
+    pub address: String,
+    pub city: String,
+    pub state: String,
+    pub zip: String,
+    #[sea_orm(column_type = "Double")]
+    pub lat: f64,
+    #[sea_orm(column_type = "Double")]
+    pub lon: f64,
+    pub naics_desc: String,
+    pub source_date: Option<Date>,
+    pub val_date: Option<Date>,
+    pub imported_at: DateTimeWithTimeZone,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub acceptance_rate: Option<f64>,
+    pub sat_avg: Option<i32>,
+    pub act_avg: Option<i32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,4 +2,10 @@
 
 pub mod prelude;
 
+pub mod application;
+pub mod application_checklist_item;
+pub mod college;
+pub mod refresh_token;
+pub mod saved_college;
+pub mod sea_orm_active_enums;
 pub mod user;
@@ -1,3 +1,8 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.4
 
+pub use super::application::Entity as Application;
+pub use super::application_checklist_item::Entity as ApplicationChecklistItem;
+pub use super::college::Entity as College;
+pub use super::refresh_token::Entity as RefreshToken;
+pub use super::saved_college::Entity as SavedCollege;
 pub use super::user::Entity as User;
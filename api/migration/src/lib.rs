@@ -1,12 +1,24 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20231104_000002_create_refresh_token_table;
+mod m20231106_000003_create_saved_college_table;
+mod m20231108_000004_create_application_tables;
+mod m20231110_000005_create_college_table;
+mod m20231112_000006_add_college_admission_metrics;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20231104_000002_create_refresh_token_table::Migration),
+            Box::new(m20231106_000003_create_saved_college_table::Migration),
+            Box::new(m20231108_000004_create_application_tables::Migration),
+            Box::new(m20231110_000005_create_college_table::Migration),
+            Box::new(m20231112_000006_add_college_admission_metrics::Migration),
+        ]
     }
 }
@@ -0,0 +1,90 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create RefreshToken table
+        // Only a hash of each token is stored, so a database leak does not hand out sessions.
+        manager.create_table(
+            sea_query::Table::create()
+            .table(RefreshToken::Table)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(RefreshToken::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+            )
+            .col(ColumnDef::new(RefreshToken::TokenHash).string().not_null().unique_key())
+            .col(ColumnDef::new(RefreshToken::UserId).integer().not_null())
+            .col(ColumnDef::new(RefreshToken::FamilyId).uuid().not_null())
+            .col(ColumnDef::new(RefreshToken::DeviceLabel).string())
+            .col(ColumnDef::new(RefreshToken::ExpiresAt).timestamp_with_time_zone().not_null())
+            .col(ColumnDef::new(RefreshToken::CreatedAt).timestamp_with_time_zone().not_null())
+            .col(ColumnDef::new(RefreshToken::RotatedFrom).integer())
+            .col(ColumnDef::new(RefreshToken::RevokedAt).timestamp_with_time_zone())
+            .foreign_key(
+                ForeignKey::create()
+                .name("fk_refresh_token_user_id")
+                .from(RefreshToken::Table, RefreshToken::UserId)
+                .to(User::Table, User::Id)
+                .on_delete(ForeignKeyAction::Cascade)
+            )
+            .foreign_key(
+                ForeignKey::create()
+                .name("fk_refresh_token_rotated_from")
+                .from(RefreshToken::Table, RefreshToken::RotatedFrom)
+                .to(RefreshToken::Table, RefreshToken::Id)
+                .on_delete(ForeignKeyAction::SetNull)
+            )
+            .to_owned()
+        ).await?;
+
+        // Revoking a family or all of a user's sessions scans by these columns.
+        manager.create_index(
+            Index::create()
+            .name("idx_refresh_token_family_id")
+            .table(RefreshToken::Table)
+            .col(RefreshToken::FamilyId)
+            .to_owned()
+        ).await?;
+        manager.create_index(
+            Index::create()
+            .name("idx_refresh_token_user_id")
+            .table(RefreshToken::Table)
+            .col(RefreshToken::UserId)
+            .to_owned()
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(sea_query::Table::drop().table(RefreshToken::Table).to_owned()).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum RefreshToken {
+    Table,
+    Id,
+    TokenHash,
+    UserId,
+    FamilyId,
+    DeviceLabel,
+    ExpiresAt,
+    CreatedAt,
+    RotatedFrom,
+    RevokedAt
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id
+}
@@ -0,0 +1,71 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create SavedCollege table
+        manager.create_table(
+            sea_query::Table::create()
+            .table(SavedCollege::Table)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(SavedCollege::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+            )
+            .col(ColumnDef::new(SavedCollege::UserId).integer().not_null())
+            .col(ColumnDef::new(SavedCollege::Ipedsid).string().not_null())
+            .col(ColumnDef::new(SavedCollege::Note).text())
+            .col(ColumnDef::new(SavedCollege::Position).integer().not_null())
+            .col(ColumnDef::new(SavedCollege::CreatedAt).timestamp_with_time_zone().not_null())
+            .foreign_key(
+                ForeignKey::create()
+                .name("fk_saved_college_user_id")
+                .from(SavedCollege::Table, SavedCollege::UserId)
+                .to(User::Table, User::Id)
+                .on_delete(ForeignKeyAction::Cascade)
+            )
+            .to_owned()
+        ).await?;
+
+        // A college can only be saved once per user.
+        manager.create_index(
+            Index::create()
+            .name("idx_saved_college_user_id_ipedsid")
+            .table(SavedCollege::Table)
+            .col(SavedCollege::UserId)
+            .col(SavedCollege::Ipedsid)
+            .unique()
+            .to_owned()
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(sea_query::Table::drop().table(SavedCollege::Table).to_owned()).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum SavedCollege {
+    Table,
+    Id,
+    UserId,
+    Ipedsid,
+    Note,
+    Position,
+    CreatedAt
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id
+}
@@ -0,0 +1,125 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create Application table
+        // Status and decision type are stored as short strings, matching the entity's active enums.
+        manager.create_table(
+            sea_query::Table::create()
+            .table(Application::Table)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(Application::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+            )
+            .col(ColumnDef::new(Application::UserId).integer().not_null())
+            .col(ColumnDef::new(Application::Ipedsid).string().not_null())
+            .col(ColumnDef::new(Application::Status).string_len(16).not_null())
+            .col(ColumnDef::new(Application::DecisionType).string_len(2))
+            .col(ColumnDef::new(Application::Deadline).date())
+            .col(ColumnDef::new(Application::Notes).text())
+            .col(ColumnDef::new(Application::CreatedAt).timestamp_with_time_zone().not_null())
+            .col(ColumnDef::new(Application::UpdatedAt).timestamp_with_time_zone().not_null())
+            .foreign_key(
+                ForeignKey::create()
+                .name("fk_application_user_id")
+                .from(Application::Table, Application::UserId)
+                .to(User::Table, User::Id)
+                .on_delete(ForeignKeyAction::Cascade)
+            )
+            .to_owned()
+        ).await?;
+
+        // A user tracks at most one application per college.
+        manager.create_index(
+            Index::create()
+            .name("idx_application_user_id_ipedsid")
+            .table(Application::Table)
+            .col(Application::UserId)
+            .col(Application::Ipedsid)
+            .unique()
+            .to_owned()
+        ).await?;
+
+        // Create ApplicationChecklistItem table
+        manager.create_table(
+            sea_query::Table::create()
+            .table(ApplicationChecklistItem::Table)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(ApplicationChecklistItem::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+            )
+            .col(ColumnDef::new(ApplicationChecklistItem::ApplicationId).integer().not_null())
+            .col(ColumnDef::new(ApplicationChecklistItem::Label).text().not_null())
+            .col(ColumnDef::new(ApplicationChecklistItem::Done).boolean().not_null().default(false))
+            .col(ColumnDef::new(ApplicationChecklistItem::DoneAt).timestamp_with_time_zone())
+            .col(ColumnDef::new(ApplicationChecklistItem::Position).integer().not_null())
+            .foreign_key(
+                ForeignKey::create()
+                .name("fk_application_checklist_item_application_id")
+                .from(ApplicationChecklistItem::Table, ApplicationChecklistItem::ApplicationId)
+                .to(Application::Table, Application::Id)
+                .on_delete(ForeignKeyAction::Cascade)
+            )
+            .to_owned()
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .name("idx_application_checklist_item_application_id")
+            .table(ApplicationChecklistItem::Table)
+            .col(ApplicationChecklistItem::ApplicationId)
+            .to_owned()
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(sea_query::Table::drop().table(ApplicationChecklistItem::Table).to_owned()).await?;
+        manager.drop_table(sea_query::Table::drop().table(Application::Table).to_owned()).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Application {
+    Table,
+    Id,
+    UserId,
+    Ipedsid,
+    Status,
+    DecisionType,
+    Deadline,
+    Notes,
+    CreatedAt,
+    UpdatedAt
+}
+
+#[derive(DeriveIden)]
+enum ApplicationChecklistItem {
+    Table,
+    Id,
+    ApplicationId,
+    Label,
+    Done,
+    DoneAt,
+    Position
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id
+}
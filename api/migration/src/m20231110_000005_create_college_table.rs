@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create College table
+        // Rows mirror the opendatasoft records and are upserted by ipedsid on every import.
+        manager.create_table(
+            sea_query::Table::create()
+            .table(College::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(College::Ipedsid).string().not_null().primary_key())
+            .col(ColumnDef::new(College::Name).string().not_null())
+            .col(ColumnDef::new(College::Address).string().not_null())
+            .col(ColumnDef::new(College::City).string().not_null())
+            .col(ColumnDef::new(College::State).string().not_null())
+            .col(ColumnDef::new(College::Zip).string().not_null())
+            .col(ColumnDef::new(College::Lat).double().not_null())
+            .col(ColumnDef::new(College::Lon).double().not_null())
+            .col(ColumnDef::new(College::NaicsDesc).string().not_null())
+            .col(ColumnDef::new(College::SourceDate).date())
+            .col(ColumnDef::new(College::ValDate).date())
+            .col(ColumnDef::new(College::ImportedAt).timestamp_with_time_zone().not_null())
+            .to_owned()
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .name("idx_college_state")
+            .table(College::Table)
+            .col(College::State)
+            .to_owned()
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(sea_query::Table::drop().table(College::Table).to_owned()).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum College {
+    Table,
+    Ipedsid,
+    Name,
+    Address,
+    City,
+    State,
+    Zip,
+    Lat,
+    Lon,
+    NaicsDesc,
+    SourceDate,
+    ValDate,
+    ImportedAt
+}
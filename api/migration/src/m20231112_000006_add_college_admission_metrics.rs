@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Admission metrics are filled in as college details are fetched, so they start out null.
+        manager.alter_table(
+            sea_query::Table::alter()
+            .table(College::Table)
+            .add_column(ColumnDef::new(College::AcceptanceRate).double())
+            .add_column(ColumnDef::new(College::SatAvg).integer())
+            .add_column(ColumnDef::new(College::ActAvg).integer())
+            .to_owned()
+        ).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            sea_query::Table::alter()
+            .table(College::Table)
+            .drop_column(College::AcceptanceRate)
+            .drop_column(College::SatAvg)
+            .drop_column(College::ActAvg)
+            .to_owned()
+        ).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum College {
+    Table,
+    AcceptanceRate,
+    SatAvg,
+    ActAvg
+}
@@ -0,0 +1,353 @@
+// The error type for route handlers and the JSON envelope every error response is sent in.
+use std::{fmt, future::Future};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use sea_orm::DbErr;
+use serde::Serialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    college_filter::FieldError, google_auth::GidTokenError, jwt::AccessTokenError,
+    refresh_token::RefreshTokenError, scraper::ScrapeError,
+};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    InvalidParams(Vec<FieldError>),
+    BadRequest(String),
+    /// No credentials were sent. Answered with a bare `Bearer` challenge, without an error code,
+    /// as RFC 6750 asks.
+    MissingToken(&'static str),
+    /// Credentials were rejected. `reason` says why in a stable, machine readable way, e.g.
+    /// `expired` so clients know to refresh their access token.
+    Unauthorized {
+        message: &'static str,
+        reason: &'static str,
+    },
+    NotFound(&'static str),
+    Conflict(&'static str),
+    /// A service the request depends on failed, e.g. College Navigator or the AI microservice.
+    Upstream(&'static str),
+    Internal(&'static str),
+}
+
+#[derive(Serialize)]
+struct ApiErrorResp<'a> {
+    code: &'static str,
+    message: &'a str,
+    details: Option<Value>,
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    /// A stable, machine readable name for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidParams(_) => "invalid_params",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::MissingToken(_) | ApiError::Unauthorized { .. } => "unauthorized",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Upstream(_) => "upstream_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::InvalidParams(_) => "Invalid query parameters",
+            ApiError::BadRequest(msg) => msg,
+            ApiError::Unauthorized { message, .. } => message,
+            ApiError::MissingToken(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::Upstream(msg)
+            | ApiError::Internal(msg) => msg,
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::InvalidParams(errors) => serde_json::to_value(errors).ok(),
+            ApiError::MissingToken(_) => Some(json!({ "reason": "missing_token" })),
+            ApiError::Unauthorized { reason, .. } => Some(json!({ "reason": reason })),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidParams(_) | ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::MissingToken(_) | ApiError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status_code());
+        match self {
+            ApiError::MissingToken(_) => {
+                resp.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+            }
+            ApiError::Unauthorized { reason, .. } => {
+                resp.insert_header((
+                    header::WWW_AUTHENTICATE,
+                    format!("Bearer error=\"invalid_token\", error_description=\"{reason}\""),
+                ));
+            }
+            _ => {}
+        }
+        resp.json(ApiErrorResp {
+            code: self.code(),
+            message: self.message(),
+            details: self.details(),
+            request_id: current_request_id(),
+        })
+    }
+}
+
+impl From<ScrapeError> for ApiError {
+    fn from(e: ScrapeError) -> Self {
+        ApiError::Upstream(e.msg())
+    }
+}
+
+impl From<DbErr> for ApiError {
+    fn from(e: DbErr) -> Self {
+        eprintln!("error: {e}");
+        ApiError::Internal("Unable to make database query")
+    }
+}
+
+impl From<AccessTokenError> for ApiError {
+    fn from(e: AccessTokenError) -> Self {
+        ApiError::Unauthorized {
+            message: e.msg(),
+            reason: e.reason(),
+        }
+    }
+}
+
+impl From<GidTokenError> for ApiError {
+    fn from(e: GidTokenError) -> Self {
+        match e {
+            GidTokenError::KeysUnavailable => ApiError::Upstream(e.msg()),
+            e => ApiError::Unauthorized {
+                message: e.msg(),
+                reason: "invalid_google_token",
+            },
+        }
+    }
+}
+
+impl From<RefreshTokenError> for ApiError {
+    fn from(e: RefreshTokenError) -> Self {
+        let reason = match e {
+            RefreshTokenError::NotFound => "invalid_refresh_token",
+            RefreshTokenError::Expired => "refresh_token_expired",
+            RefreshTokenError::Reused => "refresh_token_reused",
+            RefreshTokenError::Database => return ApiError::Internal(e.msg()),
+        };
+        ApiError::Unauthorized {
+            message: e.msg(),
+            reason,
+        }
+    }
+}
+
+/// The id of the request being handled, if called from within one.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+/// Ids passed in by a client or proxy are kept when they are short and printable, so one id
+/// can follow a request across services.
+fn usable_request_id(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?.trim();
+    let usable = !value.is_empty()
+        && value.len() <= 64
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    usable.then(|| value.to_string())
+}
+
+/// Middleware giving every request an id, available to handlers through
+/// `current_request_id` and returned in the `x-request-id` header.
+pub fn scope_request_id<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(usable_request_id)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let fut = srv.call(req);
+    REQUEST_ID.scope(request_id.clone(), async move {
+        let mut res = fut.await?;
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            res.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+        Ok(res)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        body::to_bytes,
+        rt::System,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        web, App,
+    };
+
+    use super::*;
+
+    fn envelope(resp: HttpResponse) -> Value {
+        System::new().block_on(async {
+            let body = to_bytes(resp.into_body()).await.expect("body");
+            serde_json::from_slice(&body).expect("json envelope")
+        })
+    }
+
+    #[test]
+    fn errors_are_sent_in_the_envelope() {
+        let err = ApiError::InvalidParams(vec![FieldError::new("limit", "must be positive")]);
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            envelope(resp),
+            json!({
+                "code": "invalid_params",
+                "message": "Invalid query parameters",
+                "details": [{ "field": "limit", "msg": "must be positive" }],
+                "request_id": null,
+            })
+        );
+
+        let resp = ApiError::Conflict("Already exists").error_response();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            envelope(resp),
+            json!({
+                "code": "conflict",
+                "message": "Already exists",
+                "details": null,
+                "request_id": null,
+            })
+        );
+    }
+
+    #[test]
+    fn unauthorized_errors_carry_their_reason() {
+        let resp = ApiError::from(AccessTokenError::Expired).error_response();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Bearer error=\"invalid_token\", error_description=\"expired\""
+        );
+        let body = envelope(resp);
+        assert_eq!(body["code"], "unauthorized");
+        assert_eq!(body["details"], json!({ "reason": "expired" }));
+    }
+
+    #[test]
+    fn missing_tokens_get_a_bare_challenge() {
+        let resp = ApiError::MissingToken("Missing bearer access token").error_response();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Bearer"
+        );
+        let body = envelope(resp);
+        assert_eq!(body["code"], "unauthorized");
+        assert_eq!(body["details"], json!({ "reason": "missing_token" }));
+    }
+
+    #[test]
+    fn request_ids_are_kept_or_generated() {
+        assert_eq!(
+            usable_request_id(&HeaderValue::from_static(" abc-123_x.y ")).as_deref(),
+            Some("abc-123_x.y")
+        );
+        assert!(usable_request_id(&HeaderValue::from_static("")).is_none());
+        assert!(usable_request_id(&HeaderValue::from_static("has spaces")).is_none());
+        assert!(usable_request_id(&HeaderValue::from_str(&"a".repeat(65)).unwrap()).is_none());
+    }
+
+    #[test]
+    fn request_ids_reach_the_header_and_the_envelope() {
+        System::new().block_on(async {
+            let app =
+                init_service(App::new().wrap_fn(scope_request_id).route(
+                    "/fail",
+                    web::get().to(|| async {
+                        Err::<HttpResponse, _>(ApiError::Internal("Something broke"))
+                    }),
+                ))
+                .await;
+
+            // An id passed in by the client is kept.
+            let req = TestRequest::get()
+                .uri("/fail")
+                .insert_header((REQUEST_ID_HEADER, "client-id-1"))
+                .to_request();
+            let resp = call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(
+                resp.headers().get(REQUEST_ID_HEADER).unwrap(),
+                "client-id-1"
+            );
+            let body: Value = read_body_json(resp).await;
+            assert_eq!(body["request_id"], "client-id-1");
+
+            // Otherwise one is made up, and the header and envelope agree on it.
+            let req = TestRequest::get()
+                .uri("/fail")
+                .insert_header((REQUEST_ID_HEADER, "not usable!"))
+                .to_request();
+            let resp = call_service(&app, req).await;
+            let header = resp
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            assert!(Uuid::parse_str(&header).is_ok());
+            let body: Value = read_body_json(resp).await;
+            assert_eq!(body["request_id"], header.as_str());
+        });
+    }
+}
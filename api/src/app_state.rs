@@ -1,11 +1,171 @@
-use bb8_redis::{bb8, RedisConnectionManager};
 use sea_orm::DatabaseConnection;
 
+use crate::{
+    cache::SharedCache, catalog_snapshot::CatalogSnapshots, geocoder::SharedGeocoder,
+    google_auth::GoogleAuthConfig, jwt::JwtConfig,
+};
+
 pub struct AppState {
     pub db: DatabaseConnection,
-    pub jwt_sec: String,
-    pub jwt_iss: String,
-    pub jwt_aud: String,
-    pub redis_pool: bb8::Pool<RedisConnectionManager>,
-    pub pos_stack_key: String
+    pub jwt: JwtConfig,
+    pub cache: SharedCache,
+    pub catalog: CatalogSnapshots,
+    pub geocoder: SharedGeocoder,
+    pub google_auth: GoogleAuthConfig,
+    // Whether admissions sections the parser cannot read are sent to the AI microservice.
+    pub admissions_llm_fallback: bool,
+    // Bearer token for the /admin routes, which are disabled without one.
+    pub admin_token: Option<String>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use entities::{
+        application, application_checklist_item, college, refresh_token, saved_college, user,
+    };
+    use sea_orm::{
+        sea_query::Index, ActiveModelTrait, ActiveValue, ConnectionTrait, Database, Schema,
+    };
+
+    use super::*;
+    use crate::{
+        cache::memory::MemoryCache,
+        catalog::{self, Catalog, UpstreamCollege},
+        geocoder::{GeocodeError, Geocoder},
+        jwt,
+        jwt_keys::JwtKeys,
+        structures::{CollegeCoord, CollegeStruct},
+    };
+
+    /// Knows no places, so routes never reach a real geocoding service.
+    struct NoGeocoder;
+
+    #[async_trait(?Send)]
+    impl Geocoder for NoGeocoder {
+        async fn geocode(&self, _query: &str) -> Result<Option<CollegeCoord>, GeocodeError> {
+            Ok(None)
+        }
+    }
+
+    /// A college for the test catalog.
+    pub fn test_college(
+        ipedsid: &str,
+        name: &str,
+        state: &str,
+        lat: f64,
+        lon: f64,
+    ) -> CollegeStruct {
+        CollegeStruct {
+            ipedsid: ipedsid.to_string(),
+            name: name.to_string(),
+            address: String::new(),
+            city: String::new(),
+            state: state.to_string(),
+            zip: String::new(),
+            geo_point_2d: CollegeCoord { lon, lat },
+            naics_desc: String::new(),
+            acceptance_rate: None,
+            sat_avg: None,
+            act_avg: None,
+        }
+    }
+
+    /// App state over an in-memory SQLite database whose catalog holds `colleges`.
+    /// The catalog counts as freshly imported, so it is never pulled from upstream.
+    pub async fn test_state(colleges: Vec<CollegeStruct>) -> AppState {
+        assert!(!colleges.is_empty(), "an empty catalog would be imported");
+        let db = Database::connect("sqlite::memory:")
+            .await
+            .expect("open sqlite");
+        // The migrations alter tables in ways SQLite can't, so the tables come from the
+        // entities, plus the unique indexes routes rely on.
+        let backend = db.get_database_backend();
+        let schema = Schema::new(backend);
+        let tables = [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(refresh_token::Entity),
+            schema.create_table_from_entity(saved_college::Entity),
+            schema.create_table_from_entity(application::Entity),
+            schema.create_table_from_entity(application_checklist_item::Entity),
+            schema.create_table_from_entity(college::Entity),
+        ];
+        for table in tables {
+            db.execute(backend.build(&table))
+                .await
+                .expect("create table");
+        }
+        let indexes = [
+            Index::create()
+                .name("idx_saved_college_user_id_ipedsid")
+                .table(saved_college::Entity)
+                .col(saved_college::Column::UserId)
+                .col(saved_college::Column::Ipedsid)
+                .unique()
+                .to_owned(),
+            Index::create()
+                .name("idx_application_user_id_ipedsid")
+                .table(application::Entity)
+                .col(application::Column::UserId)
+                .col(application::Column::Ipedsid)
+                .unique()
+                .to_owned(),
+        ];
+        for index in indexes {
+            db.execute(backend.build(&index))
+                .await
+                .expect("create index");
+        }
+        let records = colleges
+            .into_iter()
+            .map(|college| UpstreamCollege {
+                college,
+                sourcedate: None,
+                val_date: None,
+            })
+            .collect();
+        catalog::upsert_colleges(&db, records)
+            .await
+            .expect("import test catalog");
+
+        let cache: SharedCache = Arc::new(MemoryCache::new(100));
+        AppState {
+            db: db.clone(),
+            jwt: JwtConfig {
+                keys: Arc::new(JwtKeys::from_secret("test-secret")),
+                issuer: "https://api.example.com".to_string(),
+                audience: "app.example.com".to_string(),
+                lifetime: 60,
+                accept_legacy: false,
+            },
+            cache: cache.clone(),
+            catalog: CatalogSnapshots::new(Catalog::new(db, cache), Duration::from_secs(60)),
+            geocoder: Arc::new(NoGeocoder),
+            google_auth: GoogleAuthConfig {
+                jwks_url: "http://127.0.0.1:1/certs".to_string(),
+                client_ids: Vec::new(),
+                clock_skew: 0,
+            },
+            admissions_llm_fallback: false,
+            admin_token: None,
+        }
+    }
+
+    /// Adds a user and returns its id and an `Authorization` header value for it.
+    pub async fn sign_in(state: &AppState, email: &str) -> (i32, String) {
+        let user = user::ActiveModel {
+            email: ActiveValue::Set(email.to_string()),
+            name: ActiveValue::Set("Test Student".to_string()),
+            picture: ActiveValue::Set(String::new()),
+            ..Default::default()
+        }
+        .insert(&state.db)
+        .await
+        .expect("insert user");
+        let token = jwt::create_access_token(&state.jwt, user.id, email, "Test Student", "")
+            .expect("sign access token");
+        (user.id, format!("Bearer {token}"))
+    }
 }
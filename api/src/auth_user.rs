@@ -0,0 +1,212 @@
+// Extractor that requires a valid access token on a request.
+use std::{future::Future, pin::Pin};
+
+use actix_web::{http::header, web, FromRequest, HttpRequest};
+use entities::user::{self, Entity as User};
+use sea_orm::EntityTrait;
+
+use crate::{
+    api_error::ApiError,
+    app_state::AppState,
+    jwt::{self, AccessTokenClaims, AccessTokenError},
+};
+
+/// The signed-in user making the request.
+/// Adding this as a handler argument rejects the request with a 401 unless it carries
+/// `Authorization: Bearer <access token>` for an existing user.
+pub struct AuthenticatedUser {
+    pub claims: AccessTokenClaims,
+    pub user: user::Model,
+}
+
+/// The token in `Authorization: Bearer <token>`. The scheme is matched case-insensitively.
+pub fn get_bearer_token(req: &HttpRequest) -> Option<String> {
+    let auth_header = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = auth_header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") || token.trim().is_empty() {
+        return None;
+    }
+    Some(token.trim().to_string())
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+        let access_token = get_bearer_token(req);
+
+        Box::pin(async move {
+            let state = match state {
+                Some(state) => state,
+                None => {
+                    eprintln!("app state missing while authenticating request");
+                    return Err(ApiError::Internal("Unable to authenticate request"));
+                }
+            };
+            let access_token =
+                access_token.ok_or(ApiError::MissingToken("Missing bearer access token"))?;
+
+            let claims = jwt::decode_access_token(&access_token, &state.jwt)?;
+
+            let uid = claims
+                .sub
+                .parse::<i32>()
+                .map_err(|_| AccessTokenError::Malformed)?;
+
+            let user =
+                User::find_by_id(uid)
+                    .one(&state.db)
+                    .await?
+                    .ok_or(ApiError::Unauthorized {
+                        message: "Access token belongs to an unknown user",
+                        reason: "unknown_user",
+                    })?;
+
+            Ok(AuthenticatedUser { claims, user })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        rt::System,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App, HttpResponse,
+    };
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::app_state::tests::{sign_in, test_college, test_state};
+
+    fn bearer(value: &str) -> Option<String> {
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, value))
+            .to_http_request();
+        get_bearer_token(&req)
+    }
+
+    #[test]
+    fn bearer_tokens_are_read_with_any_case_scheme() {
+        assert_eq!(bearer("Bearer abc.def").as_deref(), Some("abc.def"));
+        assert_eq!(bearer("bearer abc.def").as_deref(), Some("abc.def"));
+        assert_eq!(bearer("BEARER  abc.def ").as_deref(), Some("abc.def"));
+        assert_eq!(bearer("Basic dXNlcjpwYXNz"), None);
+        assert_eq!(bearer("Bearer"), None);
+        assert_eq!(bearer("Bearer   "), None);
+        assert_eq!(
+            get_bearer_token(&TestRequest::default().to_http_request()),
+            None
+        );
+    }
+
+    /// What a request with `authorization` gets back: the status, the WWW-Authenticate header
+    /// and the body. `{valid}` in `authorization` stands for a valid header for a signed-in user.
+    async fn authenticate(authorization: Option<String>) -> (StatusCode, Option<String>, Value) {
+        let state = test_state(vec![test_college(
+            "100",
+            "First College",
+            "MA",
+            42.0,
+            -71.0,
+        )])
+        .await;
+        let (user_id, valid) = sign_in(&state, "student@example.com").await;
+        let app = init_service(App::new().app_data(web::Data::new(state)).route(
+            "/whoami",
+            web::get().to(|auth: AuthenticatedUser| async move {
+                HttpResponse::Ok().json(json!({ "id": auth.user.id }))
+            }),
+        ))
+        .await;
+
+        let mut req = TestRequest::get().uri("/whoami");
+        if let Some(authorization) = authorization {
+            let authorization = authorization.replace("{valid}", &valid);
+            req = req.insert_header((header::AUTHORIZATION, authorization));
+        }
+        let resp = call_service(&app, req.to_request()).await;
+        let status = resp.status();
+        let challenge = resp
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_string());
+        let mut body: Value = read_body_json(resp).await;
+        if status == StatusCode::OK {
+            assert_eq!(body["id"], user_id);
+            body = Value::Null;
+        }
+        (status, challenge, body)
+    }
+
+    fn sign(claims: Value, secret: &str) -> String {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn claims(sub: &str, exp: u64) -> Value {
+        json!({
+            "aud": "app.example.com",
+            "iss": "https://api.example.com",
+            "sub": sub,
+            "iat": get_current_timestamp(),
+            "exp": exp,
+            "email": "student@example.com",
+            "name": "Test Student",
+            "picture": "",
+        })
+    }
+
+    #[test]
+    fn valid_tokens_authenticate_their_user() {
+        System::new().block_on(async {
+            let (status, _, _) = authenticate(Some("{valid}".to_string())).await;
+            assert_eq!(status, StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn missing_tokens_get_a_bare_challenge() {
+        System::new().block_on(async {
+            for authorization in [None, Some("Basic dXNlcjpwYXNz".to_string())] {
+                let (status, challenge, body) = authenticate(authorization).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert_eq!(challenge.as_deref(), Some("Bearer"));
+                assert_eq!(body["details"]["reason"], "missing_token");
+            }
+        });
+    }
+
+    #[test]
+    fn rejected_tokens_say_why() {
+        System::new().block_on(async {
+            let later = get_current_timestamp() + 600;
+            let cases = [
+                ("not-a-jwt".to_string(), "malformed"),
+                (sign(claims("1", later), "other-secret"), "bad_signature"),
+                (sign(claims("1", 1_000), "test-secret"), "expired"),
+                (sign(claims("someone", later), "test-secret"), "malformed"),
+                (sign(claims("999", later), "test-secret"), "unknown_user"),
+            ];
+            for (token, reason) in cases {
+                let (status, challenge, body) = authenticate(Some(format!("Bearer {token}"))).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED, "{reason}");
+                assert_eq!(
+                    challenge,
+                    Some(format!(
+                        "Bearer error=\"invalid_token\", error_description=\"{reason}\""
+                    ))
+                );
+                assert_eq!(body["details"]["reason"], reason);
+            }
+        });
+    }
+}
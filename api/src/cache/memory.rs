@@ -0,0 +1,203 @@
+// A bounded in-process cache that evicts the least recently used entry when full.
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lru::LruCache;
+use uuid::Uuid;
+
+use super::{Cache, CacheError, CacheStatus};
+
+struct MemoryEntry {
+    value: String,
+    expires_at: Instant,
+}
+
+pub struct MemoryCache {
+    entries: Mutex<LruCache<String, MemoryEntry>>,
+    // Kept apart from the entries so they are never evicted. Each is a token and expiry.
+    locks: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl MemoryCache {
+    /// A capacity of zero is treated as one.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+            )),
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.lock().map_or(0, |entries| entries.len())
+    }
+}
+
+#[async_trait(?Send)]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        let Ok(mut entries) = self.entries.lock() else {
+            return Ok(None);
+        };
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Ok(Some(entry.value.clone())),
+            Some(_) => {
+                entries.pop(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError> {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.put(
+                key.to_string(),
+                MemoryEntry {
+                    value: value.to_string(),
+                    expires_at: Instant::now() + ttl,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<String>, CacheError> {
+        let Ok(mut locks) = self.locks.lock() else {
+            return Err(CacheError::Unavailable("lock table poisoned".to_string()));
+        };
+        let now = Instant::now();
+        locks.retain(|_, (_, expires_at)| *expires_at > now);
+        if locks.contains_key(key) {
+            return Ok(None);
+        }
+        let token = Uuid::new_v4().to_string();
+        locks.insert(key.to_string(), (token.clone(), now + ttl));
+        Ok(Some(token))
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), CacheError> {
+        if let Ok(mut locks) = self.locks.lock() {
+            if locks.get(key).is_some_and(|(held, _)| held == token) {
+                locks.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    async fn status(&self) -> Vec<CacheStatus> {
+        vec![CacheStatus {
+            backend: "memory",
+            healthy: true,
+            entries: Some(self.len()),
+            last_error: None,
+            last_error_at: None,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::rt::{time::sleep, System};
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn evicts_the_least_recently_used_entry_when_full() {
+        System::new().block_on(async {
+            let cache = MemoryCache::new(2);
+            cache.set("a", "1", TTL).await.unwrap();
+            cache.set("b", "2", TTL).await.unwrap();
+            // Reading "a" makes "b" the least recently used.
+            assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("1"));
+            cache.set("c", "3", TTL).await.unwrap();
+
+            assert_eq!(cache.get("b").await.unwrap(), None);
+            assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("1"));
+            assert_eq!(cache.get("c").await.unwrap().as_deref(), Some("3"));
+            assert_eq!(cache.status().await[0].entries, Some(2));
+        });
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        System::new().block_on(async {
+            let cache = MemoryCache::new(4);
+            cache
+                .set("short", "1", Duration::from_millis(10))
+                .await
+                .unwrap();
+            cache.set("long", "2", TTL).await.unwrap();
+            sleep(Duration::from_millis(20)).await;
+
+            assert_eq!(cache.get("short").await.unwrap(), None);
+            assert_eq!(cache.get("long").await.unwrap().as_deref(), Some("2"));
+            // Expired entries are dropped once seen.
+            assert_eq!(cache.status().await[0].entries, Some(1));
+        });
+    }
+
+    #[test]
+    fn locks_are_released_only_by_their_token() {
+        System::new().block_on(async {
+            let cache = MemoryCache::new(4);
+            let token = cache
+                .try_lock("job", TTL)
+                .await
+                .unwrap()
+                .expect("free lock");
+            assert_eq!(cache.try_lock("job", TTL).await.unwrap(), None);
+            // Other keys are independent.
+            assert!(cache.try_lock("other", TTL).await.unwrap().is_some());
+
+            cache.unlock("job", "someone else's token").await.unwrap();
+            assert_eq!(cache.try_lock("job", TTL).await.unwrap(), None);
+
+            cache.unlock("job", &token).await.unwrap();
+            let retaken = cache.try_lock("job", TTL).await.unwrap().expect("released");
+            assert_ne!(retaken, token);
+        });
+    }
+
+    #[test]
+    fn expired_locks_can_be_taken_again() {
+        System::new().block_on(async {
+            let cache = MemoryCache::new(4);
+            let stale = cache
+                .try_lock("job", Duration::from_millis(10))
+                .await
+                .unwrap()
+                .expect("free lock");
+            sleep(Duration::from_millis(20)).await;
+
+            let token = cache.try_lock("job", TTL).await.unwrap().expect("expired");
+            // The previous holder's late unlock leaves the new lock alone.
+            cache.unlock("job", &stale).await.unwrap();
+            assert_eq!(cache.try_lock("job", TTL).await.unwrap(), None);
+            cache.unlock("job", &token).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn locks_are_not_evicted_by_entries() {
+        System::new().block_on(async {
+            let cache = MemoryCache::new(1);
+            let _token = cache
+                .try_lock("job", TTL)
+                .await
+                .unwrap()
+                .expect("free lock");
+            cache.set("a", "1", TTL).await.unwrap();
+            cache.set("b", "2", TTL).await.unwrap();
+            assert_eq!(cache.try_lock("job", TTL).await.unwrap(), None);
+        });
+    }
+}
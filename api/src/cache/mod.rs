@@ -0,0 +1,162 @@
+// Caching of upstream answers, in Redis with an in-process fallback for when Redis is down.
+use std::{env, fmt, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bb8_redis::{bb8, RedisConnectionManager};
+use serde::Serialize;
+
+pub mod memory;
+pub mod redis;
+
+use memory::MemoryCache;
+use redis::RedisCache;
+
+/// Entries kept in process when `CACHE_MEMORY_CAPACITY` is not set.
+const DEFAULT_MEMORY_CAPACITY: usize = 1024;
+
+#[derive(Debug)]
+pub enum CacheError {
+    /// The cache could not be reached.
+    Unavailable(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Unavailable(e) => write!(f, "cache unavailable: {e}"),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+pub trait Cache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError>;
+    /// Stores `value` under `key` until `ttl` has passed.
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError>;
+    /// Takes the lock `key` for at most `ttl` unless it is already held, returning the token
+    /// to release it with.
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<String>, CacheError>;
+    /// Releases a lock taken with `try_lock`. Does nothing if it has since expired and been
+    /// taken by someone else.
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), CacheError>;
+    /// The health of each tier, for the status endpoint.
+    async fn status(&self) -> Vec<CacheStatus>;
+}
+
+pub type SharedCache = Arc<dyn Cache + Send + Sync>;
+
+#[derive(Serialize)]
+pub struct CacheStatus {
+    pub backend: &'static str,
+    pub healthy: bool,
+    // Only known for the in-process cache.
+    pub entries: Option<usize>,
+    pub last_error: Option<String>,
+    // RFC 3339.
+    pub last_error_at: Option<String>,
+}
+
+/// Redis, with the in-process cache standing in whenever Redis fails.
+/// Entries written during an outage stay in process and are not copied to Redis afterwards.
+pub struct TieredCache {
+    primary: RedisCache,
+    fallback: MemoryCache,
+}
+
+impl TieredCache {
+    pub fn new(primary: RedisCache, fallback: MemoryCache) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait(?Send)]
+impl Cache for TieredCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        match self.primary.get(key).await {
+            Ok(value) => Ok(value),
+            Err(_) => self.fallback.get(key).await,
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError> {
+        match self.primary.set(key, value, ttl).await {
+            Ok(()) => Ok(()),
+            Err(_) => self.fallback.set(key, value, ttl).await,
+        }
+    }
+
+    /// While Redis is down locks are only held within this process.
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<String>, CacheError> {
+        match self.primary.try_lock(key, ttl).await {
+            Ok(token) => Ok(token),
+            Err(_) => self.fallback.try_lock(key, ttl).await,
+        }
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), CacheError> {
+        // Redis may have gone down or come back since the lock was taken, so both tiers are
+        // released. Tokens are unique, so the other tier's locks are left alone.
+        let _ = self.primary.unlock(key, token).await;
+        self.fallback.unlock(key, token).await
+    }
+
+    async fn status(&self) -> Vec<CacheStatus> {
+        let mut status = self.primary.status().await;
+        status.extend(self.fallback.status().await);
+        status
+    }
+}
+
+/// Builds the Redis cache backed by an in-process cache of `CACHE_MEMORY_CAPACITY` entries.
+pub fn from_env(redis_pool: bb8::Pool<RedisConnectionManager>) -> SharedCache {
+    let capacity = match env::var("CACHE_MEMORY_CAPACITY") {
+        Ok(val) => val
+            .parse::<usize>()
+            .expect("Unable to parse CACHE_MEMORY_CAPACITY as usize"),
+        Err(_) => DEFAULT_MEMORY_CAPACITY,
+    };
+
+    Arc::new(TieredCache::new(
+        RedisCache::new(redis_pool),
+        MemoryCache::new(capacity),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::rt::System;
+
+    use super::{redis::tests::unreachable_redis, *};
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn falls_back_to_memory_while_redis_is_down() {
+        System::new().block_on(async {
+            let cache = TieredCache::new(unreachable_redis(), MemoryCache::new(4));
+
+            cache.set("key", "value", TTL).await.expect("memory set");
+            assert_eq!(cache.get("key").await.unwrap().as_deref(), Some("value"));
+            assert_eq!(cache.get("missing").await.unwrap(), None);
+
+            let token = cache
+                .try_lock("job", TTL)
+                .await
+                .unwrap()
+                .expect("free lock");
+            assert_eq!(cache.try_lock("job", TTL).await.unwrap(), None);
+            cache.unlock("job", &token).await.expect("memory unlock");
+            assert!(cache.try_lock("job", TTL).await.unwrap().is_some());
+
+            let status = cache.status().await;
+            assert_eq!(
+                status
+                    .iter()
+                    .map(|tier| (tier.backend, tier.healthy))
+                    .collect::<Vec<_>>(),
+                vec![("redis", false), ("memory", true)]
+            );
+            assert_eq!(status[1].entries, Some(1));
+        });
+    }
+}
@@ -0,0 +1,241 @@
+// The shared Redis cache.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bb8_redis::{bb8, redis::cmd, RedisConnectionManager};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{Cache, CacheError, CacheStatus};
+
+/// After a failure Redis is left alone this long, so requests are not each held up waiting on
+/// the pool while it is down.
+const RETRY_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Deletes a lock only if it still holds the caller's token, so an expired lock retaken by
+/// another instance is not released by mistake.
+const UNLOCK_SCRIPT: &str = r#"if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+else
+    return 0
+end"#;
+
+#[derive(Default)]
+struct RedisHealth {
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+    // Set while Redis is considered down.
+    retry_at: Option<Instant>,
+}
+
+pub struct RedisCache {
+    redis_pool: bb8::Pool<RedisConnectionManager>,
+    health: Mutex<RedisHealth>,
+}
+
+impl RedisCache {
+    pub fn new(redis_pool: bb8::Pool<RedisConnectionManager>) -> Self {
+        Self {
+            redis_pool,
+            health: Mutex::new(RedisHealth::default()),
+        }
+    }
+
+    /// Fails fast while waiting out the retry interval after a failure.
+    fn check_available(&self) -> Result<(), CacheError> {
+        let waiting = self.health.lock().is_ok_and(|health| {
+            health
+                .retry_at
+                .is_some_and(|retry_at| retry_at > Instant::now())
+        });
+        if waiting {
+            return Err(CacheError::Unavailable(
+                "waiting to retry redis".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn succeeded(&self) {
+        if let Ok(mut health) = self.health.lock() {
+            if health.retry_at.take().is_some() {
+                eprintln!("redis is available again");
+            }
+        }
+    }
+
+    async fn ping(&self) -> Result<(), CacheError> {
+        self.check_available()?;
+        let mut redis_conn = self.redis_pool.get().await.map_err(|e| self.failed(e))?;
+        cmd("PING")
+            .query_async::<_, String>(&mut *redis_conn)
+            .await
+            .map_err(|e| self.failed(e))?;
+        self.succeeded();
+        Ok(())
+    }
+
+    fn failed(&self, e: impl ToString) -> CacheError {
+        let e = e.to_string();
+        if let Ok(mut health) = self.health.lock() {
+            if health.retry_at.is_none() {
+                eprintln!("redis unavailable, using the in-process cache: {e}");
+            }
+            health.last_error = Some(e.clone());
+            health.last_error_at = Some(Utc::now());
+            health.retry_at = Some(Instant::now() + RETRY_INTERVAL);
+        }
+        CacheError::Unavailable(e)
+    }
+}
+
+#[async_trait(?Send)]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        self.check_available()?;
+        let mut redis_conn = self.redis_pool.get().await.map_err(|e| self.failed(e))?;
+        let value = cmd("GET")
+            .arg(key)
+            .query_async::<_, Option<String>>(&mut *redis_conn)
+            .await
+            .map_err(|e| self.failed(e))?;
+        self.succeeded();
+        Ok(value)
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError> {
+        self.check_available()?;
+        let mut redis_conn = self.redis_pool.get().await.map_err(|e| self.failed(e))?;
+        cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("EX")
+            // Redis rejects an expiry of zero.
+            .arg(ttl.as_secs().max(1))
+            .query_async::<_, Option<String>>(&mut *redis_conn)
+            .await
+            .map_err(|e| self.failed(e))?;
+        self.succeeded();
+        Ok(())
+    }
+
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<String>, CacheError> {
+        self.check_available()?;
+        let mut redis_conn = self.redis_pool.get().await.map_err(|e| self.failed(e))?;
+        let token = Uuid::new_v4().to_string();
+        let taken = cmd("SET")
+            .arg(key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg((ttl.as_millis() as u64).max(1))
+            .query_async::<_, Option<String>>(&mut *redis_conn)
+            .await
+            .map_err(|e| self.failed(e))?;
+        self.succeeded();
+        Ok(taken.map(|_| token))
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), CacheError> {
+        self.check_available()?;
+        let mut redis_conn = self.redis_pool.get().await.map_err(|e| self.failed(e))?;
+        cmd("EVAL")
+            .arg(UNLOCK_SCRIPT)
+            .arg(1)
+            .arg(key)
+            .arg(token)
+            .query_async::<_, i64>(&mut *redis_conn)
+            .await
+            .map_err(|e| self.failed(e))?;
+        self.succeeded();
+        Ok(())
+    }
+
+    /// Once the retry interval after a failure has passed, Redis is pinged rather than reported
+    /// down until the next request happens to use it.
+    async fn status(&self) -> Vec<CacheStatus> {
+        let retry_due = self.health.lock().is_ok_and(|health| {
+            health
+                .retry_at
+                .is_some_and(|retry_at| retry_at <= Instant::now())
+        });
+        if retry_due {
+            let _ = self.ping().await;
+        }
+
+        let Ok(health) = self.health.lock() else {
+            return Vec::new();
+        };
+        vec![CacheStatus {
+            backend: "redis",
+            healthy: health.retry_at.is_none(),
+            entries: None,
+            last_error: health.last_error.clone(),
+            last_error_at: health.last_error_at.map(|at| at.to_rfc3339()),
+        }]
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use actix_web::rt::System;
+
+    use super::*;
+
+    /// A cache whose Redis refuses every connection.
+    pub(in crate::cache) fn unreachable_redis() -> RedisCache {
+        let manager = RedisConnectionManager::new("redis://127.0.0.1:1").expect("redis url");
+        RedisCache::new(
+            bb8::Pool::builder()
+                .connection_timeout(Duration::from_millis(500))
+                .build_unchecked(manager),
+        )
+    }
+
+    #[test]
+    fn waits_out_the_retry_interval_after_a_failure() {
+        System::new().block_on(async {
+            let cache = unreachable_redis();
+            assert!(cache.get("key").await.is_err());
+            let status = cache.status().await;
+            assert!(!status[0].healthy);
+            assert!(status[0].last_error.is_some());
+
+            // Within the interval Redis is not tried again.
+            let first_error_at = status[0].last_error_at.clone();
+            assert!(matches!(
+                cache.get("key").await,
+                Err(CacheError::Unavailable(e)) if e == "waiting to retry redis"
+            ));
+            assert_eq!(cache.status().await[0].last_error_at, first_error_at);
+        });
+    }
+
+    #[test]
+    fn status_probes_redis_once_the_retry_interval_has_passed() {
+        System::new().block_on(async {
+            let cache = unreachable_redis();
+            assert!(cache.get("key").await.is_err());
+            let retry_at = Instant::now();
+            cache.health.lock().unwrap().retry_at = Some(retry_at);
+
+            // Still down, so the probe fails and the interval starts over.
+            let status = cache.status().await;
+            assert!(!status[0].healthy);
+            let health = cache.health.lock().unwrap();
+            assert!(health.retry_at.is_some_and(|next| next > retry_at));
+        });
+    }
+
+    #[test]
+    fn reports_healthy_until_redis_fails() {
+        System::new().block_on(async {
+            let status = unreachable_redis().status().await;
+            assert!(status[0].healthy);
+            assert!(status[0].last_error.is_none());
+        });
+    }
+}
@@ -0,0 +1,531 @@
+// The college catalog, stored in Postgres and refreshed from opendatasoft.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use awc::Client;
+use chrono::{Duration, NaiveDate, Utc};
+use entities::college::{self, Entity as College};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone,
+    sea_query::{Expr, OnConflict},
+    ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::SharedCache,
+    paged_fetch::{FetchError, PagedFetchConfig, PagedFetcher, PartialFetch},
+    single_flight::SingleFlight,
+    structures::{CollegeCoord, CollegeStruct},
+};
+
+/// How long an import stays fresh before the next request pulls the catalog from upstream again.
+const CATALOG_MAX_AGE_HOURS: i64 = 24;
+/// Rows per INSERT statement, kept well under the Postgres bind parameter limit.
+const UPSERT_CHUNK_SIZE: usize = 500;
+const UPSTREAM_PAGE_SIZE: usize = 100;
+/// Pages requested from upstream at once.
+const UPSTREAM_CONCURRENCY: usize = 4;
+/// A crawl that failed part way is picked up by the next refresh if it started this recently.
+const PARTIAL_CRAWL_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+const UPSTREAM_RECORDS_URL: &str = "https://public.opendatasoft.com/api/explore/v2.1/catalog/datasets/us-colleges-and-universities/records?where=naics_desc%20like%20%22COLLEGES%2C%20UNIVERSITIES%2C%20AND%20PROFESSIONAL%20SCHOOLS%22";
+
+/// A college as published upstream, with the dates the source record was last updated and
+/// validated.
+#[derive(Deserialize)]
+pub struct UpstreamCollege {
+    #[serde(flatten)]
+    pub college: CollegeStruct,
+    pub sourcedate: Option<String>,
+    pub val_date: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct ImportStats {
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+impl From<college::Model> for CollegeStruct {
+    fn from(model: college::Model) -> Self {
+        Self {
+            ipedsid: model.ipedsid,
+            name: model.name,
+            address: model.address,
+            city: model.city,
+            state: model.state,
+            zip: model.zip,
+            geo_point_2d: CollegeCoord {
+                lon: model.lon,
+                lat: model.lat,
+            },
+            naics_desc: model.naics_desc,
+            acceptance_rate: model.acceptance_rate,
+            sat_avg: model.sat_avg,
+            act_avg: model.act_avg,
+        }
+    }
+}
+
+/// Upstream dates come as either `YYYY-MM-DD` or a full timestamp; only the day is kept.
+fn parse_source_date(value: Option<&str>) -> Option<NaiveDate> {
+    let value = value?;
+    NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn to_active_model(
+    record: UpstreamCollege,
+    imported_at: DateTimeWithTimeZone,
+) -> college::ActiveModel {
+    let source_date = parse_source_date(record.sourcedate.as_deref());
+    let val_date = parse_source_date(record.val_date.as_deref());
+    let college = record.college;
+
+    college::ActiveModel {
+        ipedsid: ActiveValue::Set(college.ipedsid),
+        name: ActiveValue::Set(college.name),
+        address: ActiveValue::Set(college.address),
+        city: ActiveValue::Set(college.city),
+        state: ActiveValue::Set(college.state),
+        zip: ActiveValue::Set(college.zip),
+        lat: ActiveValue::Set(college.geo_point_2d.lat),
+        lon: ActiveValue::Set(college.geo_point_2d.lon),
+        naics_desc: ActiveValue::Set(college.naics_desc),
+        source_date: ActiveValue::Set(source_date),
+        val_date: ActiveValue::Set(val_date),
+        imported_at: ActiveValue::Set(imported_at),
+        // Upstream has no admission metrics, so these only matter for new rows and are never
+        // overwritten on conflict.
+        acceptance_rate: ActiveValue::Set(college.acceptance_rate),
+        sat_avg: ActiveValue::Set(college.sat_avg),
+        act_avg: ActiveValue::Set(college.act_avg),
+    }
+}
+
+/// Inserts new colleges and overwrites existing ones with the same `ipedsid`.
+/// Colleges missing from `records` are left alone.
+pub async fn upsert_colleges(
+    db: &DatabaseConnection,
+    records: Vec<UpstreamCollege>,
+) -> Result<ImportStats, DbErr> {
+    let existing: HashSet<String> = College::find()
+        .select_only()
+        .column(college::Column::Ipedsid)
+        .into_tuple::<String>()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect();
+
+    // A single INSERT .. ON CONFLICT cannot touch the same row twice, so the last copy of a
+    // duplicated ipedsid wins.
+    let records: HashMap<String, UpstreamCollege> = records
+        .into_iter()
+        .map(|record| (record.college.ipedsid.clone(), record))
+        .collect();
+
+    let imported_at: DateTimeWithTimeZone = Utc::now().into();
+    let mut stats = ImportStats::default();
+    let mut models = Vec::with_capacity(records.len());
+    for (ipedsid, record) in records {
+        if existing.contains(&ipedsid) {
+            stats.updated += 1;
+        } else {
+            stats.inserted += 1;
+        }
+        models.push(to_active_model(record, imported_at));
+    }
+
+    let txn = db.begin().await?;
+    while !models.is_empty() {
+        let chunk: Vec<college::ActiveModel> = models
+            .drain(..models.len().min(UPSERT_CHUNK_SIZE))
+            .collect();
+        College::insert_many(chunk)
+            .on_conflict(
+                OnConflict::column(college::Column::Ipedsid)
+                    .update_columns([
+                        college::Column::Name,
+                        college::Column::Address,
+                        college::Column::City,
+                        college::Column::State,
+                        college::Column::Zip,
+                        college::Column::Lat,
+                        college::Column::Lon,
+                        college::Column::NaicsDesc,
+                        college::Column::SourceDate,
+                        college::Column::ValDate,
+                        college::Column::ImportedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await?;
+
+    Ok(stats)
+}
+
+/// Records the admission metrics scraped for a college.
+/// Metrics that could not be read are left as they were.
+pub async fn update_admission_metrics(
+    db: &DatabaseConnection,
+    ipedsid: &str,
+    acceptance_rate: Option<f64>,
+    sat_avg: Option<i32>,
+    act_avg: Option<i32>,
+) -> Result<(), DbErr> {
+    let mut update = College::update_many().filter(college::Column::Ipedsid.eq(ipedsid));
+    if let Some(acceptance_rate) = acceptance_rate {
+        update = update.col_expr(
+            college::Column::AcceptanceRate,
+            Expr::value(acceptance_rate),
+        );
+    }
+    if let Some(sat_avg) = sat_avg {
+        update = update.col_expr(college::Column::SatAvg, Expr::value(sat_avg));
+    }
+    if let Some(act_avg) = act_avg {
+        update = update.col_expr(college::Column::ActAvg, Expr::value(act_avg));
+    }
+    if acceptance_rate.is_some() || sat_avg.is_some() || act_avg.is_some() {
+        update.exec(db).await?;
+    }
+    Ok(())
+}
+
+/// Every college in the catalog, sorted by name.
+pub async fn load_colleges(db: &DatabaseConnection) -> Result<Vec<CollegeStruct>, DbErr> {
+    Ok(College::find()
+        .order_by_asc(college::Column::Name)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(CollegeStruct::from)
+        .collect())
+}
+
+/// When the most recent import ran, or `None` if the catalog has never been imported.
+pub async fn last_imported_at(
+    db: &DatabaseConnection,
+) -> Result<Option<DateTimeWithTimeZone>, DbErr> {
+    Ok(College::find()
+        .select_only()
+        .column_as(college::Column::ImportedAt.max(), "last_imported_at")
+        .into_tuple::<Option<DateTimeWithTimeZone>>()
+        .one(db)
+        .await?
+        .flatten())
+}
+
+pub fn is_stale(last_imported_at: Option<DateTimeWithTimeZone>) -> bool {
+    match last_imported_at {
+        Some(imported_at) => {
+            Utc::now().signed_duration_since(imported_at) > Duration::hours(CATALOG_MAX_AGE_HOURS)
+        }
+        None => true,
+    }
+}
+
+fn upstream_fetcher() -> PagedFetcher {
+    PagedFetcher::new(
+        Client::default(),
+        UPSTREAM_RECORDS_URL,
+        PagedFetchConfig {
+            page_size: UPSTREAM_PAGE_SIZE,
+            concurrency: UPSTREAM_CONCURRENCY,
+            ..Default::default()
+        },
+    )
+}
+
+/// Pages through the opendatasoft dataset and returns every college in it.
+/// Pages in `resume`, left by a crawl that failed part way, are not fetched again.
+pub async fn fetch_upstream_colleges(
+    resume: Option<PartialFetch<UpstreamCollege>>,
+) -> Result<Vec<UpstreamCollege>, FetchError<UpstreamCollege>> {
+    upstream_fetcher().fetch_all(resume).await
+}
+
+/// The key refreshes are coalesced and locked under, so one runs at a time across workers and
+/// instances.
+const REFRESH_LOCK_KEY: &str = "@COLLEGE_LIST/REFRESH_LOCK";
+/// Longer than a crawl should ever take. A crashed instance's lock is released after this.
+const REFRESH_LOCK_TTL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+/// How often an instance waiting on another's refresh checks whether it is done.
+const REFRESH_LOCK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+/// How long a request for an empty catalog waits on the first import before giving up. The
+/// import carries on in the background either way.
+const COLD_START_MAX_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Holds the refresh lock, releasing it when dropped so a refresh that is cancelled part way
+/// doesn't keep other instances waiting until the lock expires.
+struct RefreshLockGuard {
+    cache: SharedCache,
+    token: Option<String>,
+}
+
+impl RefreshLockGuard {
+    async fn release(mut self) {
+        if let Some(token) = self.token.take() {
+            if let Err(e) = self.cache.unlock(REFRESH_LOCK_KEY, &token).await {
+                eprintln!("error: {e}");
+            }
+        }
+    }
+}
+
+impl Drop for RefreshLockGuard {
+    fn drop(&mut self) {
+        let Some(token) = self.token.take() else {
+            return;
+        };
+        // Nowhere to spawn the unlock while the runtime shuts down; the lock expires instead.
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+        let cache = self.cache.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = cache.unlock(REFRESH_LOCK_KEY, &token).await {
+                eprintln!("error: {e}");
+            }
+        });
+    }
+}
+
+/// How this instance's refreshes from upstream are going. Times are RFC 3339.
+#[derive(Clone, Default, Serialize)]
+pub struct UpstreamRefreshStatus {
+    pub refreshing: bool,
+    pub last_attempt_at: Option<String>,
+    pub last_success_at: Option<String>,
+    // What the last successful refresh changed.
+    pub last_import: Option<ImportStats>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+/// The catalog in the database, refreshed from upstream when it goes stale.
+#[derive(Clone)]
+pub struct Catalog {
+    db: DatabaseConnection,
+    cache: SharedCache,
+    refreshes: Arc<SingleFlight<Option<ImportStats>>>,
+    // What the last failed crawl managed to fetch, for the next one to resume from.
+    partial_crawl: Arc<Mutex<Option<PartialFetch<UpstreamCollege>>>>,
+    upstream_status: Arc<Mutex<UpstreamRefreshStatus>>,
+}
+
+impl Catalog {
+    pub fn new(db: DatabaseConnection, cache: SharedCache) -> Self {
+        Self {
+            db,
+            cache,
+            refreshes: Arc::default(),
+            partial_crawl: Arc::default(),
+            upstream_status: Arc::default(),
+        }
+    }
+
+    pub fn upstream_status(&self) -> UpstreamRefreshStatus {
+        let mut status = self
+            .upstream_status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_default();
+        status.refreshing = self.refreshes.is_running(REFRESH_LOCK_KEY);
+        status
+    }
+
+    /// Gets the catalog from the database.
+    /// A catalog older than a day is served as is while it is refreshed in the background; only
+    /// an empty catalog makes the request wait for the first import, and then for no longer than
+    /// `COLD_START_MAX_WAIT`.
+    pub async fn colleges(&self) -> Option<Vec<CollegeStruct>> {
+        let imported_at = match last_imported_at(&self.db).await {
+            Ok(imported_at) => imported_at,
+            Err(e) => {
+                eprintln!("error: {e}");
+                return None;
+            }
+        };
+
+        if is_stale(imported_at) {
+            match imported_at {
+                Some(_) => self.refresh_in_background(),
+                None => {
+                    // Spawned so the import, and the lock it holds, outlive a client that goes
+                    // away while waiting on it.
+                    let catalog = self.clone();
+                    let import = actix_web::rt::spawn(async move { catalog.refresh().await });
+                    if actix_web::rt::time::timeout(COLD_START_MAX_WAIT, import)
+                        .await
+                        .is_err()
+                    {
+                        eprintln!("timed out waiting for the first college catalog import");
+                        return None;
+                    }
+                    // Nothing to fall back on yet.
+                    if !matches!(last_imported_at(&self.db).await, Ok(Some(_))) {
+                        eprintln!("unable to import college catalog from upstream");
+                        return None;
+                    }
+                }
+            }
+        }
+
+        match load_colleges(&self.db).await {
+            Ok(colleges) => Some(colleges),
+            Err(e) => {
+                eprintln!("error: {e}");
+                None
+            }
+        }
+    }
+
+    /// Refreshes the catalog from upstream if it is older than a day, waiting for it to finish.
+    /// Returns what the refresh changed, or `None` when nothing was imported.
+    pub async fn refresh_if_stale(&self) -> Option<ImportStats> {
+        match last_imported_at(&self.db).await {
+            Ok(imported_at) if is_stale(imported_at) => self.refresh().await,
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("error: {e}");
+                None
+            }
+        }
+    }
+
+    fn refresh_in_background(&self) {
+        if self.refreshes.is_running(REFRESH_LOCK_KEY) {
+            return;
+        }
+        let catalog = self.clone();
+        actix_web::rt::spawn(async move {
+            catalog.refresh().await;
+        });
+    }
+
+    /// Refreshes the catalog from upstream, or waits for the refresh already running in this
+    /// process. Returns `None` when the refresh failed or another instance did it.
+    pub async fn refresh(&self) -> Option<ImportStats> {
+        self.refreshes
+            .run(REFRESH_LOCK_KEY, || self.refresh_with_lock())
+            .await
+    }
+
+    async fn refresh_with_lock(&self) -> Option<ImportStats> {
+        self.update_upstream_status(|status| {
+            status.last_attempt_at = Some(Utc::now().to_rfc3339());
+        });
+
+        let deadline = Instant::now() + REFRESH_LOCK_TTL;
+        let token = loop {
+            match self
+                .cache
+                .try_lock(REFRESH_LOCK_KEY, REFRESH_LOCK_TTL)
+                .await
+            {
+                Ok(Some(token)) => break Some(token),
+                Ok(None) if Instant::now() < deadline => {
+                    actix_web::rt::time::sleep(REFRESH_LOCK_POLL_INTERVAL).await
+                }
+                Ok(None) => {
+                    eprintln!("timed out waiting for another college catalog refresh");
+                    self.record_upstream_failure(
+                        "Timed out waiting for another instance's refresh".to_string(),
+                    );
+                    return None;
+                }
+                // Refreshing twice is better than not refreshing at all.
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    break None;
+                }
+            }
+        };
+        let lock = RefreshLockGuard {
+            cache: self.cache.clone(),
+            token,
+        };
+
+        // Another instance may have refreshed the catalog while this one waited for the lock.
+        let stats = match last_imported_at(&self.db).await {
+            Ok(last_imported_at) if !is_stale(last_imported_at) => {
+                self.update_upstream_status(|status| {
+                    status.last_error = None;
+                    status.consecutive_failures = 0;
+                });
+                None
+            }
+            _ => match self.refresh_from_upstream().await {
+                Ok(stats) => {
+                    self.update_upstream_status(|status| {
+                        status.last_success_at = Some(Utc::now().to_rfc3339());
+                        status.last_import = Some(stats.clone());
+                        status.last_error = None;
+                        status.consecutive_failures = 0;
+                    });
+                    Some(stats)
+                }
+                Err(e) => {
+                    eprintln!("unable to refresh college catalog from upstream: {e}");
+                    self.record_upstream_failure(e);
+                    None
+                }
+            },
+        };
+
+        lock.release().await;
+        stats
+    }
+
+    fn record_upstream_failure(&self, error: String) {
+        self.update_upstream_status(|status| {
+            status.last_error = Some(error);
+            status.consecutive_failures += 1;
+        });
+    }
+
+    fn update_upstream_status(&self, update: impl FnOnce(&mut UpstreamRefreshStatus)) {
+        if let Ok(mut status) = self.upstream_status.lock() {
+            update(&mut status);
+        }
+    }
+
+    /// Pulls the catalog from upstream and upserts it.
+    async fn refresh_from_upstream(&self) -> Result<ImportStats, String> {
+        let resume = self
+            .partial_crawl
+            .lock()
+            .ok()
+            .and_then(|mut partial_crawl| partial_crawl.take())
+            .filter(|partial| partial.started_at().elapsed() < PARTIAL_CRAWL_MAX_AGE);
+
+        let records = match fetch_upstream_colleges(resume).await {
+            Ok(records) => records,
+            Err(e) => {
+                let error = format!("Unable to crawl the college catalog: {e}");
+                if let FetchError::Incomplete {
+                    partial: Some(partial),
+                    ..
+                } = e
+                {
+                    if let Ok(mut partial_crawl) = self.partial_crawl.lock() {
+                        *partial_crawl = Some(partial);
+                    }
+                }
+                return Err(error);
+            }
+        };
+
+        upsert_colleges(&self.db, records)
+            .await
+            .map_err(|e| format!("Unable to save the college catalog: {e}"))
+    }
+}
@@ -0,0 +1,213 @@
+// The catalog held in memory with its indexes, replaced whole by a background refresh.
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use arc_swap::ArcSwapOption;
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::{
+    catalog::{Catalog, UpstreamRefreshStatus},
+    indexes::{catalog_fingerprint, CatalogIndexes},
+    single_flight::SingleFlight,
+    structures::CollegeStruct,
+};
+
+/// How often the snapshot is reloaded when `CATALOG_REFRESH_INTERVAL_SECS` is not set.
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 5 * 60;
+const RELOAD_FLIGHT_KEY: &str = "snapshot";
+
+/// One version of the catalog. Index results are positions in `colleges`.
+pub struct CatalogSnapshot {
+    pub colleges: Vec<CollegeStruct>,
+    pub indexes: Arc<CatalogIndexes>,
+}
+
+/// How the snapshot's reloads from the database are going, for the admin status endpoint, with
+/// the refreshes from upstream that feed them reported apart under `upstream`. Times are
+/// RFC 3339.
+#[derive(Clone, Default, Serialize)]
+pub struct SnapshotStatus {
+    pub reloading: bool,
+    pub item_count: usize,
+    pub refresh_interval_secs: u64,
+    pub last_attempt_at: Option<String>,
+    pub last_success_at: Option<String>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub upstream: UpstreamRefreshStatus,
+}
+
+/// The latest snapshot, shared by every worker. Readers never wait on a reload; they keep the
+/// snapshot they loaded until they drop it.
+#[derive(Clone)]
+pub struct CatalogSnapshots {
+    catalog: Catalog,
+    current: Arc<ArcSwapOption<CatalogSnapshot>>,
+    status: Arc<Mutex<SnapshotStatus>>,
+    reloads: Arc<SingleFlight<bool>>,
+    refresh_interval: Duration,
+}
+
+impl CatalogSnapshots {
+    pub fn new(catalog: Catalog, refresh_interval: Duration) -> Self {
+        Self {
+            catalog,
+            current: Arc::default(),
+            status: Arc::new(Mutex::new(SnapshotStatus {
+                refresh_interval_secs: refresh_interval.as_secs(),
+                ..Default::default()
+            })),
+            reloads: Arc::default(),
+            refresh_interval,
+        }
+    }
+
+    /// Reads the reload interval in seconds from `CATALOG_REFRESH_INTERVAL_SECS`.
+    pub fn from_env(catalog: Catalog) -> Self {
+        let refresh_interval_secs = match env::var("CATALOG_REFRESH_INTERVAL_SECS") {
+            Ok(val) => val
+                .parse::<u64>()
+                .expect("Unable to parse CATALOG_REFRESH_INTERVAL_SECS as u64"),
+            Err(_) => DEFAULT_REFRESH_INTERVAL_SECS,
+        };
+        Self::new(catalog, Duration::from_secs(refresh_interval_secs.max(1)))
+    }
+
+    /// The current snapshot. Only waits when none has been loaded yet, e.g. right after startup.
+    pub async fn get(&self) -> Option<Arc<CatalogSnapshot>> {
+        if let Some(snapshot) = self.current.load_full() {
+            return Some(snapshot);
+        }
+        self.reload().await;
+        self.current.load_full()
+    }
+
+    pub fn status(&self) -> SnapshotStatus {
+        let mut status = self
+            .status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_default();
+        status.upstream = self.catalog.upstream_status();
+        status
+    }
+
+    /// Reloads the snapshot now and then every refresh interval, refreshing the catalog from
+    /// upstream first whenever it has gone stale.
+    pub fn spawn_refresh_task(&self) {
+        let snapshots = self.clone();
+        actix_web::rt::spawn(async move {
+            loop {
+                if let Some(stats) = snapshots.catalog.refresh_if_stale().await {
+                    eprintln!(
+                        "refreshed college catalog from upstream: {} inserted, {} updated",
+                        stats.inserted, stats.updated
+                    );
+                }
+                snapshots.reload().await;
+                actix_web::rt::time::sleep(snapshots.refresh_interval).await;
+            }
+        });
+    }
+
+    /// Loads the catalog from the database and swaps it in. A failed load keeps the previous
+    /// snapshot.
+    async fn reload(&self) -> bool {
+        self.reloads
+            .run(RELOAD_FLIGHT_KEY, || self.load_and_swap())
+            .await
+    }
+
+    async fn load_and_swap(&self) -> bool {
+        self.update_status(|status| {
+            status.reloading = true;
+            status.last_attempt_at = Some(Utc::now().to_rfc3339());
+        });
+        let _reloading = ReloadingGuard {
+            status: self.status.clone(),
+        };
+
+        let Some(colleges) = self.catalog.colleges().await else {
+            self.update_status(|status| {
+                status.last_error = Some("Unable to load the college catalog".to_string());
+                status.consecutive_failures += 1;
+            });
+            return false;
+        };
+
+        // Indexes only depend on the colleges' names and places, so they are kept when only
+        // other details, like admission metrics, have changed.
+        let fingerprint = catalog_fingerprint(&colleges);
+        let indexes = match self.current.load_full() {
+            Some(current) if current.indexes.fingerprint == fingerprint => current.indexes.clone(),
+            _ => Arc::new(CatalogIndexes::build(&colleges)),
+        };
+
+        let item_count = colleges.len();
+        self.current
+            .store(Some(Arc::new(CatalogSnapshot { colleges, indexes })));
+
+        self.update_status(|status| {
+            status.item_count = item_count;
+            status.last_success_at = Some(Utc::now().to_rfc3339());
+            status.last_error = None;
+            status.consecutive_failures = 0;
+        });
+        true
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut SnapshotStatus)) {
+        if let Ok(mut status) = self.status.lock() {
+            update(&mut status);
+        }
+    }
+}
+
+/// Clears `reloading` when dropped, so a reload that is cancelled part way isn't reported as
+/// running forever.
+struct ReloadingGuard {
+    status: Arc<Mutex<SnapshotStatus>>,
+}
+
+impl Drop for ReloadingGuard {
+    fn drop(&mut self) {
+        if let Ok(mut status) = self.status.lock() {
+            status.reloading = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::rt::System;
+    use futures_util::FutureExt;
+
+    use crate::app_state::tests::{test_college, test_state};
+
+    #[test]
+    fn a_cancelled_reload_is_not_left_running() {
+        System::new().block_on(async {
+            let state = test_state(vec![test_college(
+                "100",
+                "First College",
+                "MA",
+                42.0,
+                -71.0,
+            )])
+            .await;
+
+            // The first poll marks the reload as running, then waits on the database.
+            assert!(state.catalog.load_and_swap().now_or_never().is_none());
+            assert!(!state.catalog.status().reloading);
+
+            assert!(state.catalog.load_and_swap().await);
+            let status = state.catalog.status();
+            assert!(!status.reloading);
+            assert_eq!(status.item_count, 1);
+        });
+    }
+}
@@ -0,0 +1,131 @@
+// Grouping of nearby colleges into clusters for drawing on a map.
+use std::{collections::HashMap, f64::consts::PI};
+
+use crate::structures::CollegeCoord;
+
+pub const MAX_ZOOM: u8 = 22;
+
+/// Colleges less than this many pixels apart on screen are drawn as one cluster.
+const CLUSTER_CELL_PX: f64 = 60.0;
+/// The width of the whole world at zoom level 0, as in the usual web map tiles.
+const TILE_SIZE_PX: f64 = 256.0;
+/// Web Mercator cannot show the poles; latitudes are clamped to what the maps draw.
+const MAX_MERCATOR_LAT: f64 = 85.051_128_78;
+
+/// Colleges that fall close together at some zoom level.
+pub struct Cluster {
+    pub centroid: CollegeCoord,
+    // Positions of the colleges in the list they were clustered from, in that list's order.
+    pub members: Vec<usize>,
+}
+
+/// The position of a point in screen pixels on a Web Mercator map at `zoom`.
+fn to_pixels(point: &CollegeCoord, zoom: u8) -> (f64, f64) {
+    let world_px = TILE_SIZE_PX * 2f64.powi(zoom as i32);
+    let lat = point
+        .lat
+        .clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT)
+        .to_radians();
+
+    let x = (point.lon + 180.0) / 360.0 * world_px;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * world_px;
+    (x, y)
+}
+
+/// Groups points that share a square of screen pixels at `zoom`, so clusters stay the same
+/// size on screen at every zoom level. Clusters come out largest first.
+pub fn cluster_points<'a>(
+    points: impl IntoIterator<Item = (usize, &'a CollegeCoord)>,
+    zoom: u8,
+) -> Vec<Cluster> {
+    // Per cell: the members and the sums of their coordinates.
+    let mut cells: HashMap<(i64, i64), (Vec<usize>, f64, f64)> = HashMap::new();
+    for (idx, point) in points {
+        let (x, y) = to_pixels(point, zoom);
+        let cell = cells
+            .entry((
+                (x / CLUSTER_CELL_PX).floor() as i64,
+                (y / CLUSTER_CELL_PX).floor() as i64,
+            ))
+            .or_default();
+        cell.0.push(idx);
+        cell.1 += point.lat;
+        cell.2 += point.lon;
+    }
+
+    let mut clusters: Vec<Cluster> = cells
+        .into_values()
+        .map(|(members, lat_sum, lon_sum)| Cluster {
+            centroid: CollegeCoord {
+                lon: lon_sum / members.len() as f64,
+                lat: lat_sum / members.len() as f64,
+            },
+            members,
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then_with(|| a.members[0].cmp(&b.members[0]))
+    });
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coord(lat: f64, lon: f64) -> CollegeCoord {
+        CollegeCoord { lon, lat }
+    }
+
+    fn members(clusters: &[Cluster]) -> Vec<Vec<usize>> {
+        clusters
+            .iter()
+            .map(|cluster| cluster.members.clone())
+            .collect()
+    }
+
+    #[test]
+    fn nearby_points_merge_when_zoomed_out() {
+        // Two campuses in Boston, one in Cambridge and one in New York.
+        let points = [
+            coord(42.350, -71.105),
+            coord(42.360, -71.094),
+            coord(40.807, -73.962),
+            coord(42.340, -71.090),
+        ];
+
+        let clusters = cluster_points(points.iter().enumerate(), 4);
+        assert_eq!(members(&clusters), vec![vec![0, 1, 2, 3]]);
+
+        let clusters = cluster_points(points.iter().enumerate(), 5);
+        assert_eq!(members(&clusters), vec![vec![0, 1, 3], vec![2]]);
+        let boston = &clusters[0].centroid;
+        assert!((boston.lat - 42.35).abs() < 1e-9);
+        assert!((boston.lon - -71.096_333).abs() < 1e-6);
+
+        let clusters = cluster_points(points.iter().enumerate(), MAX_ZOOM);
+        assert_eq!(members(&clusters), vec![vec![0], vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn clusters_keep_the_given_positions() {
+        let points = [coord(10.0, 10.0), coord(10.0, 10.0), coord(-10.0, -10.0)];
+        let clusters = cluster_points([(7, &points[0]), (3, &points[1]), (5, &points[2])], 2);
+
+        assert_eq!(members(&clusters), vec![vec![7, 3], vec![5]]);
+    }
+
+    #[test]
+    fn polar_points_are_clamped_onto_the_map() {
+        let points = [coord(90.0, 0.0), coord(89.0, 0.0), coord(-90.0, 0.0)];
+        let clusters = cluster_points(points.iter().enumerate(), 3);
+
+        assert_eq!(members(&clusters), vec![vec![0, 1], vec![2]]);
+        assert!(clusters
+            .iter()
+            .all(|cluster| cluster.centroid.lat.is_finite()));
+    }
+}
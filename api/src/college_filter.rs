@@ -0,0 +1,467 @@
+// Structured filters for narrowing down the college catalog.
+use serde::{Deserialize, Serialize};
+
+use crate::structures::{CollegeCoord, CollegeStruct};
+
+/// Why a query parameter was rejected.
+#[derive(Serialize, std::fmt::Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub msg: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, msg: impl Into<String>) -> Self {
+        Self {
+            field,
+            msg: msg.into(),
+        }
+    }
+}
+
+/// The raw filter parameters. Every filter is optional and they all combine with AND.
+#[derive(Deserialize, Default)]
+pub struct CollegeFilterQuery {
+    // Comma separated two letter codes, e.g. "NY,NJ".
+    pub state: Option<String>,
+    pub city: Option<String>,
+    pub zip: Option<String>,
+    pub naics_desc: Option<String>,
+    // "min_lon,min_lat,max_lon,max_lat"
+    pub bbox: Option<String>,
+    pub min_acceptance_rate: Option<String>,
+    pub max_acceptance_rate: Option<String>,
+    pub min_sat: Option<String>,
+    pub max_sat: Option<String>,
+    pub min_act: Option<String>,
+    pub max_act: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let parts: Vec<f64> = value
+            .split(',')
+            .map(|part| part.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| "must be four numbers: min_lon,min_lat,max_lon,max_lat".to_string())?;
+        let [min_lon, min_lat, max_lon, max_lat] = parts[..] else {
+            return Err("must be four numbers: min_lon,min_lat,max_lon,max_lat".to_string());
+        };
+
+        if !(-180.0..=180.0).contains(&min_lon) || !(-180.0..=180.0).contains(&max_lon) {
+            return Err("longitudes must be between -180 and 180".to_string());
+        }
+        if !(-90.0..=90.0).contains(&min_lat) || !(-90.0..=90.0).contains(&max_lat) {
+            return Err("latitudes must be between -90 and 90".to_string());
+        }
+        if min_lat > max_lat {
+            return Err("min_lat must not be greater than max_lat".to_string());
+        }
+
+        Ok(Self {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+
+    pub fn contains(&self, point: &CollegeCoord) -> bool {
+        let in_lon = if self.min_lon <= self.max_lon {
+            (self.min_lon..=self.max_lon).contains(&point.lon)
+        } else {
+            // The box crosses the antimeridian.
+            point.lon >= self.min_lon || point.lon <= self.max_lon
+        };
+        in_lon && (self.min_lat..=self.max_lat).contains(&point.lat)
+    }
+}
+
+/// An inclusive range where either end may be open.
+struct Range<T> {
+    min: Option<T>,
+    max: Option<T>,
+}
+
+impl<T: PartialOrd + Copy> Range<T> {
+    fn is_set(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+
+    /// Colleges whose metric is unknown never match a range that is set.
+    fn matches(&self, value: Option<T>) -> bool {
+        if !self.is_set() {
+            return true;
+        }
+        match value {
+            Some(value) => {
+                self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Validated filters, ready to be applied to the catalog.
+pub struct CollegeFilter {
+    states: Option<Vec<String>>,
+    city: Option<String>,
+    zip_prefix: Option<String>,
+    naics_desc: Option<String>,
+    bbox: Option<BoundingBox>,
+    acceptance_rate: Range<f64>,
+    sat: Range<i32>,
+    act: Range<i32>,
+}
+
+fn parse_bounded<T: std::str::FromStr + PartialOrd + Copy + std::fmt::Display>(
+    value: Option<&str>,
+    field: &'static str,
+    bounds: (T, T),
+    errors: &mut Vec<FieldError>,
+) -> Option<T> {
+    let value = value?;
+    match value.trim().parse::<T>() {
+        Ok(parsed) if parsed >= bounds.0 && parsed <= bounds.1 => Some(parsed),
+        _ => {
+            errors.push(FieldError::new(
+                field,
+                format!("must be a number between {} and {}", bounds.0, bounds.1),
+            ));
+            None
+        }
+    }
+}
+
+fn parse_range<T: std::str::FromStr + PartialOrd + Copy + std::fmt::Display>(
+    min: Option<&str>,
+    max: Option<&str>,
+    fields: (&'static str, &'static str),
+    bounds: (T, T),
+    errors: &mut Vec<FieldError>,
+) -> Range<T> {
+    let range = Range {
+        min: parse_bounded(min, fields.0, bounds, errors),
+        max: parse_bounded(max, fields.1, bounds, errors),
+    };
+    if let (Some(min), Some(max)) = (range.min, range.max) {
+        if min > max {
+            errors.push(FieldError::new(
+                fields.0,
+                format!("must not be greater than {}", fields.1),
+            ));
+        }
+    }
+    range
+}
+
+fn non_empty(value: Option<&String>) -> Option<&str> {
+    value
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+impl CollegeFilter {
+    /// Validates every parameter and reports all of the bad ones at once.
+    pub fn from_query(query: &CollegeFilterQuery) -> Result<Self, Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let states = non_empty(query.state.as_ref()).map(|states| {
+            states
+                .split(',')
+                .map(|state| state.trim().to_uppercase())
+                .filter(|state| !state.is_empty())
+                .collect::<Vec<String>>()
+        });
+        // A list of nothing but commas would match no college at all.
+        if states.as_ref().is_some_and(Vec::is_empty)
+            || states
+                .iter()
+                .flatten()
+                .any(|state| state.len() != 2 || !state.chars().all(|c| c.is_ascii_alphabetic()))
+        {
+            errors.push(FieldError::new(
+                "state",
+                "must be a comma separated list of two letter state codes",
+            ));
+        }
+
+        let zip_prefix = non_empty(query.zip.as_ref()).map(str::to_string);
+        if let Some(zip_prefix) = &zip_prefix {
+            if zip_prefix.len() > 5 || !zip_prefix.chars().all(|c| c.is_ascii_digit()) {
+                errors.push(FieldError::new("zip", "must be up to five digits"));
+            }
+        }
+
+        let bbox = match non_empty(query.bbox.as_ref()).map(BoundingBox::parse) {
+            Some(Ok(bbox)) => Some(bbox),
+            Some(Err(msg)) => {
+                errors.push(FieldError::new("bbox", msg));
+                None
+            }
+            None => None,
+        };
+
+        let acceptance_rate = parse_range(
+            non_empty(query.min_acceptance_rate.as_ref()),
+            non_empty(query.max_acceptance_rate.as_ref()),
+            ("min_acceptance_rate", "max_acceptance_rate"),
+            (0.0, 100.0),
+            &mut errors,
+        );
+        let sat = parse_range(
+            non_empty(query.min_sat.as_ref()),
+            non_empty(query.max_sat.as_ref()),
+            ("min_sat", "max_sat"),
+            (400, 1600),
+            &mut errors,
+        );
+        let act = parse_range(
+            non_empty(query.min_act.as_ref()),
+            non_empty(query.max_act.as_ref()),
+            ("min_act", "max_act"),
+            (1, 36),
+            &mut errors,
+        );
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Self {
+            states,
+            city: non_empty(query.city.as_ref()).map(str::to_lowercase),
+            zip_prefix,
+            naics_desc: non_empty(query.naics_desc.as_ref()).map(str::to_lowercase),
+            bbox,
+            acceptance_rate,
+            sat,
+            act,
+        })
+    }
+
+    /// The bounding box the colleges must fall in, if one was given.
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        self.bbox
+    }
+
+    pub fn matches(&self, college: &CollegeStruct) -> bool {
+        if let Some(states) = &self.states {
+            if !states
+                .iter()
+                .any(|state| state.eq_ignore_ascii_case(&college.state))
+            {
+                return false;
+            }
+        }
+        if let Some(city) = &self.city {
+            if college.city.to_lowercase() != *city {
+                return false;
+            }
+        }
+        if let Some(zip_prefix) = &self.zip_prefix {
+            if !college.zip.starts_with(zip_prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(naics_desc) = &self.naics_desc {
+            if college.naics_desc.to_lowercase() != *naics_desc {
+                return false;
+            }
+        }
+        if let Some(bbox) = &self.bbox {
+            if !bbox.contains(&college.geo_point_2d) {
+                return false;
+            }
+        }
+
+        self.acceptance_rate.matches(college.acceptance_rate)
+            && self.sat.matches(college.sat_avg)
+            && self.act.matches(college.act_avg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(params: &[(&str, &str)]) -> CollegeFilterQuery {
+        let mut query = CollegeFilterQuery::default();
+        for (name, value) in params {
+            let value = Some(value.to_string());
+            match *name {
+                "state" => query.state = value,
+                "city" => query.city = value,
+                "zip" => query.zip = value,
+                "naics_desc" => query.naics_desc = value,
+                "bbox" => query.bbox = value,
+                "min_acceptance_rate" => query.min_acceptance_rate = value,
+                "max_acceptance_rate" => query.max_acceptance_rate = value,
+                "min_sat" => query.min_sat = value,
+                "max_sat" => query.max_sat = value,
+                "min_act" => query.min_act = value,
+                "max_act" => query.max_act = value,
+                _ => panic!("unknown parameter {name}"),
+            }
+        }
+        query
+    }
+
+    /// The fields rejected for a query, in the order they were reported.
+    fn rejected(params: &[(&str, &str)]) -> Vec<&'static str> {
+        match CollegeFilter::from_query(&query(params)) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    fn filter(params: &[(&str, &str)]) -> CollegeFilter {
+        CollegeFilter::from_query(&query(params))
+            .unwrap_or_else(|errors| panic!("rejected: {errors:?}"))
+    }
+
+    fn college() -> CollegeStruct {
+        CollegeStruct {
+            ipedsid: "166027".to_string(),
+            name: "Harvard University".to_string(),
+            address: "Massachusetts Hall".to_string(),
+            city: "Cambridge".to_string(),
+            state: "MA".to_string(),
+            zip: "02138".to_string(),
+            geo_point_2d: CollegeCoord {
+                lon: -71.1167,
+                lat: 42.3770,
+            },
+            naics_desc: "COLLEGES, UNIVERSITIES, AND PROFESSIONAL SCHOOLS".to_string(),
+            acceptance_rate: Some(3.4),
+            sat_avg: Some(1550),
+            act_avg: None,
+        }
+    }
+
+    #[test]
+    fn state_codes_must_be_two_letters() {
+        assert!(rejected(&[("state", "ma, ny")]).is_empty());
+        assert_eq!(rejected(&[("state", "MAS")]), vec!["state"]);
+        assert_eq!(rejected(&[("state", "M1")]), vec!["state"]);
+        // Only separators would otherwise filter out every college.
+        assert_eq!(rejected(&[("state", ",")]), vec!["state"]);
+        assert_eq!(rejected(&[("state", " , ")]), vec!["state"]);
+        // A blank parameter is the same as leaving it out.
+        assert!(rejected(&[("state", " ")]).is_empty());
+    }
+
+    #[test]
+    fn zip_prefixes_must_be_up_to_five_digits() {
+        assert!(rejected(&[("zip", "021")]).is_empty());
+        assert_eq!(rejected(&[("zip", "021384")]), vec!["zip"]);
+        assert_eq!(rejected(&[("zip", "02l38")]), vec!["zip"]);
+    }
+
+    #[test]
+    fn bounding_boxes_must_be_four_coordinates_in_range() {
+        assert!(rejected(&[("bbox", "-72,42,-71,43")]).is_empty());
+        assert_eq!(rejected(&[("bbox", "-72,42,-71")]), vec!["bbox"]);
+        assert_eq!(rejected(&[("bbox", "-72,42,-71,north")]), vec!["bbox"]);
+        assert_eq!(rejected(&[("bbox", "-190,42,-71,43")]), vec!["bbox"]);
+        assert_eq!(rejected(&[("bbox", "-72,91,-71,43")]), vec!["bbox"]);
+        assert_eq!(rejected(&[("bbox", "-72,43,-71,42")]), vec!["bbox"]);
+    }
+
+    #[test]
+    fn numbers_must_be_in_range() {
+        assert_eq!(
+            rejected(&[
+                ("min_acceptance_rate", "-1"),
+                ("max_acceptance_rate", "101")
+            ]),
+            vec!["min_acceptance_rate", "max_acceptance_rate"]
+        );
+        assert_eq!(
+            rejected(&[("min_acceptance_rate", "NaN")]),
+            vec!["min_acceptance_rate"]
+        );
+        assert_eq!(rejected(&[("min_sat", "399")]), vec!["min_sat"]);
+        assert_eq!(rejected(&[("max_sat", "1601")]), vec!["max_sat"]);
+        assert_eq!(rejected(&[("min_sat", "1200.5")]), vec!["min_sat"]);
+        assert_eq!(rejected(&[("min_act", "0")]), vec!["min_act"]);
+        assert_eq!(rejected(&[("max_act", "thirty")]), vec!["max_act"]);
+    }
+
+    #[test]
+    fn minimums_must_not_exceed_maximums() {
+        assert_eq!(
+            rejected(&[("min_sat", "1400"), ("max_sat", "1200")]),
+            vec!["min_sat"]
+        );
+        assert_eq!(
+            rejected(&[("min_act", "30"), ("max_act", "20")]),
+            vec!["min_act"]
+        );
+        assert!(rejected(&[("min_act", "25"), ("max_act", "25")]).is_empty());
+    }
+
+    #[test]
+    fn every_bad_parameter_is_reported() {
+        assert_eq!(
+            rejected(&[("state", "Mass"), ("zip", "abc"), ("max_act", "99")]),
+            vec!["state", "zip", "max_act"]
+        );
+    }
+
+    #[test]
+    fn matches_when_every_filter_does() {
+        let college = college();
+        assert!(filter(&[]).matches(&college));
+        assert!(filter(&[
+            ("state", "ny,ma"),
+            ("city", "cambridge"),
+            ("zip", "021"),
+            (
+                "naics_desc",
+                "colleges, universities, and professional schools"
+            ),
+            ("bbox", "-72,42,-71,43"),
+            ("max_acceptance_rate", "10"),
+            ("min_sat", "1500"),
+        ])
+        .matches(&college));
+
+        assert!(!filter(&[("state", "NY")]).matches(&college));
+        assert!(!filter(&[("city", "Boston")]).matches(&college));
+        assert!(!filter(&[("zip", "100")]).matches(&college));
+        assert!(!filter(&[("naics_desc", "junior colleges")]).matches(&college));
+        assert!(!filter(&[("bbox", "-75,40,-73,41")]).matches(&college));
+        assert!(!filter(&[("min_acceptance_rate", "50")]).matches(&college));
+        assert!(!filter(&[("max_sat", "1400")]).matches(&college));
+    }
+
+    #[test]
+    fn unknown_metrics_never_match_a_range() {
+        let college = college();
+        assert!(!filter(&[("min_act", "1")]).matches(&college));
+        assert!(filter(&[("min_sat", "400")]).matches(&college));
+    }
+
+    #[test]
+    fn bounding_boxes_can_cross_the_antimeridian() {
+        let bbox = BoundingBox::parse("170,-20,-170,20").unwrap();
+        assert!(bbox.contains(&CollegeCoord {
+            lon: 179.0,
+            lat: 0.0
+        }));
+        assert!(bbox.contains(&CollegeCoord {
+            lon: -175.0,
+            lat: 0.0
+        }));
+        assert!(!bbox.contains(&CollegeCoord { lon: 0.0, lat: 0.0 }));
+    }
+}
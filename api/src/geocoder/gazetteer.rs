@@ -0,0 +1,323 @@
+// Offline geocoding of US ZIP codes and "city, state" pairs from a local CSV gazetteer.
+//
+// The file needs `zip`, `city`, `state` (two-letter code), `lat` and `lon` columns, one row per
+// ZIP code, as in the freely available US ZIP code databases.
+use std::{collections::HashMap, fs, io};
+
+use async_trait::async_trait;
+
+use super::{GeocodeError, Geocoder};
+use crate::structures::CollegeCoord;
+
+const STATE_NAMES: [(&str, &str); 56] = [
+    ("alabama", "al"),
+    ("alaska", "ak"),
+    ("american samoa", "as"),
+    ("arizona", "az"),
+    ("arkansas", "ar"),
+    ("california", "ca"),
+    ("colorado", "co"),
+    ("connecticut", "ct"),
+    ("delaware", "de"),
+    ("district of columbia", "dc"),
+    ("florida", "fl"),
+    ("georgia", "ga"),
+    ("guam", "gu"),
+    ("hawaii", "hi"),
+    ("idaho", "id"),
+    ("illinois", "il"),
+    ("indiana", "in"),
+    ("iowa", "ia"),
+    ("kansas", "ks"),
+    ("kentucky", "ky"),
+    ("louisiana", "la"),
+    ("maine", "me"),
+    ("maryland", "md"),
+    ("massachusetts", "ma"),
+    ("michigan", "mi"),
+    ("minnesota", "mn"),
+    ("mississippi", "ms"),
+    ("missouri", "mo"),
+    ("montana", "mt"),
+    ("nebraska", "ne"),
+    ("nevada", "nv"),
+    ("new hampshire", "nh"),
+    ("new jersey", "nj"),
+    ("new mexico", "nm"),
+    ("new york", "ny"),
+    ("north carolina", "nc"),
+    ("north dakota", "nd"),
+    ("northern mariana islands", "mp"),
+    ("ohio", "oh"),
+    ("oklahoma", "ok"),
+    ("oregon", "or"),
+    ("pennsylvania", "pa"),
+    ("puerto rico", "pr"),
+    ("rhode island", "ri"),
+    ("south carolina", "sc"),
+    ("south dakota", "sd"),
+    ("tennessee", "tn"),
+    ("texas", "tx"),
+    ("utah", "ut"),
+    ("vermont", "vt"),
+    ("virgin islands", "vi"),
+    ("virginia", "va"),
+    ("washington", "wa"),
+    ("west virginia", "wv"),
+    ("wisconsin", "wi"),
+    ("wyoming", "wy"),
+];
+
+/// Trailing words naming the country, which add nothing to a US lookup.
+const COUNTRY_WORDS: [&str; 3] = ["us", "usa", "united states"];
+
+fn state_code(words: &str) -> Option<&'static str> {
+    STATE_NAMES
+        .iter()
+        .find(|(name, code)| *name == words || *code == words)
+        .map(|(_, code)| *code)
+}
+
+/// Splits into lowercase words, dropping punctuation such as the comma in "Austin, TX".
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .replace('.', "")
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// "12345" or "12345-6789", as the five-digit ZIP code.
+fn parse_zip(word: &str) -> Option<&str> {
+    let zip = word.split_once('-').map_or(word, |(zip, _)| zip);
+    (zip.len() == 5 && zip.chars().all(|c| c.is_ascii_digit())).then_some(zip)
+}
+
+pub struct Gazetteer {
+    zips: HashMap<String, CollegeCoord>,
+    // Keyed by city and state code; a city spanning several ZIP codes sits at their average.
+    cities: HashMap<(String, String), CollegeCoord>,
+    // The states a city name occurs in, for queries without a state.
+    city_states: HashMap<String, Vec<String>>,
+}
+
+impl Gazetteer {
+    pub fn from_csv_file(path: &str) -> io::Result<Self> {
+        Self::from_csv(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_csv(contents: &str) -> io::Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(contents.as_bytes());
+
+        let headers: Vec<String> = reader
+            .headers()
+            .map_err(io::Error::other)?
+            .iter()
+            .map(|header| header.trim().to_lowercase())
+            .collect();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("gazetteer has no {name} column"),
+                    )
+                })
+        };
+        let (zip_col, city_col, state_col, lat_col, lon_col) = (
+            column("zip")?,
+            column("city")?,
+            column("state")?,
+            column("lat")?,
+            column("lon")?,
+        );
+
+        let mut zips = HashMap::new();
+        let mut city_sums: HashMap<(String, String), (f64, f64, usize)> = HashMap::new();
+        for record in reader.records() {
+            let record = record.map_err(io::Error::other)?;
+            let field = |col: usize| record.get(col).unwrap_or_default().trim();
+
+            // Rows without usable coordinates are skipped rather than failing the whole file.
+            let (Ok(lat), Ok(lon)) = (field(lat_col).parse::<f64>(), field(lon_col).parse::<f64>())
+            else {
+                continue;
+            };
+
+            // ZIP codes lose their leading zeros when the file has been through a spreadsheet.
+            let zip = format!("{:0>5}", field(zip_col));
+            if parse_zip(&zip).is_some() {
+                zips.insert(zip, CollegeCoord { lon, lat });
+            }
+
+            let city = words(field(city_col)).join(" ");
+            let state = field(state_col).to_lowercase();
+            if !city.is_empty() && !state.is_empty() {
+                let sums = city_sums.entry((city, state)).or_insert((0.0, 0.0, 0));
+                sums.0 += lat;
+                sums.1 += lon;
+                sums.2 += 1;
+            }
+        }
+
+        let mut city_states: HashMap<String, Vec<String>> = HashMap::new();
+        let cities = city_sums
+            .into_iter()
+            .map(|((city, state), (lat_sum, lon_sum, count))| {
+                city_states
+                    .entry(city.clone())
+                    .or_default()
+                    .push(state.clone());
+                let coords = CollegeCoord {
+                    lon: lon_sum / count as f64,
+                    lat: lat_sum / count as f64,
+                };
+                ((city, state), coords)
+            })
+            .collect();
+
+        Ok(Self {
+            zips,
+            cities,
+            city_states,
+        })
+    }
+
+    /// Looks up a ZIP code anywhere in the query, then a city followed by an optional state,
+    /// e.g. "Austin, TX" or "austin texas". A city without a state must be unambiguous.
+    pub fn lookup(&self, query: &str) -> Option<CollegeCoord> {
+        let mut query_words = words(query);
+
+        if let Some(coords) = query_words
+            .iter()
+            .filter_map(|word| parse_zip(word))
+            .find_map(|zip| self.zips.get(zip))
+        {
+            return Some(coords.clone());
+        }
+
+        for country in COUNTRY_WORDS {
+            let country_words = country.split(' ').count();
+            if query_words.len() > country_words
+                && query_words[query_words.len() - country_words..].join(" ") == country
+            {
+                query_words.truncate(query_words.len() - country_words);
+                break;
+            }
+        }
+
+        // State names are at most three words long, e.g. "district of columbia".
+        for state_words in (1..=3.min(query_words.len().saturating_sub(1))).rev() {
+            let split = query_words.len() - state_words;
+            let Some(state) = state_code(&query_words[split..].join(" ")) else {
+                continue;
+            };
+            let city = query_words[..split].join(" ");
+            if let Some(coords) = self.cities.get(&(city, state.to_string())) {
+                return Some(coords.clone());
+            }
+        }
+
+        let city = query_words.join(" ");
+        match self.city_states.get(&city).map(Vec::as_slice) {
+            Some([state]) => self.cities.get(&(city, state.clone())).cloned(),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Geocoder for Gazetteer {
+    async fn geocode(&self, query: &str) -> Result<Option<CollegeCoord>, GeocodeError> {
+        Ok(self.lookup(query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "\
+zip,city,state,lat,lon
+78701,Austin,TX,30.27,-97.74
+78705,Austin,TX,30.29,-97.74
+2139,Cambridge,MA,42.36,-71.10
+21613,Cambridge,MD,38.57,-76.08
+20001,Washington,DC,38.91,-77.02
+12345,Nowhere,NY,not a number,-73.00
+";
+
+    fn gazetteer() -> Gazetteer {
+        Gazetteer::from_csv(CSV).expect("valid gazetteer")
+    }
+
+    fn assert_at(coords: Option<CollegeCoord>, lat: f64, lon: f64) {
+        let coords = coords.expect("a match");
+        assert!(
+            (coords.lat - lat).abs() < 1e-9 && (coords.lon - lon).abs() < 1e-9,
+            "expected ({lat}, {lon}), got ({}, {})",
+            coords.lat,
+            coords.lon
+        );
+    }
+
+    #[test]
+    fn finds_zip_codes() {
+        let gazetteer = gazetteer();
+        assert_at(gazetteer.lookup("78705"), 30.29, -97.74);
+        assert_at(gazetteer.lookup("Somewhere near 78701-1234"), 30.27, -97.74);
+        // Leading zeros lost in a spreadsheet are put back.
+        assert_at(gazetteer.lookup("02139"), 42.36, -71.10);
+    }
+
+    #[test]
+    fn finds_cities_with_a_state_code() {
+        let gazetteer = gazetteer();
+        // A city spanning several ZIP codes sits at their average.
+        assert_at(gazetteer.lookup("Austin, TX"), 30.28, -97.74);
+        assert_at(gazetteer.lookup("cambridge ma"), 42.36, -71.10);
+        assert_at(gazetteer.lookup("Cambridge, MD, USA"), 38.57, -76.08);
+    }
+
+    #[test]
+    fn finds_cities_with_a_full_state_name() {
+        let gazetteer = gazetteer();
+        assert_at(gazetteer.lookup("Austin, Texas"), 30.28, -97.74);
+        assert_at(gazetteer.lookup("Cambridge Massachusetts"), 42.36, -71.10);
+        assert_at(
+            gazetteer.lookup("Washington, District of Columbia"),
+            38.91,
+            -77.02,
+        );
+    }
+
+    #[test]
+    fn finds_unambiguous_cities_without_a_state() {
+        let gazetteer = gazetteer();
+        assert_at(gazetteer.lookup("Austin"), 30.28, -97.74);
+        // Cambridge is in two states.
+        assert!(gazetteer.lookup("Cambridge").is_none());
+    }
+
+    #[test]
+    fn unknown_places_are_not_found() {
+        let gazetteer = gazetteer();
+        assert!(gazetteer.lookup("Springfield, IL").is_none());
+        assert!(gazetteer.lookup("Austin, CA").is_none());
+        assert!(gazetteer.lookup("99999").is_none());
+        assert!(gazetteer.lookup("").is_none());
+        // Rows without coordinates are skipped.
+        assert!(gazetteer.lookup("12345").is_none());
+        assert!(gazetteer.lookup("Nowhere, NY").is_none());
+    }
+
+    #[test]
+    fn files_without_the_needed_columns_are_rejected() {
+        assert!(Gazetteer::from_csv("zip,city,lat,lon\n78701,Austin,30.27,-97.74\n").is_err());
+    }
+}
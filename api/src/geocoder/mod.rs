@@ -0,0 +1,141 @@
+// Turning a place typed by the user into coordinates.
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::{cache::SharedCache, structures::CollegeCoord};
+
+pub mod gazetteer;
+pub mod nominatim;
+pub mod positionstack;
+
+use gazetteer::Gazetteer;
+use nominatim::NominatimGeocoder;
+use positionstack::PositionStackGeocoder;
+
+/// Found places are cached for a month; misses only for a day, in case the place gets added.
+const GEOCODE_CACHE_EXP: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const GEOCODE_MISS_CACHE_EXP: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(std::fmt::Debug)]
+pub enum GeocodeError {
+    /// The geocoding service could not be reached or gave an unreadable answer.
+    Unavailable,
+}
+
+#[async_trait(?Send)]
+pub trait Geocoder {
+    /// Finds the coordinates of `query`, or `None` if no such place is known.
+    async fn geocode(&self, query: &str) -> Result<Option<CollegeCoord>, GeocodeError>;
+}
+
+pub type SharedGeocoder = Arc<dyn Geocoder + Send + Sync>;
+
+/// Lowercases and collapses whitespace so equivalent queries share a cache entry.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Asks each geocoder in turn until one finds the place.
+/// Only fails when nothing was found and at least one geocoder was unavailable.
+pub struct GeocoderChain {
+    geocoders: Vec<Box<dyn Geocoder + Send + Sync>>,
+}
+
+#[async_trait(?Send)]
+impl Geocoder for GeocoderChain {
+    async fn geocode(&self, query: &str) -> Result<Option<CollegeCoord>, GeocodeError> {
+        let mut unavailable = false;
+        for geocoder in &self.geocoders {
+            match geocoder.geocode(query).await {
+                Ok(Some(coords)) => return Ok(Some(coords)),
+                Ok(None) => {}
+                Err(GeocodeError::Unavailable) => unavailable = true,
+            }
+        }
+        if unavailable {
+            Err(GeocodeError::Unavailable)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Caches another geocoder's answers under the normalized query.
+/// The cache being down only costs a cache miss.
+pub struct CachedGeocoder<G> {
+    inner: G,
+    cache: SharedCache,
+}
+
+impl<G> CachedGeocoder<G> {
+    pub fn new(inner: G, cache: SharedCache) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait(?Send)]
+impl<G: Geocoder> Geocoder for CachedGeocoder<G> {
+    async fn geocode(&self, query: &str) -> Result<Option<CollegeCoord>, GeocodeError> {
+        let normalized = normalize_query(query);
+        let cache_key = format!("GEOCODE_{normalized}");
+
+        if let Ok(Some(cached)) = self.cache.get(&cache_key).await {
+            if let Ok(coords) = serde_json::from_str::<Option<CollegeCoord>>(&cached) {
+                return Ok(coords);
+            }
+        }
+
+        let coords = self.inner.geocode(&normalized).await?;
+
+        if let Ok(serialized) = serde_json::to_string(&coords) {
+            let exp = match coords {
+                Some(_) => GEOCODE_CACHE_EXP,
+                None => GEOCODE_MISS_CACHE_EXP,
+            };
+            let _cache_store_resp = self.cache.set(&cache_key, &serialized, exp).await;
+        }
+
+        Ok(coords)
+    }
+}
+
+/// Builds the geocoder selected by `GEOCODER`: "positionstack" (the default when
+/// `POS_STACK_KEY` is set), "nominatim" (the default otherwise) or "gazetteer".
+/// When `GAZETTEER_PATH` is set the local gazetteer also backs up the online geocoders.
+pub fn from_env(cache: SharedCache) -> SharedGeocoder {
+    let gazetteer = std::env::var("GAZETTEER_PATH").ok().map(|path| {
+        Gazetteer::from_csv_file(&path)
+            .unwrap_or_else(|e| panic!("Unable to load gazetteer from {path}: {e}"))
+    });
+    let pos_stack_key = std::env::var("POS_STACK_KEY").ok();
+
+    let geocoder_name = std::env::var("GEOCODER").unwrap_or_else(|_| {
+        match pos_stack_key {
+            Some(_) => "positionstack",
+            None => "nominatim",
+        }
+        .to_string()
+    });
+
+    let mut geocoders: Vec<Box<dyn Geocoder + Send + Sync>> = Vec::new();
+    match geocoder_name.as_str() {
+        "positionstack" => geocoders.push(Box::new(PositionStackGeocoder::new(
+            pos_stack_key.expect("No POS_STACK_KEY in .env file"),
+        ))),
+        "nominatim" => geocoders.push(Box::new(NominatimGeocoder::from_env())),
+        "gazetteer" => {}
+        other => panic!("Unknown GEOCODER {other}, expected positionstack, nominatim or gazetteer"),
+    }
+    match gazetteer {
+        Some(gazetteer) => geocoders.push(Box::new(gazetteer)),
+        None if geocoders.is_empty() => panic!("GEOCODER=gazetteer requires GAZETTEER_PATH"),
+        None => {}
+    }
+
+    Arc::new(CachedGeocoder::new(GeocoderChain { geocoders }, cache))
+}
@@ -0,0 +1,79 @@
+// Geocoding through a Nominatim-compatible search API, e.g. OpenStreetMap's or a self-hosted one.
+use async_trait::async_trait;
+use awc::{http::header, Client};
+use serde::Deserialize;
+
+use super::{GeocodeError, Geocoder};
+use crate::structures::CollegeCoord;
+
+const DEFAULT_NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+const DEFAULT_NOMINATIM_USER_AGENT: &str = "college-app-api";
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    // Nominatim returns coordinates as strings.
+    lat: String,
+    lon: String,
+}
+
+pub struct NominatimGeocoder {
+    search_url: String,
+    user_agent: String,
+}
+
+impl NominatimGeocoder {
+    /// Uses `NOMINATIM_URL` and `NOMINATIM_USER_AGENT`; the public instance requires a
+    /// descriptive user agent.
+    pub fn from_env() -> Self {
+        let base_url =
+            std::env::var("NOMINATIM_URL").unwrap_or_else(|_| DEFAULT_NOMINATIM_URL.to_string());
+        Self {
+            search_url: format!("{}/search", base_url.trim_end_matches('/')),
+            user_agent: std::env::var("NOMINATIM_USER_AGENT")
+                .unwrap_or_else(|_| DEFAULT_NOMINATIM_USER_AGENT.to_string()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Geocoder for NominatimGeocoder {
+    async fn geocode(&self, query: &str) -> Result<Option<CollegeCoord>, GeocodeError> {
+        let awc_client = Client::default();
+
+        let req_query = [
+            ("q", query),
+            ("format", "jsonv2"),
+            ("limit", "1"),
+            ("countrycodes", "us"),
+        ];
+
+        let request = awc_client
+            .get(&self.search_url)
+            .insert_header((header::USER_AGENT, self.user_agent.as_str()))
+            .query(&req_query)
+            .map_err(|e| {
+                eprintln!("error: {e}");
+                GeocodeError::Unavailable
+            })?;
+        let mut resp = request.send().await.map_err(|e| {
+            eprintln!("error: {e}");
+            GeocodeError::Unavailable
+        })?;
+        let resp_body = resp.body().await.map_err(|e| {
+            eprintln!("error: {e}");
+            GeocodeError::Unavailable
+        })?;
+
+        let places = serde_json::from_slice::<Vec<NominatimPlace>>(&resp_body).map_err(|e| {
+            eprintln!("error: {e}");
+            GeocodeError::Unavailable
+        })?;
+
+        Ok(places.first().and_then(|place| {
+            Some(CollegeCoord {
+                lon: place.lon.parse().ok()?,
+                lat: place.lat.parse().ok()?,
+            })
+        }))
+    }
+}
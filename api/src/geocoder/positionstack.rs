@@ -0,0 +1,74 @@
+// Geocoding through api.positionstack.com.
+use async_trait::async_trait;
+use awc::Client;
+use serde::Deserialize;
+
+use super::{GeocodeError, Geocoder};
+use crate::structures::CollegeCoord;
+
+const DEFAULT_POS_STACK_URL: &str = "https://api.positionstack.com/v1/forward";
+
+#[derive(Deserialize)]
+pub struct PosStackResp {
+    pub data: Vec<PosStackCoord>,
+}
+
+#[derive(Deserialize)]
+pub struct PosStackCoord {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+pub struct PositionStackGeocoder {
+    access_key: String,
+    url: String,
+}
+
+impl PositionStackGeocoder {
+    /// `POS_STACK_URL` overrides the endpoint, e.g. for plans without HTTPS.
+    pub fn new(access_key: String) -> Self {
+        Self {
+            access_key,
+            url: std::env::var("POS_STACK_URL")
+                .unwrap_or_else(|_| DEFAULT_POS_STACK_URL.to_string()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Geocoder for PositionStackGeocoder {
+    async fn geocode(&self, query: &str) -> Result<Option<CollegeCoord>, GeocodeError> {
+        let awc_client = Client::default();
+
+        let req_query = [
+            ("access_key", self.access_key.as_str()),
+            ("query", query),
+            ("output", "json"),
+            ("limit", "1"),
+        ];
+
+        let request = awc_client.get(&self.url).query(&req_query).map_err(|e| {
+            eprintln!("error: {e}");
+            GeocodeError::Unavailable
+        })?;
+        let mut resp = request.send().await.map_err(|e| {
+            eprintln!("error: {e}");
+            GeocodeError::Unavailable
+        })?;
+        let resp_body = resp.body().await.map_err(|e| {
+            eprintln!("error: {e}");
+            GeocodeError::Unavailable
+        })?;
+
+        match serde_json::from_slice::<PosStackResp>(&resp_body) {
+            Ok(val) => Ok(val.data.first().map(|coord_obj| CollegeCoord {
+                lon: coord_obj.longitude,
+                lat: coord_obj.latitude,
+            })),
+            Err(e) => {
+                eprintln!("error: {e}");
+                Err(GeocodeError::Unavailable)
+            }
+        }
+    }
+}
@@ -0,0 +1,511 @@
+// Verification of Google ID tokens against Google's published signing keys.
+use std::time::Duration;
+
+use awc::{http::header, Client};
+use jsonwebtoken::{
+    decode, decode_header, errors::ErrorKind, get_current_timestamp, jwk::JwkSet, Algorithm,
+    DecodingKey, Validation,
+};
+use serde::Deserialize;
+
+use crate::cache::Cache;
+
+const GOOGLE_JWKS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+const GOOGLE_ISSUERS: [&str; 2] = ["accounts.google.com", "https://accounts.google.com"];
+const GOOGLE_JWKS_CACHE_KEY: &str = "@GOOGLE_JWKS/CACHE";
+// Marks the last fetch forced by an unknown kid. Such fetches are limited to one per interval so
+// tokens with made-up kids cannot make every request go to Google.
+const GOOGLE_JWKS_REFETCH_KEY: &str = "@GOOGLE_JWKS/REFETCHED";
+const GOOGLE_JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+// Used when Google does not send a usable max-age.
+const GOOGLE_JWKS_DEFAULT_EXP: usize = 5 * 60;
+const DEFAULT_CLOCK_SKEW: u64 = 60;
+
+/// Settings for verifying Google ID tokens.
+/// The JWKS URL can be pointed at a local server so the verifier can be exercised without Google.
+#[derive(Clone)]
+pub struct GoogleAuthConfig {
+    pub jwks_url: String,
+    pub client_ids: Vec<String>,
+    pub clock_skew: u64,
+}
+
+impl GoogleAuthConfig {
+    /// Reads `GOOGLE_CLIENT_IDS` (comma separated), and optionally `GOOGLE_JWKS_URL` and `GOOGLE_CLOCK_SKEW`.
+    pub fn from_env() -> Self {
+        let client_ids: Vec<String> = std::env::var("GOOGLE_CLIENT_IDS")
+            .expect("No GOOGLE_CLIENT_IDS in .env file")
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect();
+        if client_ids.is_empty() {
+            panic!("GOOGLE_CLIENT_IDS must contain at least one client id");
+        }
+
+        let jwks_url =
+            std::env::var("GOOGLE_JWKS_URL").unwrap_or_else(|_| GOOGLE_JWKS_URL.to_string());
+
+        let clock_skew = match std::env::var("GOOGLE_CLOCK_SKEW") {
+            Ok(val) => val
+                .parse::<u64>()
+                .expect("Unable to parse GOOGLE_CLOCK_SKEW as u64"),
+            Err(_) => DEFAULT_CLOCK_SKEW,
+        };
+
+        Self {
+            jwks_url,
+            client_ids,
+            clock_skew,
+        }
+    }
+}
+
+#[derive(Deserialize, std::fmt::Debug)]
+pub struct GoogleIdTokenPayload {
+    pub azp: Option<String>,
+    pub iat: u64,
+
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+    pub picture: Option<String>,
+}
+
+#[derive(std::fmt::Debug)]
+pub enum GidTokenError {
+    Malformed,
+    UnknownKey,
+    KeysUnavailable,
+    InvalidSignature,
+    Expired,
+    IssuedInFuture,
+    InvalidIssuer,
+    InvalidAudience,
+    EmailNotVerified,
+}
+
+impl GidTokenError {
+    pub fn msg(&self) -> &'static str {
+        match self {
+            GidTokenError::Malformed => "Malformed Google Id Token",
+            GidTokenError::UnknownKey => "Google Id Token signed with an unknown key",
+            GidTokenError::KeysUnavailable => "Unable to fetch Google signing keys",
+            GidTokenError::InvalidSignature => "Invalid Google Id Token signature",
+            GidTokenError::Expired => "Google Id Token has expired",
+            GidTokenError::IssuedInFuture => "Google Id Token was issued in the future",
+            GidTokenError::InvalidIssuer => "Google Id Token has an invalid issuer",
+            GidTokenError::InvalidAudience => "Google Id Token was not issued for this app",
+            GidTokenError::EmailNotVerified => "Google account email is not verified",
+        }
+    }
+}
+
+/// Verifies the signature and claims of a Google ID token and returns its payload.
+pub async fn verify_gid_token(
+    gid_token: &str,
+    config: &GoogleAuthConfig,
+    cache: &dyn Cache,
+) -> Result<GoogleIdTokenPayload, GidTokenError> {
+    let token_header = decode_header(gid_token).map_err(|_| GidTokenError::Malformed)?;
+    if token_header.alg != Algorithm::RS256 {
+        return Err(GidTokenError::Malformed);
+    }
+    let kid = token_header.kid.ok_or(GidTokenError::Malformed)?;
+
+    // Google rotates its keys, so an unknown kid on a cached set warrants one fresh fetch, as
+    // long as there hasn't been one recently.
+    let mut jwks = get_google_jwks(&config.jwks_url, cache, false)
+        .await
+        .ok_or(GidTokenError::KeysUnavailable)?;
+    if jwks.find(&kid).is_none() && may_refetch_google_jwks(cache).await {
+        jwks = get_google_jwks(&config.jwks_url, cache, true)
+            .await
+            .ok_or(GidTokenError::KeysUnavailable)?;
+    }
+    let jwk = jwks.find(&kid).ok_or(GidTokenError::UnknownKey)?;
+    let decoding_key = DecodingKey::from_jwk(jwk).map_err(|_| GidTokenError::UnknownKey)?;
+
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_issuer(&GOOGLE_ISSUERS);
+    validation.set_audience(&config.client_ids);
+    validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+    validation.leeway = config.clock_skew;
+
+    let payload = match decode::<GoogleIdTokenPayload>(gid_token, &decoding_key, &validation) {
+        Ok(td) => td.claims,
+        Err(e) => {
+            return Err(match e.kind() {
+                ErrorKind::ExpiredSignature => GidTokenError::Expired,
+                ErrorKind::InvalidIssuer => GidTokenError::InvalidIssuer,
+                ErrorKind::InvalidAudience => GidTokenError::InvalidAudience,
+                ErrorKind::InvalidSignature => GidTokenError::InvalidSignature,
+                e => {
+                    eprintln!("google id token error: {:?}", e);
+                    GidTokenError::Malformed
+                }
+            })
+        }
+    };
+
+    if payload.iat > get_current_timestamp() + config.clock_skew {
+        return Err(GidTokenError::IssuedInFuture);
+    }
+
+    // When present, the authorized party must also be one of our clients.
+    if let Some(azp) = &payload.azp {
+        if !config.client_ids.contains(azp) {
+            return Err(GidTokenError::InvalidAudience);
+        }
+    }
+
+    if payload.email_verified != Some(true) {
+        return Err(GidTokenError::EmailNotVerified);
+    }
+
+    Ok(payload)
+}
+
+/// Whether a forced fetch of the keys is allowed now, recording it if so.
+/// The lock is never released; it expires after the interval, so holding it means a fetch was
+/// forced within the last interval.
+async fn may_refetch_google_jwks(cache: &dyn Cache) -> bool {
+    match cache
+        .try_lock(GOOGLE_JWKS_REFETCH_KEY, GOOGLE_JWKS_REFETCH_INTERVAL)
+        .await
+    {
+        Ok(token) => token.is_some(),
+        Err(e) => {
+            eprintln!("error: {e}");
+            false
+        }
+    }
+}
+
+async fn get_google_jwks(jwks_url: &str, cache: &dyn Cache, skip_cache: bool) -> Option<JwkSet> {
+    if !skip_cache {
+        if let Ok(Some(cached)) = cache.get(GOOGLE_JWKS_CACHE_KEY).await {
+            match serde_json::from_str::<JwkSet>(&cached) {
+                Ok(jwks) => return Some(jwks),
+                Err(_) => eprintln!("unable to deserialize cached google jwks"),
+            }
+        }
+    }
+
+    let mut resp = match Client::default().get(jwks_url).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return None;
+        }
+    };
+    if !resp.status().is_success() {
+        eprintln!("google jwks request failed with status {}", resp.status());
+        return None;
+    }
+
+    let max_age = resp
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|val| val.to_str().ok())
+        .and_then(parse_max_age);
+
+    let resp_body = match resp.body().await {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return None;
+        }
+    };
+    let jwks = match serde_json::from_slice::<JwkSet>(&resp_body) {
+        Ok(jwks) => jwks,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return None;
+        }
+    };
+
+    // A max-age of zero means the keys must not be cached at all.
+    let exp = max_age.unwrap_or(GOOGLE_JWKS_DEFAULT_EXP);
+    if exp > 0 {
+        if let Err(e) = cache
+            .set(
+                GOOGLE_JWKS_CACHE_KEY,
+                String::from_utf8_lossy(&resp_body).as_ref(),
+                Duration::from_secs(exp as u64),
+            )
+            .await
+        {
+            eprintln!("unable to cache google jwks: {e}");
+        }
+    }
+
+    Some(jwks)
+}
+
+fn parse_max_age(cache_control: &str) -> Option<usize> {
+    if cache_control
+        .split(',')
+        .any(|directive| matches!(directive.trim(), "no-store" | "no-cache"))
+    {
+        return Some(0);
+    }
+
+    cache_control.split(',').find_map(|directive| {
+        directive
+            .trim()
+            .strip_prefix("max-age=")
+            .and_then(|secs| secs.parse::<usize>().ok())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use actix_web::{dev::ServerHandle, rt::System, web, App, HttpResponse, HttpServer};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::cache::memory::MemoryCache;
+
+    const CLIENT_ID: &str = "test-client.apps.googleusercontent.com";
+    const KID: &str = "test-key-1";
+    const OTHER_KID: &str = "test-key-2";
+
+    /// A stand-in for Google's JWKS endpoint, serving whatever `jwks` holds and counting fetches.
+    struct FakeJwks {
+        jwks: Arc<Mutex<Value>>,
+        fetches: Arc<AtomicUsize>,
+        config: GoogleAuthConfig,
+        server: ServerHandle,
+    }
+
+    impl FakeJwks {
+        fn start(keys: Vec<Value>) -> Self {
+            let jwks = Arc::new(Mutex::new(json!({ "keys": keys })));
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let (served, counted) = (jwks.clone(), fetches.clone());
+            let server = HttpServer::new(move || {
+                let (served, counted) = (served.clone(), counted.clone());
+                App::new().route(
+                    "/certs",
+                    web::get().to(move || {
+                        counted.fetch_add(1, Ordering::SeqCst);
+                        let body = served.lock().unwrap().to_string();
+                        async move {
+                            HttpResponse::Ok()
+                                .insert_header((header::CACHE_CONTROL, "public, max-age=3600"))
+                                .content_type("application/json")
+                                .body(body)
+                        }
+                    }),
+                )
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("bind fake jwks server");
+            let port = server.addrs()[0].port();
+            let server = server.run();
+            let handle = server.handle();
+            actix_web::rt::spawn(server);
+
+            Self {
+                jwks,
+                fetches,
+                config: GoogleAuthConfig {
+                    jwks_url: format!("http://127.0.0.1:{port}/certs"),
+                    client_ids: vec![CLIENT_ID.to_string()],
+                    clock_skew: DEFAULT_CLOCK_SKEW,
+                },
+                server: handle,
+            }
+        }
+
+        fn fetches(&self) -> usize {
+            self.fetches.load(Ordering::SeqCst)
+        }
+    }
+
+    fn jwk() -> Value {
+        serde_json::from_str(include_str!("fixtures/rsa_jwk.json")).unwrap()
+    }
+
+    fn other_jwk() -> Value {
+        serde_json::from_str(include_str!("fixtures/rsa_other_jwk.json")).unwrap()
+    }
+
+    fn claims() -> Value {
+        let now = get_current_timestamp();
+        json!({
+            "iss": "https://accounts.google.com",
+            "aud": CLIENT_ID,
+            "azp": CLIENT_ID,
+            "sub": "1234567890",
+            "iat": now,
+            "exp": now + 3600,
+            "email": "student@example.com",
+            "email_verified": true,
+            "name": "Test Student",
+        })
+    }
+
+    fn sign(claims: &Value, kid: &str, private_pem: &[u8]) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let key = EncodingKey::from_rsa_pem(private_pem).expect("test private key");
+        encode(&header, claims, &key).expect("sign test token")
+    }
+
+    fn sign_with_test_key(claims: &Value) -> String {
+        sign(claims, KID, include_bytes!("fixtures/rsa_private.pem"))
+    }
+
+    /// Verifies a token against a fresh fake JWKS endpoint serving the test key.
+    fn verify(gid_token: &str) -> Result<GoogleIdTokenPayload, GidTokenError> {
+        System::new().block_on(async {
+            let google = FakeJwks::start(vec![jwk()]);
+            let result = verify_gid_token(gid_token, &google.config, &MemoryCache::new(16)).await;
+            google.server.stop(false).await;
+            result
+        })
+    }
+
+    #[test]
+    fn accepts_a_valid_token() {
+        let payload = verify(&sign_with_test_key(&claims())).expect("valid token");
+        assert_eq!(payload.email.as_deref(), Some("student@example.com"));
+        assert_eq!(payload.name.as_deref(), Some("Test Student"));
+    }
+
+    #[test]
+    fn rejects_a_token_signed_by_another_key() {
+        let gid_token = sign(
+            &claims(),
+            KID,
+            include_bytes!("fixtures/rsa_other_private.pem"),
+        );
+        assert!(matches!(
+            verify(&gid_token),
+            Err(GidTokenError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_the_wrong_issuer_audience_and_authorized_party() {
+        let with_claim = |claim: &str, value: &str| {
+            let mut claims = claims();
+            claims[claim] = json!(value);
+            verify(&sign_with_test_key(&claims))
+        };
+
+        assert!(matches!(
+            with_claim("iss", "https://evil.example.com"),
+            Err(GidTokenError::InvalidIssuer)
+        ));
+        assert!(matches!(
+            with_claim("aud", "someone-else.apps.googleusercontent.com"),
+            Err(GidTokenError::InvalidAudience)
+        ));
+        assert!(matches!(
+            with_claim("azp", "someone-else.apps.googleusercontent.com"),
+            Err(GidTokenError::InvalidAudience)
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_and_future_tokens() {
+        let now = get_current_timestamp();
+
+        let mut expired = claims();
+        expired["iat"] = json!(now - 7200);
+        expired["exp"] = json!(now - 3600);
+        assert!(matches!(
+            verify(&sign_with_test_key(&expired)),
+            Err(GidTokenError::Expired)
+        ));
+
+        let mut future = claims();
+        future["iat"] = json!(now + 600);
+        future["exp"] = json!(now + 4200);
+        assert!(matches!(
+            verify(&sign_with_test_key(&future)),
+            Err(GidTokenError::IssuedInFuture)
+        ));
+    }
+
+    #[test]
+    fn rejects_unverified_emails() {
+        let mut claims = claims();
+        claims["email_verified"] = json!(false);
+        assert!(matches!(
+            verify(&sign_with_test_key(&claims)),
+            Err(GidTokenError::EmailNotVerified)
+        ));
+    }
+
+    #[test]
+    fn refetches_the_keys_once_they_rotate() {
+        System::new().block_on(async {
+            let google = FakeJwks::start(vec![jwk()]);
+            let cache = MemoryCache::new(16);
+
+            let old_token = sign_with_test_key(&claims());
+            assert!(verify_gid_token(&old_token, &google.config, &cache)
+                .await
+                .is_ok());
+            assert_eq!(google.fetches(), 1);
+
+            *google.jwks.lock().unwrap() = json!({ "keys": [jwk(), other_jwk()] });
+            let new_token = sign(
+                &claims(),
+                OTHER_KID,
+                include_bytes!("fixtures/rsa_other_private.pem"),
+            );
+            assert!(verify_gid_token(&new_token, &google.config, &cache)
+                .await
+                .is_ok());
+            assert_eq!(google.fetches(), 2);
+
+            // Both keys are cached now.
+            assert!(verify_gid_token(&old_token, &google.config, &cache)
+                .await
+                .is_ok());
+            assert_eq!(google.fetches(), 2);
+
+            google.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn unknown_kids_force_at_most_one_refetch_per_interval() {
+        System::new().block_on(async {
+            let google = FakeJwks::start(vec![jwk()]);
+            let cache = MemoryCache::new(16);
+
+            for attempt in 0..3 {
+                let gid_token = sign_with_test_key(&claims());
+                let forged = sign(
+                    &claims(),
+                    &format!("made-up-{attempt}"),
+                    include_bytes!("fixtures/rsa_private.pem"),
+                );
+                assert!(verify_gid_token(&gid_token, &google.config, &cache)
+                    .await
+                    .is_ok());
+                assert!(matches!(
+                    verify_gid_token(&forged, &google.config, &cache).await,
+                    Err(GidTokenError::UnknownKey)
+                ));
+            }
+            // The first fetch, plus the single forced one.
+            assert_eq!(google.fetches(), 2);
+
+            google.server.stop(false).await;
+        });
+    }
+}
@@ -0,0 +1,534 @@
+// Offline import of an opendatasoft "us-colleges-and-universities" export into the catalog.
+//
+// Usage: api import-catalog <file> [csv|json|geojson]
+// The format is taken from the file extension when it is not given.
+use std::{collections::HashMap, env, fs, io, path::Path};
+
+use sea_orm::Database;
+use serde_json::{Map, Value};
+
+use crate::{
+    catalog::{self, UpstreamCollege},
+    structures::{CollegeCoord, CollegeStruct},
+};
+
+/// The only kind of institution the catalog holds; other rows in a full export are skipped.
+const CATALOG_NAICS_DESC: &str = "COLLEGES, UNIVERSITIES, AND PROFESSIONAL SCHOOLS";
+
+enum ExportFormat {
+    Csv,
+    Json,
+    GeoJson,
+}
+
+impl ExportFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "geojson" => Some(Self::GeoJson),
+            _ => None,
+        }
+    }
+}
+
+/// One row of the export, with field names lowercased as in the JSON API.
+struct ExportRow {
+    // 1-based line in CSV files, 1-based position of the record otherwise.
+    number: usize,
+    // Why the row could not be read at all, e.g. invalid UTF-8 or a record that isn't an object.
+    fields: Result<Map<String, Value>, String>,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Exports use `;` as the delimiter by default, but re-saved files often use `,`. Whichever
+/// the header line has more of wins.
+fn detect_delimiter(contents: &[u8]) -> u8 {
+    let header_line = contents.split(|b| *b == b'\n').next().unwrap_or_default();
+    let count = |delimiter: u8| header_line.iter().filter(|b| **b == delimiter).count();
+    if count(b';') >= count(b',') {
+        b';'
+    } else {
+        b','
+    }
+}
+
+fn read_csv_rows(contents: &[u8]) -> io::Result<Vec<ExportRow>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(detect_delimiter(contents))
+        .flexible(true)
+        .from_reader(contents);
+
+    // Column labels such as "Geo Point" or "IPEDSID" become "geo_point" and "ipedsid".
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| invalid_data(format!("unable to read CSV header: {e}")))?
+        .iter()
+        .map(|header| header.trim().to_lowercase().replace(' ', "_"))
+        .collect();
+
+    let mut rows = Vec::new();
+    for (idx, record) in reader.records().enumerate() {
+        // A row that can't be read is reported like any other invalid row.
+        let row = match record {
+            Ok(record) => ExportRow {
+                number: record.position().map_or(idx + 2, |pos| pos.line() as usize),
+                fields: Ok(headers
+                    .iter()
+                    .zip(record.iter())
+                    .filter(|(_, value)| !value.trim().is_empty())
+                    .map(|(header, value)| {
+                        (header.clone(), Value::String(value.trim().to_string()))
+                    })
+                    .collect()),
+            },
+            Err(e) => ExportRow {
+                number: e.position().map_or(idx + 2, |pos| pos.line() as usize),
+                fields: Err(format!("unreadable CSV row: {e}")),
+            },
+        };
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn read_json_rows(contents: &str) -> io::Result<Vec<ExportRow>> {
+    // Accepts the bare array from the export endpoint or a saved `records` API response.
+    let records = match serde_json::from_str::<Value>(contents)
+        .map_err(|e| invalid_data(format!("unable to parse JSON: {e}")))?
+    {
+        Value::Array(records) => records,
+        Value::Object(mut obj) => match obj.remove("results") {
+            Some(Value::Array(records)) => records,
+            _ => return Err(invalid_data("expected an array of records".to_string())),
+        },
+        _ => return Err(invalid_data("expected an array of records".to_string())),
+    };
+
+    Ok(records
+        .into_iter()
+        .enumerate()
+        .map(|(idx, record)| ExportRow {
+            number: idx + 1,
+            fields: match record {
+                Value::Object(fields) => Ok(fields),
+                _ => Err("record is not an object".to_string()),
+            },
+        })
+        .collect())
+}
+
+fn read_geojson_rows(contents: &str) -> io::Result<Vec<ExportRow>> {
+    let collection = serde_json::from_str::<Value>(contents)
+        .map_err(|e| invalid_data(format!("unable to parse GeoJSON: {e}")))?;
+    let features = match collection.get("features") {
+        Some(Value::Array(features)) => features,
+        _ => return Err(invalid_data("expected a FeatureCollection".to_string())),
+    };
+
+    Ok(features
+        .iter()
+        .enumerate()
+        .map(|(idx, feature)| {
+            let mut fields = match feature.get("properties") {
+                Some(Value::Object(properties)) => properties.clone(),
+                _ => Map::new(),
+            };
+            // The point geometry is authoritative for the location.
+            if let Some(Value::Array(coords)) = feature
+                .get("geometry")
+                .filter(|geometry| geometry.get("type") == Some(&Value::from("Point")))
+                .and_then(|geometry| geometry.get("coordinates"))
+            {
+                if let [lon, lat, ..] = coords.as_slice() {
+                    fields.insert(
+                        "geo_point_2d".to_string(),
+                        serde_json::json!({ "lon": lon, "lat": lat }),
+                    );
+                }
+            }
+            ExportRow {
+                number: idx + 1,
+                fields: Ok(fields),
+            }
+        })
+        .collect())
+}
+
+fn field_str(fields: &Map<String, Value>, name: &str) -> Option<String> {
+    match fields.get(name)? {
+        Value::String(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn field_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(value) => value.as_f64(),
+        Value::String(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Finds the location in `geo_point_2d` (an object, or "lat, lon" text in CSV), falling back
+/// to the `latitude`/`longitude` columns.
+fn row_coords(fields: &Map<String, Value>) -> Option<CollegeCoord> {
+    let geo_point = fields
+        .get("geo_point_2d")
+        .or_else(|| fields.get("geo_point"));
+
+    let (lat, lon) = match geo_point {
+        Some(Value::Object(point)) => {
+            (field_f64(point.get("lat")?)?, field_f64(point.get("lon")?)?)
+        }
+        Some(Value::String(point)) => {
+            let (lat, lon) = point.split_once(',')?;
+            (lat.trim().parse().ok()?, lon.trim().parse().ok()?)
+        }
+        _ => (
+            field_f64(fields.get("latitude")?)?,
+            field_f64(fields.get("longitude")?)?,
+        ),
+    };
+
+    Some(CollegeCoord { lon, lat })
+}
+
+/// Returns `None` for rows that are not part of the catalog.
+fn validate_row(fields: &Map<String, Value>) -> Result<Option<UpstreamCollege>, String> {
+    let naics_desc = field_str(fields, "naics_desc").unwrap_or_default();
+    if naics_desc != CATALOG_NAICS_DESC {
+        return Ok(None);
+    }
+
+    let ipedsid = field_str(fields, "ipedsid").ok_or("missing ipedsid")?;
+    let name = field_str(fields, "name").ok_or_else(|| format!("{ipedsid}: missing name"))?;
+    let geo_point_2d =
+        row_coords(fields).ok_or_else(|| format!("{ipedsid}: missing or unreadable location"))?;
+    if !(-90.0..=90.0).contains(&geo_point_2d.lat) || !(-180.0..=180.0).contains(&geo_point_2d.lon)
+    {
+        return Err(format!("{ipedsid}: location out of range"));
+    }
+
+    Ok(Some(UpstreamCollege {
+        college: CollegeStruct {
+            ipedsid,
+            name,
+            address: field_str(fields, "address").unwrap_or_default(),
+            city: field_str(fields, "city").unwrap_or_default(),
+            state: field_str(fields, "state").unwrap_or_default(),
+            zip: field_str(fields, "zip").unwrap_or_default(),
+            geo_point_2d,
+            naics_desc,
+            acceptance_rate: None,
+            sat_avg: None,
+            act_avg: None,
+        },
+        sourcedate: field_str(fields, "sourcedate"),
+        val_date: field_str(fields, "val_date"),
+    }))
+}
+
+/// The rows of an export sorted into what gets imported and what doesn't.
+#[derive(Default)]
+struct ValidatedRows {
+    records: Vec<UpstreamCollege>,
+    // Rows that are not part of the catalog.
+    skipped: usize,
+    errors: Vec<String>,
+}
+
+/// Validates every row, reporting each invalid one by number. A college listed more than once
+/// is imported from its first row; later rows for it are reported as invalid.
+fn validate_rows(rows: Vec<ExportRow>) -> ValidatedRows {
+    let mut validated = ValidatedRows::default();
+    let mut first_rows: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        match row.fields.and_then(|fields| validate_row(&fields)) {
+            Ok(Some(record)) => match first_rows.get(&record.college.ipedsid) {
+                Some(first_row) => validated.errors.push(format!(
+                    "row {}: {}: duplicate of row {first_row}",
+                    row.number, record.college.ipedsid
+                )),
+                None => {
+                    first_rows.insert(record.college.ipedsid.clone(), row.number);
+                    validated.records.push(record);
+                }
+            },
+            Ok(None) => validated.skipped += 1,
+            Err(e) => validated.errors.push(format!("row {}: {e}", row.number)),
+        }
+    }
+    validated
+}
+
+pub async fn run(args: Vec<String>) -> io::Result<()> {
+    let path = match args.first() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: api import-catalog <file> [csv|json|geojson]");
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "missing export file",
+            ));
+        }
+    };
+
+    let format_name = match args.get(1) {
+        Some(format_name) => format_name.clone(),
+        None => Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_string(),
+    };
+    let format = ExportFormat::parse(&format_name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown export format \"{format_name}\", expected csv, json or geojson"),
+        )
+    })?;
+
+    // Read as bytes so a CSV row with bad encoding only fails that row.
+    let contents = fs::read(path)?;
+    let text =
+        || std::str::from_utf8(&contents).map_err(|e| invalid_data(format!("invalid UTF-8: {e}")));
+    let rows = match format {
+        ExportFormat::Csv => read_csv_rows(&contents)?,
+        ExportFormat::Json => read_json_rows(text()?)?,
+        ExportFormat::GeoJson => read_geojson_rows(text()?)?,
+    };
+    let ValidatedRows {
+        records,
+        skipped,
+        errors,
+    } = validate_rows(rows);
+
+    let database_url = env::var("DATABASE_URL").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "DATABASE_URL is not set in the environment or a .env file",
+        )
+    })?;
+    let db = Database::connect(database_url)
+        .await
+        .expect("Unable to connect to Postgres database");
+
+    let stats = catalog::upsert_colleges(&db, records)
+        .await
+        .map_err(io::Error::other)?;
+
+    for error in &errors {
+        eprintln!("invalid {error}");
+    }
+    println!(
+        "inserted: {}, updated: {}, skipped: {}, invalid: {}",
+        stats.inserted,
+        stats.updated,
+        skipped,
+        errors.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const NAICS: &str = CATALOG_NAICS_DESC;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => panic!("not an object"),
+        }
+    }
+
+    fn college_fields() -> Value {
+        json!({
+            "ipedsid": "166027",
+            "name": "Harvard University",
+            "city": "Cambridge",
+            "state": "MA",
+            "naics_desc": NAICS,
+            "geo_point_2d": {"lat": 42.377, "lon": -71.1167},
+        })
+    }
+
+    fn ipedsids(validated: &ValidatedRows) -> Vec<&str> {
+        validated
+            .records
+            .iter()
+            .map(|record| record.college.ipedsid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn delimiter_is_whichever_the_header_has_more_of() {
+        assert_eq!(detect_delimiter(b"IPEDSID;NAME;Geo Point\n1,2;x;y"), b';');
+        assert_eq!(detect_delimiter(b"IPEDSID,NAME,Geo Point\n1;2,x,y"), b',');
+        assert_eq!(detect_delimiter(b""), b';');
+    }
+
+    #[test]
+    fn csv_rows_are_read_with_normalized_headers() {
+        let contents = format!(
+            "IPEDSID;NAME;STATE;NAICS_DESC;Geo Point\n\
+             166027;Harvard University;MA;{NAICS};42.377, -71.1167\n\
+             ;;;;\n"
+        );
+        let rows = read_csv_rows(contents.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].number, 2);
+        let first = rows[0].fields.as_ref().unwrap();
+        assert_eq!(first["ipedsid"], "166027");
+        assert_eq!(first["geo_point"], "42.377, -71.1167");
+        // Blank cells are left out.
+        assert!(rows[1].fields.as_ref().unwrap().is_empty());
+
+        let record = validate_row(first).unwrap().unwrap();
+        assert_eq!(record.college.geo_point_2d.lat, 42.377);
+        assert_eq!(record.college.geo_point_2d.lon, -71.1167);
+    }
+
+    #[test]
+    fn comma_separated_csv_is_read_too() {
+        let contents = format!(
+            "IPEDSID,NAME,NAICS_DESC,LATITUDE,LONGITUDE\n\
+             166027,Harvard University,\"{NAICS}\",42.377,-71.1167\n"
+        );
+        let rows = read_csv_rows(contents.as_bytes()).unwrap();
+        let validated = validate_rows(rows);
+        assert_eq!(ipedsids(&validated), vec!["166027"]);
+        assert!(validated.errors.is_empty());
+    }
+
+    #[test]
+    fn unreadable_csv_rows_are_reported_and_the_rest_imported() {
+        let mut contents = format!(
+            "IPEDSID;NAME;NAICS_DESC;Geo Point\n\
+             166027;Harvard University;{NAICS};42.377, -71.1167\n"
+        )
+        .into_bytes();
+        contents.extend_from_slice(b"999999;Bad \xff Name;x;0, 0\n");
+        contents.extend_from_slice(
+            format!("130794;Yale University;{NAICS};41.3111, -72.9267\n").as_bytes(),
+        );
+
+        let validated = validate_rows(read_csv_rows(&contents).unwrap());
+
+        assert_eq!(ipedsids(&validated), vec!["166027", "130794"]);
+        assert_eq!(validated.errors.len(), 1);
+        assert!(
+            validated.errors[0].starts_with("row 3: unreadable CSV row"),
+            "{}",
+            validated.errors[0]
+        );
+    }
+
+    #[test]
+    fn json_is_read_as_an_array_or_a_records_response() {
+        let array = json!([college_fields(), "not a record"]).to_string();
+        let rows = read_json_rows(&array).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].number, 2);
+        assert_eq!(
+            rows[1].fields.as_ref().unwrap_err(),
+            "record is not an object"
+        );
+
+        let response = json!({"total_count": 1, "results": [college_fields()]}).to_string();
+        let validated = validate_rows(read_json_rows(&response).unwrap());
+        assert_eq!(ipedsids(&validated), vec!["166027"]);
+
+        assert!(read_json_rows("{\"results\": 1}").is_err());
+        assert!(read_json_rows("[").is_err());
+    }
+
+    #[test]
+    fn geojson_takes_the_location_from_the_geometry() {
+        let mut properties = college_fields();
+        properties["geo_point_2d"] = json!({"lat": 0.0, "lon": 0.0});
+        let collection = json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [-71.1167, 42.377]},
+                "properties": properties,
+            }],
+        })
+        .to_string();
+
+        let validated = validate_rows(read_geojson_rows(&collection).unwrap());
+
+        let location = &validated.records[0].college.geo_point_2d;
+        assert_eq!((location.lat, location.lon), (42.377, -71.1167));
+        assert!(read_geojson_rows("{\"type\": \"Feature\"}").is_err());
+    }
+
+    #[test]
+    fn rows_outside_the_catalog_are_skipped() {
+        let mut row = college_fields();
+        row["naics_desc"] = json!("JUNIOR COLLEGES");
+        assert!(validate_row(&fields(row)).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_rows_say_what_is_wrong() {
+        let without = |field: &str| {
+            let mut row = fields(college_fields());
+            row.remove(field);
+            validate_row(&row).err()
+        };
+        assert_eq!(without("ipedsid").as_deref(), Some("missing ipedsid"));
+        assert_eq!(without("name").as_deref(), Some("166027: missing name"));
+        assert_eq!(
+            without("geo_point_2d").as_deref(),
+            Some("166027: missing or unreadable location")
+        );
+
+        let mut row = college_fields();
+        row["geo_point_2d"] = json!({"lat": 91.0, "lon": 0.0});
+        assert_eq!(
+            validate_row(&fields(row)).err().as_deref(),
+            Some("166027: location out of range")
+        );
+        let mut row = college_fields();
+        row["geo_point_2d"] = json!("north, west");
+        assert!(validate_row(&fields(row)).is_err());
+    }
+
+    #[test]
+    fn numeric_fields_are_read_as_text() {
+        let mut row = college_fields();
+        row["ipedsid"] = json!(166027);
+        row["zip"] = json!(2138);
+        let record = validate_row(&fields(row)).unwrap().unwrap();
+        assert_eq!(record.college.ipedsid, "166027");
+        assert_eq!(record.college.zip, "2138");
+    }
+
+    #[test]
+    fn duplicate_colleges_are_reported_and_only_imported_once() {
+        let mut renamed = college_fields();
+        renamed["name"] = json!("Harvard College");
+        let contents = json!([college_fields(), renamed]).to_string();
+
+        let validated = validate_rows(read_json_rows(&contents).unwrap());
+
+        assert_eq!(ipedsids(&validated), vec!["166027"]);
+        assert_eq!(validated.records[0].college.name, "Harvard University");
+        assert_eq!(
+            validated.errors,
+            vec!["row 2: 166027: duplicate of row 1".to_string()]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+// Prefix lookup of college names for autocomplete.
+use crate::structures::CollegeStruct;
+
+use super::search::tokenize;
+
+/// The most suggestions a lookup can return.
+pub const MAX_SUGGESTIONS: usize = 10;
+
+/// Words a name can be completed from besides its first, e.g. "michigan" for
+/// "University of Michigan". Leading stopwords are skipped.
+const SKIPPED_WORDS: [&str; 4] = ["of", "the", "and", "at"];
+
+#[derive(Default)]
+struct TrieNode {
+    children: Vec<(char, u32)>,
+    // The best colleges below this node, best first.
+    top: Vec<u32>,
+}
+
+/// A trie over college names in which every node keeps its best completions, so a lookup
+/// costs only the length of the prefix.
+pub struct AutocompleteIndex {
+    nodes: Vec<TrieNode>,
+}
+
+impl AutocompleteIndex {
+    pub fn build(colleges: &[CollegeStruct]) -> Self {
+        // Each name is reachable from the start of any of its words. Completions of the whole
+        // name rank above completions from a later word, then shorter names above longer ones.
+        let mut keys: Vec<(bool, usize, &str, String, u32)> = Vec::new();
+        for (doc, college) in colleges.iter().enumerate() {
+            let tokens = tokenize(&college.name);
+            for start in 0..tokens.len() {
+                if start > 0 && SKIPPED_WORDS.contains(&tokens[start].as_str()) {
+                    continue;
+                }
+                keys.push((
+                    start > 0,
+                    college.name.len(),
+                    college.name.as_str(),
+                    tokens[start..].join(" "),
+                    doc as u32,
+                ));
+            }
+        }
+        keys.sort_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+
+        let mut index = Self {
+            nodes: vec![TrieNode::default()],
+        };
+        // Keys are inserted best first, so each node's list fills up in rank order.
+        for (_, _, _, key, doc) in keys {
+            index.insert(&key, doc);
+        }
+        index
+    }
+
+    fn insert(&mut self, key: &str, doc: u32) {
+        let mut node = 0;
+        for c in key.chars() {
+            let next = match self.nodes[node]
+                .children
+                .iter()
+                .find(|(child_char, _)| *child_char == c)
+            {
+                Some((_, child)) => *child as usize,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.push((c, child as u32));
+                    child
+                }
+            };
+            node = next;
+
+            let top = &mut self.nodes[node].top;
+            if top.len() < MAX_SUGGESTIONS && !top.contains(&doc) {
+                top.push(doc);
+            }
+        }
+    }
+
+    /// The best colleges whose name, or a word in it onwards, starts with `prefix`, as
+    /// positions in the catalog list.
+    pub fn complete(&self, prefix: &str, limit: usize) -> Vec<usize> {
+        let key = tokenize(prefix).join(" ");
+        if key.is_empty() {
+            return Vec::new();
+        }
+
+        let mut node = 0;
+        for c in key.chars() {
+            match self.nodes[node]
+                .children
+                .iter()
+                .find(|(child_char, _)| *child_char == c)
+            {
+                Some((_, child)) => node = *child as usize,
+                None => return Vec::new(),
+            }
+        }
+
+        self.nodes[node]
+            .top
+            .iter()
+            .take(limit)
+            .map(|doc| *doc as usize)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::CollegeCoord;
+
+    fn index(names: &[&str]) -> AutocompleteIndex {
+        let colleges: Vec<CollegeStruct> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| CollegeStruct {
+                ipedsid: i.to_string(),
+                name: name.to_string(),
+                address: String::new(),
+                city: String::new(),
+                state: String::new(),
+                zip: String::new(),
+                geo_point_2d: CollegeCoord { lon: 0.0, lat: 0.0 },
+                naics_desc: String::new(),
+                acceptance_rate: None,
+                sat_avg: None,
+                act_avg: None,
+            })
+            .collect();
+        AutocompleteIndex::build(&colleges)
+    }
+
+    const NAMES: [&str; 6] = [
+        "University of Michigan-Flint",
+        "Michigan State University",
+        "University of Michigan-Ann Arbor",
+        "Michigan Technological University",
+        "Central Michigan University",
+        "The University of Texas at Austin",
+    ];
+
+    fn complete(prefix: &str) -> Vec<&'static str> {
+        index(&NAMES)
+            .complete(prefix, MAX_SUGGESTIONS)
+            .into_iter()
+            .map(|doc| NAMES[doc])
+            .collect()
+    }
+
+    #[test]
+    fn whole_name_matches_come_before_later_words() {
+        assert_eq!(
+            complete("mich"),
+            vec![
+                "Michigan State University",
+                "Michigan Technological University",
+                "Central Michigan University",
+                "University of Michigan-Flint",
+                "University of Michigan-Ann Arbor",
+            ]
+        );
+    }
+
+    #[test]
+    fn shorter_names_come_first() {
+        assert_eq!(
+            complete("University of M"),
+            vec![
+                "University of Michigan-Flint",
+                "University of Michigan-Ann Arbor",
+            ]
+        );
+        // Names of the same length are in alphabetical order.
+        let index = index(&["Gamma College", "Alpha College", "Beta College"]);
+        assert_eq!(index.complete("college", MAX_SUGGESTIONS), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn later_words_complete_but_not_from_stopwords() {
+        assert_eq!(complete("texas"), vec!["The University of Texas at Austin"]);
+        assert_eq!(
+            complete("austin"),
+            vec!["The University of Texas at Austin"]
+        );
+        assert!(complete("at austin").is_empty());
+        assert_eq!(
+            complete("the univ"),
+            vec!["The University of Texas at Austin"]
+        );
+    }
+
+    #[test]
+    fn prefixes_are_tokenized_like_names() {
+        assert_eq!(
+            complete("MICHIGAN-ann"),
+            vec!["University of Michigan-Ann Arbor"]
+        );
+        assert!(complete("  ").is_empty());
+        assert!(complete("harvard").is_empty());
+    }
+
+    #[test]
+    fn suggestions_are_capped() {
+        let names: Vec<String> = (1..=15).map(|i| format!("College {i:02}")).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let index = index(&names);
+
+        assert_eq!(
+            index.complete("college", 100),
+            (0..MAX_SUGGESTIONS).collect::<Vec<_>>()
+        );
+        assert_eq!(index.complete("college", 3), vec![0, 1, 2]);
+        assert!(index.complete("college", 0).is_empty());
+    }
+}
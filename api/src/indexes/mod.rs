@@ -0,0 +1,51 @@
+// Indexes derived from the college catalog, rebuilt whenever the catalog changes.
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use crate::structures::CollegeStruct;
+
+pub mod autocomplete;
+pub mod search;
+pub mod spatial;
+
+use autocomplete::AutocompleteIndex;
+use search::SearchIndex;
+use spatial::SpatialIndex;
+
+/// Every index over one version of the catalog.
+/// Results refer to colleges by their position in the list the indexes were built from.
+pub struct CatalogIndexes {
+    pub fingerprint: u64,
+    pub search: SearchIndex,
+    pub autocomplete: AutocompleteIndex,
+    pub spatial: SpatialIndex,
+}
+
+impl CatalogIndexes {
+    pub fn build(colleges: &[CollegeStruct]) -> Self {
+        Self {
+            fingerprint: catalog_fingerprint(colleges),
+            search: SearchIndex::build(colleges),
+            autocomplete: AutocompleteIndex::build(colleges),
+            spatial: SpatialIndex::build(colleges),
+        }
+    }
+}
+
+/// Identifies a version of the catalog, so indexes are only rebuilt after an import changes it.
+/// It depends on the order of the list, since index results are positions in it.
+pub fn catalog_fingerprint(colleges: &[CollegeStruct]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    colleges.len().hash(&mut hasher);
+    for college in colleges {
+        college.ipedsid.hash(&mut hasher);
+        college.name.hash(&mut hasher);
+        college.city.hash(&mut hasher);
+        college.state.hash(&mut hasher);
+        college.geo_point_2d.lat.to_bits().hash(&mut hasher);
+        college.geo_point_2d.lon.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,597 @@
+// Typo-tolerant search over college names, cities and states.
+use std::collections::{HashMap, HashSet};
+
+use crate::structures::CollegeStruct;
+
+/// Words that carry no meaning in a college name and are ignored when matching.
+const STOPWORDS: [&str; 7] = ["of", "the", "and", "at", "in", "for", "a"];
+
+/// Common names for colleges that share no words or initials with their official name.
+const QUERY_ALIASES: [(&str, &str); 15] = [
+    ("upenn", "university of pennsylvania"),
+    ("penn", "university of pennsylvania"),
+    ("umich", "university of michigan ann arbor"),
+    ("university of michigan", "university of michigan ann arbor"),
+    ("caltech", "california institute of technology"),
+    ("gatech", "georgia institute of technology"),
+    ("georgia tech", "georgia institute of technology"),
+    ("uiuc", "university of illinois urbana champaign"),
+    ("ut austin", "university of texas at austin"),
+    ("berkeley", "university of california berkeley"),
+    ("ucb", "university of california berkeley"),
+    ("wustl", "washington university in st louis"),
+    ("uva", "university of virginia"),
+    ("unc", "university of north carolina at chapel hill"),
+    ("uw", "university of washington seattle"),
+];
+
+/// Abbreviations used for single words, each expanding to every word it may stand for.
+const TOKEN_ALIASES: [(&str, &[&str]); 9] = [
+    ("u", &["university"]),
+    ("univ", &["university"]),
+    ("uni", &["university"]),
+    ("coll", &["college"]),
+    ("inst", &["institute"]),
+    ("tech", &["technology", "technical", "tech"]),
+    ("st", &["saint", "state", "st"]),
+    ("mt", &["mount", "mt"]),
+    ("cc", &["community", "cc"]),
+];
+
+#[derive(Clone, Copy)]
+enum Field {
+    Name,
+    City,
+    State,
+}
+
+impl Field {
+    fn weight(self) -> f64 {
+        match self {
+            Field::Name => 1.0,
+            Field::City | Field::State => 0.7,
+        }
+    }
+}
+
+struct SearchDoc {
+    normalized_name: String,
+    name_token_count: usize,
+}
+
+pub struct SearchIndex {
+    vocab: Vec<String>,
+    token_ids: HashMap<String, usize>,
+    // Positions in `vocab`, in alphabetical order of the words, for finding words by prefix.
+    sorted_vocab: Vec<usize>,
+    // For each bigram, the words in `vocab` containing it, for finding words with typos.
+    bigram_vocab: HashMap<(char, char), Vec<usize>>,
+    // For each word in `vocab`, the colleges and fields it appears in.
+    postings: Vec<Vec<(usize, Field)>>,
+    docs: Vec<SearchDoc>,
+    acronyms: HashMap<String, Vec<usize>>,
+}
+
+/// A college matching a query, by position in the catalog list.
+pub struct SearchHit {
+    pub college: usize,
+    pub score: f64,
+}
+
+/// Lowercases and splits on anything that is not a letter or digit.
+/// Apostrophes are dropped so "Mary's" stays one word.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .replace(['\'', '\u{2019}'], "")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_stopword(token: &str) -> bool {
+    STOPWORDS.contains(&token)
+}
+
+/// Optimal string alignment distance: edits, counting a swap of neighbours as one.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev_prev = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        cur[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(prev_prev[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev_prev, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// How many typos a query word of this length may have. Longer words are allowed more.
+fn max_edits(query_len: usize) -> usize {
+    match query_len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Query words this short only match indexed words that start with them exactly.
+const MIN_PREFIX_LEN: usize = 3;
+
+/// How well a query word matches an indexed word, from 0 (not at all) to 1 (identical).
+fn token_similarity(query: &str, token: &str) -> f64 {
+    if query == token {
+        return 1.0;
+    }
+
+    let query_len = query.chars().count();
+    if query_len >= MIN_PREFIX_LEN && token.starts_with(query) {
+        return 0.85;
+    }
+
+    let max_edits = max_edits(query_len);
+    if max_edits == 0 || query_len.abs_diff(token.chars().count()) > max_edits {
+        return 0.0;
+    }
+    match edit_distance(query, token) {
+        distance if distance <= max_edits => 0.9 - 0.15 * distance as f64,
+        _ => 0.0,
+    }
+}
+
+/// The distinct pairs of neighbouring characters in a word, with its start and end marked so
+/// the first and last letters count too.
+fn bigrams(token: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = std::iter::once('^')
+        .chain(token.chars())
+        .chain(std::iter::once('$'))
+        .collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+fn acronym(tokens: &[String]) -> String {
+    tokens
+        .iter()
+        .filter(|token| !is_stopword(token))
+        .filter_map(|token| token.chars().next())
+        .collect()
+}
+
+/// One way of reading a query.
+struct QueryVariant {
+    // The query with abbreviations expanded, for matching whole phrases.
+    phrase: String,
+    // The words to match, each as a list of alternatives.
+    tokens: Vec<Vec<String>>,
+}
+
+fn expand_token(token: &str) -> Vec<String> {
+    match TOKEN_ALIASES.iter().find(|(alias, _)| *alias == token) {
+        Some((_, expansions)) => expansions.iter().map(|word| word.to_string()).collect(),
+        None => vec![token.to_string()],
+    }
+}
+
+/// The words joined back up with abbreviations spelled out. Only abbreviations with a single
+/// meaning are.
+fn spell_out(tokens: &[String]) -> String {
+    tokens
+        .iter()
+        .map(|token| match expand_token(token).as_slice() {
+            [expansion] => expansion.clone(),
+            _ => token.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Reads the query as written and, if it is a known alias as written or spelled out, as the
+/// name it stands for.
+fn query_variants(normalized: &str) -> Vec<QueryVariant> {
+    let spelled_out = spell_out(&tokenize(normalized));
+    let mut texts = vec![normalized.to_string()];
+    if let Some((_, expansion)) = QUERY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == normalized || *alias == spelled_out)
+    {
+        texts.push(expansion.to_string());
+    }
+
+    texts
+        .into_iter()
+        .map(|text| {
+            let tokens = tokenize(&text);
+            let phrase = spell_out(&tokens);
+
+            let meaningful: Vec<&String> =
+                tokens.iter().filter(|token| !is_stopword(token)).collect();
+            // A query made only of stopwords still has to match something.
+            let tokens = if meaningful.is_empty() {
+                tokens.iter().map(|token| expand_token(token)).collect()
+            } else {
+                meaningful
+                    .into_iter()
+                    .map(|token| expand_token(token))
+                    .collect()
+            };
+
+            QueryVariant { phrase, tokens }
+        })
+        .collect()
+}
+
+impl SearchIndex {
+    pub fn build(colleges: &[CollegeStruct]) -> Self {
+        let mut token_ids: HashMap<String, usize> = HashMap::new();
+        let mut vocab = Vec::new();
+        let mut postings: Vec<Vec<(usize, Field)>> = Vec::new();
+        let mut docs = Vec::with_capacity(colleges.len());
+        let mut acronyms: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (doc, college) in colleges.iter().enumerate() {
+            let name_tokens = tokenize(&college.name);
+            let fields = [
+                (Field::Name, name_tokens.clone()),
+                (Field::City, tokenize(&college.city)),
+                (Field::State, tokenize(&college.state)),
+            ];
+
+            for (field, tokens) in fields {
+                for token in tokens {
+                    let id = *token_ids.entry(token.clone()).or_insert_with(|| {
+                        vocab.push(token);
+                        postings.push(Vec::new());
+                        vocab.len() - 1
+                    });
+                    postings[id].push((doc, field));
+                }
+            }
+
+            let college_acronym = acronym(&name_tokens);
+            if college_acronym.len() >= 2 {
+                acronyms.entry(college_acronym).or_default().push(doc);
+            }
+            docs.push(SearchDoc {
+                normalized_name: name_tokens.join(" "),
+                name_token_count: name_tokens
+                    .iter()
+                    .filter(|token| !is_stopword(token))
+                    .count(),
+            });
+        }
+
+        let mut sorted_vocab: Vec<usize> = (0..vocab.len()).collect();
+        sorted_vocab.sort_unstable_by(|a, b| vocab[*a].cmp(&vocab[*b]));
+        let mut bigram_vocab: HashMap<(char, char), Vec<usize>> = HashMap::new();
+        for (id, token) in vocab.iter().enumerate() {
+            for bigram in bigrams(token) {
+                bigram_vocab.entry(bigram).or_default().push(id);
+            }
+        }
+
+        Self {
+            vocab,
+            token_ids,
+            sorted_vocab,
+            bigram_vocab,
+            postings,
+            docs,
+            acronyms,
+        }
+    }
+
+    /// The indexed words `query` matches, by position in `vocab`, with how well they match.
+    /// Only words that could match are compared: the word itself, words it is a prefix of, and
+    /// words sharing enough of its bigrams to be within its allowed typos.
+    fn matching_tokens(&self, query: &str) -> Vec<(usize, f64)> {
+        let mut candidates: HashSet<usize> = HashSet::new();
+        if let Some(id) = self.token_ids.get(query) {
+            candidates.insert(*id);
+        }
+
+        let query_len = query.chars().count();
+        if query_len >= MIN_PREFIX_LEN {
+            let start = self
+                .sorted_vocab
+                .partition_point(|id| self.vocab[*id].as_str() < query);
+            candidates.extend(
+                self.sorted_vocab[start..]
+                    .iter()
+                    .take_while(|id| self.vocab[**id].starts_with(query)),
+            );
+        }
+
+        let max_edits = max_edits(query_len);
+        if max_edits > 0 {
+            // An edit changes at most three of a word's bigrams, e.g. swapping "b" and "c" in
+            // "abcd" loses "ab", "bc" and "cd", so a close enough word keeps the rest.
+            let query_bigrams = bigrams(query);
+            let min_shared = query_bigrams.len().saturating_sub(3 * max_edits).max(1);
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            for bigram in &query_bigrams {
+                for id in self.bigram_vocab.get(bigram).into_iter().flatten() {
+                    *shared.entry(*id).or_insert(0) += 1;
+                }
+            }
+            candidates.extend(
+                shared
+                    .into_iter()
+                    .filter(|(_, count)| *count >= min_shared)
+                    .map(|(id, _)| id),
+            );
+        }
+
+        candidates
+            .into_iter()
+            .map(|id| (id, token_similarity(query, &self.vocab[id])))
+            .filter(|(_, similarity)| *similarity > 0.0)
+            .collect()
+    }
+
+    /// Scores every college against one reading of the query.
+    /// A college must match every word of it to be included.
+    fn score_variant(&self, tokens: &[Vec<String>]) -> HashMap<usize, f64> {
+        let mut totals: HashMap<usize, f64> = HashMap::new();
+
+        for (idx, alternatives) in tokens.iter().enumerate() {
+            let mut best: HashMap<usize, f64> = HashMap::new();
+            let matches = alternatives
+                .iter()
+                .flat_map(|alternative| self.matching_tokens(alternative));
+            for (vocab_id, similarity) in matches {
+                for (doc, field) in &self.postings[vocab_id] {
+                    let score = similarity * field.weight();
+                    let entry = best.entry(*doc).or_insert(0.0);
+                    *entry = entry.max(score);
+                }
+            }
+
+            if idx == 0 {
+                totals = best;
+            } else {
+                totals.retain(|doc, total| match best.get(doc) {
+                    Some(score) => {
+                        *total += score;
+                        true
+                    }
+                    None => false,
+                });
+            }
+            if totals.is_empty() {
+                break;
+            }
+        }
+
+        let token_count = tokens.len().max(1) as f64;
+        for (doc, total) in totals.iter_mut() {
+            *total /= token_count;
+            // Prefer the college whose name is nothing but the query, e.g. "University of
+            // Michigan-Ann Arbor" over "University of Michigan-Flint" for "michigan ann arbor".
+            let extra_words = self.docs[*doc]
+                .name_token_count
+                .saturating_sub(tokens.len());
+            *total -= 0.02 * extra_words.min(10) as f64;
+        }
+        totals
+    }
+
+    /// Ranks the colleges matching `query`, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let normalized = tokenize(query).join(" ");
+        if normalized.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for variant in query_variants(&normalized) {
+            let padded_phrase = format!(" {} ", variant.phrase);
+            for (doc, mut score) in self.score_variant(&variant.tokens) {
+                // Words in the order they were typed count for more than scattered matches.
+                let name = &self.docs[doc].normalized_name;
+                if *name == variant.phrase {
+                    score += 1.0;
+                } else if format!(" {name} ").contains(&padded_phrase) {
+                    score += 0.25;
+                }
+                let entry = scores.entry(doc).or_insert(f64::MIN);
+                *entry = entry.max(score);
+            }
+        }
+
+        // Initialisms like "MIT" or "NYU".
+        let compact = normalized.replace(' ', "");
+        if let Some(docs) = self.acronyms.get(&compact) {
+            for doc in docs {
+                let entry = scores.entry(*doc).or_insert(0.0);
+                *entry = entry.max(0.0) + 1.5;
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|(college, score)| SearchHit { college, score })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.college.cmp(&b.college))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::CollegeCoord;
+
+    const CATALOG: [(&str, &str, &str); 8] = [
+        ("University of Pennsylvania", "Philadelphia", "PA"),
+        ("University of Michigan-Flint", "Flint", "MI"),
+        ("University of Michigan-Ann Arbor", "Ann Arbor", "MI"),
+        ("Massachusetts Institute of Technology", "Cambridge", "MA"),
+        ("Michigan State University", "East Lansing", "MI"),
+        (
+            "Pennsylvania State University-Main Campus",
+            "University Park",
+            "PA",
+        ),
+        ("Harvard University", "Cambridge", "MA"),
+        ("Middlebury College", "Middlebury", "VT"),
+    ];
+
+    fn index() -> SearchIndex {
+        let colleges: Vec<CollegeStruct> = CATALOG
+            .iter()
+            .enumerate()
+            .map(|(i, (name, city, state))| CollegeStruct {
+                ipedsid: i.to_string(),
+                name: name.to_string(),
+                address: String::new(),
+                city: city.to_string(),
+                state: state.to_string(),
+                zip: String::new(),
+                geo_point_2d: CollegeCoord { lon: 0.0, lat: 0.0 },
+                naics_desc: String::new(),
+                acceptance_rate: None,
+                sat_avg: None,
+                act_avg: None,
+            })
+            .collect();
+        SearchIndex::build(&colleges)
+    }
+
+    fn top(index: &SearchIndex, query: &str) -> Option<&'static str> {
+        index
+            .search(query, 10)
+            .first()
+            .map(|hit| CATALOG[hit.college].0)
+    }
+
+    #[test]
+    fn common_names_find_the_college_they_mean() {
+        let index = index();
+        assert_eq!(top(&index, "UPenn"), Some("University of Pennsylvania"));
+        assert_eq!(
+            top(&index, "Univ of Michigan"),
+            Some("University of Michigan-Ann Arbor")
+        );
+        assert_eq!(
+            top(&index, "MIT"),
+            Some("Massachusetts Institute of Technology")
+        );
+        assert_eq!(
+            top(&index, "michigan flint"),
+            Some("University of Michigan-Flint")
+        );
+    }
+
+    #[test]
+    fn initialisms_match_the_first_letters_of_the_name() {
+        let index = index();
+        assert_eq!(top(&index, "msu"), Some("Michigan State University"));
+        // Dotted initials work too, and stopwords like "of" are not part of the initialism.
+        assert_eq!(
+            top(&index, "m.i.t."),
+            Some("Massachusetts Institute of Technology")
+        );
+    }
+
+    #[test]
+    fn typos_are_tolerated_in_longer_words() {
+        let index = index();
+        assert_eq!(top(&index, "havard"), Some("Harvard University"));
+        assert_eq!(
+            top(&index, "Massachusets"),
+            Some("Massachusetts Institute of Technology")
+        );
+        // A swap of neighbouring letters counts as one typo.
+        assert_eq!(top(&index, "midlebury colelge"), Some("Middlebury College"));
+        // Short words must be typed exactly.
+        assert_eq!(top(&index, "mti"), None);
+    }
+
+    #[test]
+    fn words_match_by_prefix_and_in_any_field() {
+        let index = index();
+        assert_eq!(top(&index, "harv"), Some("Harvard University"));
+        let cambridge: Vec<&str> = index
+            .search("cambridge", 10)
+            .iter()
+            .map(|hit| CATALOG[hit.college].0)
+            .collect();
+        assert_eq!(cambridge.len(), 2);
+        assert!(cambridge.contains(&"Harvard University"));
+        assert!(cambridge.contains(&"Massachusetts Institute of Technology"));
+    }
+
+    #[test]
+    fn every_word_must_match() {
+        let index = index();
+        assert!(index.search("harvard michigan", 10).is_empty());
+        assert!(index.search("", 10).is_empty());
+        assert!(index.search("!!", 10).is_empty());
+    }
+
+    #[test]
+    fn results_are_capped_at_the_limit() {
+        let index = index();
+        let all = index.search("university", 10);
+        assert_eq!(all.len(), 6);
+        let top_three = index.search("university", 3);
+        assert_eq!(top_three.len(), 3);
+        let ids = |hits: &[SearchHit]| hits.iter().map(|hit| hit.college).collect::<Vec<_>>();
+        assert_eq!(ids(&top_three), ids(&all[..3]));
+        assert!(index.search("university", 0).is_empty());
+    }
+
+    #[test]
+    fn candidates_are_the_words_a_full_scan_would_match() {
+        let index = index();
+        for query in [
+            "university",
+            "univrsity",
+            "uinversity",
+            "pen",
+            "pensylvania",
+            "mich",
+            "michgian",
+            "cambrige",
+            "ma",
+            "mit",
+            "xyz",
+        ] {
+            let mut found: Vec<usize> = index
+                .matching_tokens(query)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            found.sort_unstable();
+            let scanned: Vec<usize> = (0..index.vocab.len())
+                .filter(|id| token_similarity(query, &index.vocab[*id]) > 0.0)
+                .collect();
+            assert_eq!(found, scanned, "{query}");
+        }
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_as_one() {
+        assert_eq!(edit_distance("harvard", "harvard"), 0);
+        assert_eq!(edit_distance("havard", "harvard"), 1);
+        assert_eq!(edit_distance("michgian", "michigan"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,365 @@
+// Grid index over college locations for radius and nearest-neighbour queries.
+use std::{collections::HashMap, f64::consts::PI};
+
+use crate::{
+    college_filter::BoundingBox,
+    structures::{CollegeCoord, CollegeStruct},
+};
+
+const R_EARTH: f64 = 3956.0;
+const ONE_DEG_TO_RAD: f64 = PI / 180.0;
+const MILES_PER_DEG_LAT: f64 = 69.0;
+/// Half of the earth's circumference; no two points are further apart than this.
+pub const MAX_DISTANCE_MILES: f64 = 12_430.0;
+
+/// Cells are half a degree square, about 35 by 35 miles in the continental US.
+const CELL_DEGREES: f64 = 0.5;
+const LAT_CELLS: i32 = (180.0 / CELL_DEGREES) as i32;
+const LON_CELLS: i32 = (360.0 / CELL_DEGREES) as i32;
+
+/// The radius the nearest-neighbour search starts from before widening.
+const NEAREST_START_RADIUS_MILES: f64 = 10.0;
+
+const KM_PER_MILE: f64 = 1.609_344;
+
+/// The unit distances are given in by clients. The index itself always works in miles.
+#[derive(Clone, Copy)]
+pub enum DistanceUnit {
+    Miles,
+    Kilometers,
+}
+
+impl DistanceUnit {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "mi" => Some(Self::Miles),
+            "km" => Some(Self::Kilometers),
+            _ => None,
+        }
+    }
+
+    pub fn to_miles(self, distance: f64) -> f64 {
+        match self {
+            Self::Miles => distance,
+            Self::Kilometers => distance / KM_PER_MILE,
+        }
+    }
+
+    pub fn convert_miles(self, miles: f64) -> f64 {
+        match self {
+            Self::Miles => miles,
+            Self::Kilometers => miles * KM_PER_MILE,
+        }
+    }
+}
+
+#[inline(always)]
+fn convert_to_radians(angle: f64) -> f64 {
+    angle * ONE_DEG_TO_RAD
+}
+
+/// Great-circle distance in miles.
+pub fn calculate_distance_between_coords(p1: &CollegeCoord, p2: &CollegeCoord) -> f64 {
+    let lat1 = convert_to_radians(p1.lat);
+    let long1 = convert_to_radians(p1.lon);
+    let lat2 = convert_to_radians(p2.lat);
+    let long2 = convert_to_radians(p2.lon);
+
+    // Haversine formula
+    let dlong = long2 - long1;
+    let dlat = lat2 - lat1;
+
+    let half_dlat = dlat / 2.0;
+    let half_dlong = dlong / 2.0;
+
+    let result = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlong.sin().powi(2);
+    let result = 2.0 * result.sqrt().asin();
+
+    result * R_EARTH
+}
+
+fn lat_row(lat: f64) -> i32 {
+    (((lat + 90.0) / CELL_DEGREES).floor() as i32).clamp(0, LAT_CELLS - 1)
+}
+
+fn lon_col(lon: f64) -> i32 {
+    (((lon + 180.0) / CELL_DEGREES).floor() as i32).rem_euclid(LON_CELLS)
+}
+
+pub struct SpatialIndex {
+    cells: HashMap<(i32, i32), Vec<u32>>,
+    points: Vec<CollegeCoord>,
+}
+
+impl SpatialIndex {
+    pub fn build(colleges: &[CollegeStruct]) -> Self {
+        let mut cells: HashMap<(i32, i32), Vec<u32>> = HashMap::new();
+        let points: Vec<CollegeCoord> = colleges
+            .iter()
+            .map(|college| college.geo_point_2d.clone())
+            .collect();
+
+        for (idx, point) in points.iter().enumerate() {
+            cells
+                .entry((lat_row(point.lat), lon_col(point.lon)))
+                .or_default()
+                .push(idx as u32);
+        }
+
+        Self { cells, points }
+    }
+
+    /// Every college within `radius` miles of `center`, with its distance, as positions in the
+    /// catalog list. Only the grid cells overlapping the radius are checked.
+    pub fn within(&self, center: &CollegeCoord, radius: f64) -> Vec<(usize, f64)> {
+        let lat_delta = radius / MILES_PER_DEG_LAT;
+        let min_lat = center.lat - lat_delta;
+        let max_lat = center.lat + lat_delta;
+
+        // A degree of longitude shrinks towards the poles, so the box widens there.
+        let widest_lat = min_lat.abs().max(max_lat.abs());
+        let lon_cols: Vec<i32> = if widest_lat >= 89.0 {
+            (0..LON_CELLS).collect()
+        } else {
+            let lon_delta = lat_delta / convert_to_radians(widest_lat).cos();
+            let first = ((center.lon - lon_delta + 180.0) / CELL_DEGREES).floor() as i32;
+            let last = ((center.lon + lon_delta + 180.0) / CELL_DEGREES).floor() as i32;
+            if last - first + 1 >= LON_CELLS {
+                (0..LON_CELLS).collect()
+            } else {
+                (first..=last)
+                    .map(|col| col.rem_euclid(LON_CELLS))
+                    .collect()
+            }
+        };
+
+        let mut hits = Vec::new();
+        for row in lat_row(min_lat)..=lat_row(max_lat) {
+            for col in &lon_cols {
+                let Some(cell) = self.cells.get(&(row, *col)) else {
+                    continue;
+                };
+                for idx in cell {
+                    let idx = *idx as usize;
+                    let distance = calculate_distance_between_coords(&self.points[idx], center);
+                    if distance <= radius {
+                        hits.push((idx, distance));
+                    }
+                }
+            }
+        }
+        hits
+    }
+
+    /// Every college inside `bbox`, as positions in the catalog list.
+    pub fn in_bbox(&self, bbox: &BoundingBox) -> Vec<usize> {
+        let first = lon_col(bbox.min_lon);
+        // A box ending on the antimeridian would otherwise wrap round to the first column.
+        let last = (((bbox.max_lon + 180.0) / CELL_DEGREES).floor() as i32).clamp(0, LON_CELLS - 1);
+        let mut lon_cols: Vec<i32> = if bbox.min_lon <= bbox.max_lon {
+            (first..=last).collect()
+        } else {
+            (first..LON_CELLS).chain(0..=last).collect()
+        };
+        // A wrapping box that starts on the antimeridian, or starts and ends in the same column,
+        // would otherwise visit some columns twice.
+        lon_cols.sort_unstable();
+        lon_cols.dedup();
+
+        let mut hits = Vec::new();
+        for row in lat_row(bbox.min_lat)..=lat_row(bbox.max_lat) {
+            for col in &lon_cols {
+                let Some(cell) = self.cells.get(&(row, *col)) else {
+                    continue;
+                };
+                hits.extend(
+                    cell.iter()
+                        .map(|idx| *idx as usize)
+                        .filter(|idx| bbox.contains(&self.points[*idx])),
+                );
+            }
+        }
+        hits.sort_unstable();
+        hits
+    }
+
+    /// The `k` closest colleges to `center` that `keep` accepts and are no further than
+    /// `max_radius` miles, closest first.
+    /// The search radius doubles until enough colleges are found.
+    pub fn nearest(
+        &self,
+        center: &CollegeCoord,
+        k: usize,
+        max_radius: Option<f64>,
+        keep: impl Fn(usize) -> bool,
+    ) -> Vec<(usize, f64)> {
+        let max_radius = max_radius.unwrap_or(MAX_DISTANCE_MILES);
+        let mut radius = NEAREST_START_RADIUS_MILES.min(max_radius);
+
+        loop {
+            let mut hits: Vec<(usize, f64)> = self
+                .within(center, radius)
+                .into_iter()
+                .filter(|(idx, _)| keep(*idx))
+                .collect();
+
+            if hits.len() >= k || radius >= max_radius {
+                hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+                hits.truncate(k);
+                return hits;
+            }
+            radius = (radius * 2.0).min(max_radius);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn college(lat: f64, lon: f64) -> CollegeStruct {
+        CollegeStruct {
+            ipedsid: format!("{lat},{lon}"),
+            name: String::new(),
+            address: String::new(),
+            city: String::new(),
+            state: String::new(),
+            zip: String::new(),
+            geo_point_2d: CollegeCoord { lon, lat },
+            naics_desc: String::new(),
+            acceptance_rate: None,
+            sat_avg: None,
+            act_avg: None,
+        }
+    }
+
+    fn index_of(points: &[(f64, f64)]) -> SpatialIndex {
+        let colleges: Vec<CollegeStruct> = points
+            .iter()
+            .map(|(lat, lon)| college(*lat, *lon))
+            .collect();
+        SpatialIndex::build(&colleges)
+    }
+
+    fn positions(mut hits: Vec<(usize, f64)>) -> Vec<usize> {
+        hits.sort_by_key(|(idx, _)| *idx);
+        hits.into_iter().map(|(idx, _)| idx).collect()
+    }
+
+    #[test]
+    fn within_reaches_across_the_antimeridian() {
+        let index = index_of(&[(0.0, 179.9), (0.0, -179.9), (0.0, 170.0)]);
+        let center = CollegeCoord {
+            lon: -179.99,
+            lat: 0.0,
+        };
+
+        assert_eq!(positions(index.within(&center, 20.0)), vec![0, 1]);
+    }
+
+    #[test]
+    fn within_reaches_over_the_poles() {
+        // Either side of the north pole, less than 15 miles apart over it.
+        let index = index_of(&[(89.9, 0.0), (89.9, 180.0), (89.9, 90.0), (80.0, 0.0)]);
+        let center = CollegeCoord {
+            lon: -90.0,
+            lat: 89.95,
+        };
+        assert_eq!(positions(index.within(&center, 20.0)), vec![0, 1, 2]);
+
+        let south = index_of(&[(-89.95, 45.0), (-89.95, -135.0), (-85.0, 0.0)]);
+        let center = CollegeCoord {
+            lon: 0.0,
+            lat: -90.0,
+        };
+        assert_eq!(positions(south.within(&center, 10.0)), vec![0, 1]);
+    }
+
+    #[test]
+    fn within_covers_the_whole_earth_at_the_largest_distance() {
+        let index = index_of(&[(40.0, -74.0), (-33.9, 151.2), (89.9, 0.0), (0.0, 180.0)]);
+        let center = CollegeCoord {
+            lon: -74.0,
+            lat: 40.0,
+        };
+
+        assert_eq!(
+            positions(index.within(&center, MAX_DISTANCE_MILES)),
+            vec![0, 1, 2, 3]
+        );
+    }
+
+    fn bbox(min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> BoundingBox {
+        BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        }
+    }
+
+    #[test]
+    fn in_bbox_finds_each_college_once() {
+        let index = index_of(&[
+            (40.0, -74.0),
+            (0.0, 179.9),
+            (0.0, -179.9),
+            (0.0, -175.0),
+            (0.0, 10.15),
+            (0.0, 10.3),
+        ]);
+
+        assert_eq!(index.in_bbox(&bbox(-80.0, 35.0, -70.0, 45.0)), vec![0]);
+        // Crossing the antimeridian.
+        assert_eq!(index.in_bbox(&bbox(179.0, -1.0, -179.0, 1.0)), vec![1, 2]);
+        // Starting on it, so the first column is also the one the wrap ends in.
+        assert_eq!(index.in_bbox(&bbox(180.0, -1.0, -170.0, 1.0)), vec![2, 3]);
+        // Ending on it.
+        assert_eq!(index.in_bbox(&bbox(170.0, -1.0, 180.0, 1.0)), vec![1]);
+        // Wrapping all the way round, with both ends in the same column.
+        assert_eq!(
+            index.in_bbox(&bbox(10.2, -90.0, 10.1, 90.0)),
+            vec![0, 1, 2, 3, 5]
+        );
+    }
+
+    #[test]
+    fn nearest_returns_what_there_is_when_k_exceeds_the_matches() {
+        let index = index_of(&[(40.0, -74.0), (40.5, -74.0), (41.0, -74.0), (10.0, 10.0)]);
+        let center = CollegeCoord {
+            lon: -74.0,
+            lat: 40.0,
+        };
+
+        let hits = index.nearest(&center, 10, None, |_| true);
+        assert_eq!(
+            hits.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert!(hits.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+        // Both the radius and the filter still apply.
+        let hits = index.nearest(&center, 10, Some(80.0), |idx| idx != 1);
+        assert_eq!(
+            hits.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+
+        assert!(index.nearest(&center, 10, None, |_| false).is_empty());
+    }
+
+    #[test]
+    fn nearest_looks_across_the_antimeridian() {
+        let index = index_of(&[(0.0, 170.0), (0.0, 179.95), (0.0, -170.0)]);
+        let center = CollegeCoord {
+            lon: -179.95,
+            lat: 0.0,
+        };
+
+        let hits = index.nearest(&center, 2, None, |_| true);
+        assert_eq!(
+            hits.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+}
@@ -1,17 +1,71 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
 
 use jsonwebtoken::{
-    decode, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation,
+    decode, decode_header, encode, errors::ErrorKind, get_current_timestamp, Header, Validation,
 };
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-const JWT_EXP_TIME: u128 = 3600000;
+use crate::jwt_keys::JwtKeys;
 
+const DEFAULT_JWT_LIFETIME: u64 = 60 * 60;
+const JWT_LEEWAY: u64 = 60;
+// Tokens issued before exp/iat were switched to seconds carried milliseconds.
+// No seconds-based timestamp reaches this value for thousands of years.
+const LEGACY_MILLIS_THRESHOLD: u64 = 100_000_000_000;
+
+/// Settings for issuing and validating our own access tokens.
+#[derive(Clone)]
+pub struct JwtConfig {
+    pub keys: Arc<JwtKeys>,
+    pub issuer: String,
+    pub audience: String,
+    /// Access token lifetime in seconds.
+    pub lifetime: u64,
+    /// Whether tokens with millisecond `exp`/`iat`/`nbf` are still accepted. Off by default.
+    pub accept_legacy: bool,
+}
+
+impl JwtConfig {
+    /// Reads the keys (see [`JwtKeys::from_env`]), `JWT_ISSUER` and `JWT_AUDIENCE`, and optionally
+    /// `JWT_LIFETIME_SECS` and `JWT_ACCEPT_LEGACY_TOKENS`.
+    pub fn from_env() -> Self {
+        let lifetime = match std::env::var("JWT_LIFETIME_SECS") {
+            Ok(val) => val
+                .parse::<u64>()
+                .expect("Unable to parse JWT_LIFETIME_SECS as u64"),
+            Err(_) => DEFAULT_JWT_LIFETIME,
+        };
+
+        // Legacy tokens only lived for an hour after the upgrade, so they are refused unless a
+        // deployment still needs them and opts in.
+        let accept_legacy = match std::env::var("JWT_ACCEPT_LEGACY_TOKENS") {
+            Ok(val) => val
+                .parse::<bool>()
+                .expect("Unable to parse JWT_ACCEPT_LEGACY_TOKENS as bool"),
+            Err(_) => false,
+        };
+
+        Self {
+            keys: Arc::new(JwtKeys::from_env()),
+            issuer: std::env::var("JWT_ISSUER").expect("No JWT_ISSUER in .env file"),
+            audience: std::env::var("JWT_AUDIENCE").expect("No jwt audience in .env file"),
+            lifetime,
+            accept_legacy,
+        }
+    }
+}
+
+/// Registered claims follow RFC 7519, so `exp`, `iat` and `nbf` are in seconds.
 #[derive(Deserialize, Serialize, std::fmt::Debug)]
 pub struct AccessTokenClaims {
     pub aud: String,
-    pub exp: u128,
-    pub iat: u128,
+    pub exp: u64,
+    pub iat: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
     pub iss: String,
     pub sub: String,
 
@@ -21,40 +75,33 @@ pub struct AccessTokenClaims {
 }
 
 pub fn create_access_token(
-    jwt_secret: &str,
-    jwt_iss: &str,
-    jwt_aud: &str,
+    config: &JwtConfig,
     uid: i32,
     email: &str,
     name: &str,
     picture: &str,
 ) -> Option<String> {
-    let system_time = match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(n) => n.as_millis(),
-        Err(e) => {
-            eprintln!("error: {e}");
-            return None;
-        }
-    };
+    let now = get_current_timestamp();
 
     let token_claims = AccessTokenClaims {
-        aud: jwt_aud.to_string(),
-        iss: jwt_iss.to_string(),
+        aud: config.audience.clone(),
+        iss: config.issuer.clone(),
 
         email: email.to_string(),
         name: name.to_string(),
         picture: picture.to_string(),
         sub: uid.to_string(),
 
-        iat: system_time,
-        exp: system_time + JWT_EXP_TIME,
+        iat: now,
+        nbf: Some(now),
+        exp: now + config.lifetime,
+        jti: Some(Uuid::new_v4().to_string()),
     };
 
-    match encode(
-        &Header::default(),
-        &token_claims,
-        &EncodingKey::from_secret(jwt_secret.as_bytes()),
-    ) {
+    let mut header = Header::new(config.keys.signing_alg());
+    header.kid = config.keys.signing_kid().map(str::to_string);
+
+    match encode(&header, &token_claims, config.keys.encoding_key()) {
         Ok(token) => Some(token),
         Err(e) => {
             eprintln!("error: {e}");
@@ -63,31 +110,250 @@ pub fn create_access_token(
     }
 }
 
+/// Why an access token was rejected.
+#[derive(std::fmt::Debug, PartialEq)]
+pub enum AccessTokenError {
+    Expired,
+    NotYetValid,
+    LegacyFormat,
+    InvalidAudience,
+    InvalidIssuer,
+    InvalidSignature,
+    UnknownKey,
+    Malformed,
+}
+
+impl AccessTokenError {
+    /// A stable, machine-readable reason for clients.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AccessTokenError::Expired => "expired",
+            AccessTokenError::NotYetValid => "not_yet_valid",
+            AccessTokenError::LegacyFormat => "legacy_format",
+            AccessTokenError::InvalidAudience => "bad_audience",
+            AccessTokenError::InvalidIssuer => "bad_issuer",
+            AccessTokenError::InvalidSignature => "bad_signature",
+            AccessTokenError::UnknownKey => "unknown_key",
+            AccessTokenError::Malformed => "malformed",
+        }
+    }
+
+    pub fn msg(&self) -> &'static str {
+        match self {
+            AccessTokenError::Expired => "Access token has expired",
+            AccessTokenError::NotYetValid => "Access token is not valid yet",
+            AccessTokenError::LegacyFormat => "Access token uses an outdated format",
+            AccessTokenError::InvalidAudience => "Access token was issued for a different audience",
+            AccessTokenError::InvalidIssuer => "Access token was issued by an unknown issuer",
+            AccessTokenError::InvalidSignature => "Access token signature is invalid",
+            AccessTokenError::UnknownKey => "Access token was signed with an unknown key",
+            AccessTokenError::Malformed => "Access token is malformed",
+        }
+    }
+}
+
 pub fn decode_access_token(
     access_token: &str,
-    jwt_secret: &str,
-    jwt_iss: &str,
-    jwt_aud: &str,
-) -> Option<AccessTokenClaims> {
-    let mut validation = Validation::new(jsonwebtoken::Algorithm::HS256);
-    validation.set_audience(&[jwt_aud]);
-    validation.set_issuer(&[jwt_iss]);
-
-    match decode::<AccessTokenClaims>(
-        &access_token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(td) => Some(td.claims),
+    config: &JwtConfig,
+) -> Result<AccessTokenClaims, AccessTokenError> {
+    let header = decode_header(access_token).map_err(|_| AccessTokenError::Malformed)?;
+    let decoding_key = config
+        .keys
+        .verifying_key(header.kid.as_deref(), header.alg)
+        .ok_or(AccessTokenError::UnknownKey)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&config.audience]);
+    validation.set_issuer(&[&config.issuer]);
+    // Time-based claims are checked below, once legacy millisecond values have been normalized.
+    validation.validate_exp = false;
+    validation.validate_nbf = false;
+    validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+
+    let mut claims = match decode::<AccessTokenClaims>(access_token, decoding_key, &validation) {
+        Ok(td) => td.claims,
         Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => eprintln!("expired token"),
-                ErrorKind::InvalidToken => eprintln!("invalid access token"),
-                ErrorKind::InvalidAudience => eprintln!("invalid audience"),
-                ErrorKind::InvalidIssuer => eprintln!("invalid issuer"),
-                e => eprintln!("some other jwt error {:?}", e),
-            }
-            None
+            return Err(match e.kind() {
+                ErrorKind::InvalidAudience => AccessTokenError::InvalidAudience,
+                ErrorKind::InvalidIssuer => AccessTokenError::InvalidIssuer,
+                ErrorKind::InvalidSignature => AccessTokenError::InvalidSignature,
+                _ => AccessTokenError::Malformed,
+            })
         }
+    };
+
+    if claims.exp >= LEGACY_MILLIS_THRESHOLD {
+        if !config.accept_legacy {
+            return Err(AccessTokenError::LegacyFormat);
+        }
+        claims.exp /= 1000;
+        claims.iat /= 1000;
+        claims.nbf = claims.nbf.map(|nbf| nbf / 1000);
+    }
+
+    let now = get_current_timestamp();
+    if claims.exp + JWT_LEEWAY < now {
+        return Err(AccessTokenError::Expired);
+    }
+    if let Some(nbf) = claims.nbf {
+        if nbf > now + JWT_LEEWAY {
+            return Err(AccessTokenError::NotYetValid);
+        }
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn config(accept_legacy: bool) -> JwtConfig {
+        JwtConfig {
+            keys: Arc::new(JwtKeys::from_secret("test-secret")),
+            issuer: "https://api.example.com".to_string(),
+            audience: "app.example.com".to_string(),
+            lifetime: DEFAULT_JWT_LIFETIME,
+            accept_legacy,
+        }
+    }
+
+    fn sign(config: &JwtConfig, claims: &Value) -> String {
+        encode(
+            &Header::new(config.keys.signing_alg()),
+            claims,
+            config.keys.encoding_key(),
+        )
+        .expect("sign test token")
+    }
+
+    /// Claims as tokens issued before the switch to seconds carried them.
+    fn legacy_claims(config: &JwtConfig, iat_ms: u64, exp_ms: u64) -> Value {
+        json!({
+            "aud": config.audience,
+            "iss": config.issuer,
+            "sub": "42",
+            "iat": iat_ms,
+            "nbf": iat_ms,
+            "exp": exp_ms,
+            "email": "student@example.com",
+            "name": "Test Student",
+            "picture": "",
+        })
+    }
+
+    #[test]
+    fn new_tokens_round_trip() {
+        let config = config(false);
+        let token = create_access_token(&config, 42, "student@example.com", "Test Student", "")
+            .expect("token");
+        let claims = decode_access_token(&token, &config).expect("valid token");
+        assert_eq!(claims.sub, "42");
+        assert_eq!(claims.exp, claims.iat + config.lifetime);
+    }
+
+    #[test]
+    fn legacy_millisecond_claims_are_normalized_when_accepted() {
+        let config = config(true);
+        let now_ms = get_current_timestamp() * 1000;
+        let token = sign(&config, &legacy_claims(&config, now_ms, now_ms + 3_600_000));
+
+        let claims = decode_access_token(&token, &config).expect("legacy token");
+        assert_eq!(claims.iat, now_ms / 1000);
+        assert_eq!(claims.nbf, Some(now_ms / 1000));
+        assert_eq!(claims.exp, now_ms / 1000 + 3600);
+    }
+
+    #[test]
+    fn legacy_millisecond_claims_are_still_checked() {
+        let config = config(true);
+        let now_ms = get_current_timestamp() * 1000;
+
+        let expired = legacy_claims(&config, now_ms - 7_200_000, now_ms - 3_600_000);
+        assert_eq!(
+            decode_access_token(&sign(&config, &expired), &config).err(),
+            Some(AccessTokenError::Expired)
+        );
+
+        let future = legacy_claims(&config, now_ms + 600_000, now_ms + 4_200_000);
+        assert_eq!(
+            decode_access_token(&sign(&config, &future), &config).err(),
+            Some(AccessTokenError::NotYetValid)
+        );
+    }
+
+    #[test]
+    fn tokens_are_verified_with_the_key_named_by_their_kid() {
+        let public_pems = vec![
+            (
+                "rsa".to_string(),
+                include_bytes!("fixtures/rsa_public.pem").to_vec(),
+            ),
+            (
+                "ed".to_string(),
+                include_bytes!("fixtures/ed25519_public.pem").to_vec(),
+            ),
+        ];
+        let rsa_signer = JwtConfig {
+            keys: Arc::new(JwtKeys::from_pems(
+                "rsa",
+                include_bytes!("fixtures/rsa_private.pem"),
+                &public_pems,
+                None,
+            )),
+            ..config(false)
+        };
+        let ed_signer = JwtConfig {
+            keys: Arc::new(JwtKeys::from_pems(
+                "ed",
+                include_bytes!("fixtures/ed25519_private.pem"),
+                &public_pems,
+                None,
+            )),
+            ..config(false)
+        };
+
+        // Either key verifies tokens signed by the other, as during a rollover.
+        for (signer, verifier) in [(&rsa_signer, &ed_signer), (&ed_signer, &rsa_signer)] {
+            let token = create_access_token(signer, 42, "student@example.com", "Test Student", "")
+                .expect("token");
+            assert_eq!(
+                decode_header(&token).unwrap().kid.as_deref(),
+                signer.keys.signing_kid()
+            );
+            assert!(decode_access_token(&token, verifier).is_ok());
+        }
+
+        // A token naming a key it was not signed with is rejected.
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("ed".to_string());
+        let claims = legacy_claims(&rsa_signer, 0, 0);
+        let token = encode(&header, &claims, rsa_signer.keys.encoding_key()).unwrap();
+        assert_eq!(
+            decode_access_token(&token, &rsa_signer).err(),
+            Some(AccessTokenError::UnknownKey)
+        );
+
+        // So is one naming a key that does not exist.
+        header.kid = Some("unknown".to_string());
+        let token = encode(&header, &claims, rsa_signer.keys.encoding_key()).unwrap();
+        assert_eq!(
+            decode_access_token(&token, &rsa_signer).err(),
+            Some(AccessTokenError::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn legacy_tokens_are_rejected_unless_enabled() {
+        let config = config(false);
+        let now_ms = get_current_timestamp() * 1000;
+        let token = sign(&config, &legacy_claims(&config, now_ms, now_ms + 3_600_000));
+        assert_eq!(
+            decode_access_token(&token, &config).err(),
+            Some(AccessTokenError::LegacyFormat)
+        );
     }
 }
@@ -0,0 +1,331 @@
+// Signing and verification keys for our access tokens.
+use std::{collections::HashMap, fs};
+
+use base64::{engine::general_purpose, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey,
+};
+use simple_asn1::{oid, ASN1Block};
+
+/// The key used to sign new tokens and every key that tokens may still be verified with.
+///
+/// With asymmetric keys, each token carries a `kid` header naming its verification key, so a
+/// new signing key can be rolled out while tokens signed by the previous one remain valid.
+pub struct JwtKeys {
+    signing_kid: Option<String>,
+    signing_alg: Algorithm,
+    encoding_key: EncodingKey,
+    verifying_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    hmac_key: Option<DecodingKey>,
+    jwks: JwkSet,
+}
+
+impl JwtKeys {
+    /// Uses an RS256 or EdDSA key pair when `JWT_SIGNING_KEY_ID` and `JWT_SIGNING_KEY_PATH`
+    /// are set, with the public keys listed in `JWT_PUBLIC_KEYS` as `kid=path` pairs.
+    /// Otherwise falls back to HS256 with `JWT_SECRET`.
+    /// When both are configured, `JWT_SECRET` only verifies older HS256 tokens.
+    pub fn from_env() -> Self {
+        let hmac_secret = std::env::var("JWT_SECRET").ok();
+
+        let (signing_kid, signing_key_path) = match (
+            std::env::var("JWT_SIGNING_KEY_ID"),
+            std::env::var("JWT_SIGNING_KEY_PATH"),
+        ) {
+            (Ok(kid), Ok(path)) => (kid, path),
+            _ => return Self::from_secret(&hmac_secret.expect("No JWT secret found in .env file")),
+        };
+
+        let public_keys =
+            std::env::var("JWT_PUBLIC_KEYS").expect("No JWT_PUBLIC_KEYS in .env file");
+        let public_pems: Vec<(String, Vec<u8>)> = public_keys
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (kid, path) = entry
+                    .trim()
+                    .split_once('=')
+                    .expect("JWT_PUBLIC_KEYS entries must look like kid=path");
+                let pem_bytes = fs::read(path)
+                    .unwrap_or_else(|e| panic!("Unable to read public key for kid {kid}: {e}"));
+                (kid.to_string(), pem_bytes)
+            })
+            .collect();
+
+        let private_pem = fs::read(&signing_key_path).expect("Unable to read JWT_SIGNING_KEY_PATH");
+
+        Self::from_pems(
+            &signing_kid,
+            &private_pem,
+            &public_pems,
+            hmac_secret.as_deref(),
+        )
+    }
+
+    /// Signs with the private key in `private_pem`, whose public key must be listed under
+    /// `signing_kid` in `public_pems`, and verifies with every key in `public_pems`.
+    pub fn from_pems(
+        signing_kid: &str,
+        private_pem: &[u8],
+        public_pems: &[(String, Vec<u8>)],
+        hmac_secret: Option<&str>,
+    ) -> Self {
+        let mut verifying_keys = HashMap::new();
+        let mut jwks = JwkSet { keys: Vec::new() };
+
+        for (kid, pem_bytes) in public_pems {
+            let (alg, params) = parse_public_key_pem(pem_bytes)
+                .unwrap_or_else(|| panic!("Unsupported public key for kid {kid}"));
+
+            let jwk = Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(match alg {
+                        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+                        _ => KeyAlgorithm::RS256,
+                    }),
+                    key_id: Some(kid.to_string()),
+                    ..Default::default()
+                },
+                algorithm: params,
+            };
+            let decoding_key =
+                DecodingKey::from_jwk(&jwk).expect("Unable to build decoding key from public key");
+
+            verifying_keys.insert(kid.to_string(), (alg, decoding_key));
+            jwks.keys.push(jwk);
+        }
+
+        let signing_alg = match verifying_keys.get(signing_kid) {
+            Some((alg, _)) => *alg,
+            None => panic!("JWT_PUBLIC_KEYS must include the signing key {signing_kid}"),
+        };
+
+        let encoding_key = match signing_alg {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem),
+            _ => EncodingKey::from_rsa_pem(private_pem),
+        }
+        .expect("Unable to parse JWT signing key");
+
+        Self {
+            signing_kid: Some(signing_kid.to_string()),
+            signing_alg,
+            encoding_key,
+            verifying_keys,
+            hmac_key: hmac_secret.map(|secret| DecodingKey::from_secret(secret.as_bytes())),
+            jwks,
+        }
+    }
+
+    /// Signs and verifies with HS256 and nothing else.
+    pub fn from_secret(secret: &str) -> Self {
+        Self {
+            signing_kid: None,
+            signing_alg: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            verifying_keys: HashMap::new(),
+            hmac_key: Some(DecodingKey::from_secret(secret.as_bytes())),
+            jwks: JwkSet { keys: Vec::new() },
+        }
+    }
+
+    pub fn signing_kid(&self) -> Option<&str> {
+        self.signing_kid.as_deref()
+    }
+
+    pub fn signing_alg(&self) -> Algorithm {
+        self.signing_alg
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    /// Finds the key a token claims to be signed with.
+    /// Tokens without a `kid` can only have been signed with the shared secret.
+    pub fn verifying_key(&self, kid: Option<&str>, alg: Algorithm) -> Option<&DecodingKey> {
+        match kid {
+            Some(kid) => self
+                .verifying_keys
+                .get(kid)
+                .filter(|(key_alg, _)| *key_alg == alg)
+                .map(|(_, key)| key),
+            None if alg == Algorithm::HS256 => self.hmac_key.as_ref(),
+            None => None,
+        }
+    }
+
+    /// The public keys, for publishing at `/.well-known/jwks.json`.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+fn b64_url(bytes: &[u8]) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn rsa_params(blocks: &[ASN1Block]) -> Option<AlgorithmParameters> {
+    // RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }
+    let ASN1Block::Sequence(_, fields) = blocks.first()? else {
+        return None;
+    };
+    match fields.as_slice() {
+        [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e), ..] => {
+            Some(AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: b64_url(&n.to_bytes_be().1),
+                e: b64_url(&e.to_bytes_be().1),
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Reads an RSA (PKCS#1 or SPKI) or Ed25519 (SPKI) public key.
+fn parse_public_key_pem(pem_bytes: &[u8]) -> Option<(Algorithm, AlgorithmParameters)> {
+    let pem = pem::parse(pem_bytes).ok()?;
+    let blocks = simple_asn1::from_der(pem.contents()).ok()?;
+
+    match pem.tag() {
+        "RSA PUBLIC KEY" => Some((Algorithm::RS256, rsa_params(&blocks)?)),
+        "PUBLIC KEY" => {
+            // SubjectPublicKeyInfo ::= SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING }
+            let ASN1Block::Sequence(_, spki) = blocks.first()? else {
+                return None;
+            };
+            let (ASN1Block::Sequence(_, alg_id), ASN1Block::BitString(_, _, key)) =
+                (spki.first()?, spki.get(1)?)
+            else {
+                return None;
+            };
+            let ASN1Block::ObjectIdentifier(_, key_oid) = alg_id.first()? else {
+                return None;
+            };
+
+            if *key_oid == oid!(1, 2, 840, 113_549, 1, 1, 1) {
+                let rsa_blocks = simple_asn1::from_der(key).ok()?;
+                Some((Algorithm::RS256, rsa_params(&rsa_blocks)?))
+            } else if *key_oid == oid!(1, 3, 101, 112) {
+                Some((
+                    Algorithm::EdDSA,
+                    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                        key_type: OctetKeyPairType::OctetKeyPair,
+                        curve: EllipticCurve::Ed25519,
+                        x: b64_url(key),
+                    }),
+                ))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{decode, encode, Header, Validation};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const RSA_PRIVATE: &[u8] = include_bytes!("fixtures/rsa_private.pem");
+    const RSA_SPKI: &[u8] = include_bytes!("fixtures/rsa_public.pem");
+    const RSA_PKCS1: &[u8] = include_bytes!("fixtures/rsa_public_pkcs1.pem");
+    const ED25519_PRIVATE: &[u8] = include_bytes!("fixtures/ed25519_private.pem");
+    const ED25519_SPKI: &[u8] = include_bytes!("fixtures/ed25519_public.pem");
+
+    /// Signs with `private_pem` and verifies with the key parsed from `public_pem`.
+    fn round_trip(public_pem: &[u8], private_pem: &[u8]) -> Algorithm {
+        let (alg, params) = parse_public_key_pem(public_pem).expect("supported public key");
+        let jwk = Jwk {
+            common: CommonParameters::default(),
+            algorithm: params,
+        };
+        let encoding_key = match alg {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem),
+            _ => EncodingKey::from_rsa_pem(private_pem),
+        }
+        .expect("private key");
+
+        let claims = json!({ "sub": "42", "exp": u64::MAX / 2 });
+        let token = encode(&Header::new(alg), &claims, &encoding_key).expect("sign");
+        let mut validation = Validation::new(alg);
+        validation.set_required_spec_claims(&["sub"]);
+        let decoded = decode::<Value>(
+            &token,
+            &DecodingKey::from_jwk(&jwk).expect("decoding key"),
+            &validation,
+        )
+        .expect("signature verifies");
+        assert_eq!(decoded.claims, claims);
+        alg
+    }
+
+    #[test]
+    fn parses_rsa_spki_and_pkcs1_public_keys() {
+        assert_eq!(round_trip(RSA_SPKI, RSA_PRIVATE), Algorithm::RS256);
+        assert_eq!(round_trip(RSA_PKCS1, RSA_PRIVATE), Algorithm::RS256);
+
+        let (_, AlgorithmParameters::RSA(spki)) = parse_public_key_pem(RSA_SPKI).unwrap() else {
+            panic!("not an RSA key");
+        };
+        let (_, AlgorithmParameters::RSA(pkcs1)) = parse_public_key_pem(RSA_PKCS1).unwrap() else {
+            panic!("not an RSA key");
+        };
+        assert_eq!((&spki.n, &spki.e), (&pkcs1.n, &pkcs1.e));
+        assert_eq!(spki.e, "AQAB");
+    }
+
+    #[test]
+    fn parses_ed25519_public_keys() {
+        assert_eq!(round_trip(ED25519_SPKI, ED25519_PRIVATE), Algorithm::EdDSA);
+    }
+
+    #[test]
+    fn rejects_malformed_and_unsupported_pems() {
+        assert!(parse_public_key_pem(b"not a pem").is_none());
+
+        let truncated = String::from_utf8(RSA_SPKI.to_vec())
+            .unwrap()
+            .replacen("MII", "MIA", 1);
+        assert!(parse_public_key_pem(truncated.as_bytes()).is_none());
+
+        // A private key is not a public key.
+        assert!(parse_public_key_pem(RSA_PRIVATE).is_none());
+    }
+
+    #[test]
+    fn verifying_keys_are_selected_by_kid() {
+        let public_pems = vec![
+            ("rsa".to_string(), RSA_SPKI.to_vec()),
+            ("ed".to_string(), ED25519_SPKI.to_vec()),
+        ];
+        let rsa_keys = JwtKeys::from_pems("rsa", RSA_PRIVATE, &public_pems, None);
+        let ed_keys = JwtKeys::from_pems("ed", ED25519_PRIVATE, &public_pems, None);
+        assert_eq!(rsa_keys.signing_alg(), Algorithm::RS256);
+        assert_eq!(ed_keys.signing_alg(), Algorithm::EdDSA);
+        assert_eq!(rsa_keys.jwks().keys.len(), 2);
+
+        assert!(rsa_keys
+            .verifying_key(Some("rsa"), Algorithm::RS256)
+            .is_some());
+        assert!(rsa_keys
+            .verifying_key(Some("ed"), Algorithm::EdDSA)
+            .is_some());
+        // The algorithm has to match the key's.
+        assert!(rsa_keys
+            .verifying_key(Some("ed"), Algorithm::RS256)
+            .is_none());
+        assert!(rsa_keys
+            .verifying_key(Some("unknown"), Algorithm::RS256)
+            .is_none());
+        // Without a shared secret, tokens without a kid have nothing to be verified with.
+        assert!(rsa_keys.verifying_key(None, Algorithm::HS256).is_none());
+    }
+}
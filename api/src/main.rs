@@ -1,18 +1,47 @@
-use std::{env, io};
+use std::{env, io, time::Duration};
 
 use actix_web::{middleware::Logger, web, App, HttpServer};
+use api_error::ApiError;
 use app_state::AppState;
 use bb8_redis::{bb8, RedisConnectionManager};
+use catalog::Catalog;
+use catalog_snapshot::CatalogSnapshots;
 use dotenvy::dotenv;
+use google_auth::GoogleAuthConfig;
+use jwt::JwtConfig;
 use sea_orm::Database;
 
+mod api_error;
 mod app_state;
+mod auth_user;
+mod cache;
+mod catalog;
+mod catalog_snapshot;
+mod clustering;
+mod college_filter;
+mod geocoder;
+mod google_auth;
+mod import_catalog;
+mod indexes;
 mod jwt;
+mod jwt_keys;
+mod paged_fetch;
+mod refresh_token;
 mod routes;
+mod scraper;
+mod single_flight;
 mod structures;
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
+    // `api import-catalog <file>` loads a local catalog export instead of starting the server.
+    // It only needs DATABASE_URL, which may come from the environment instead of a .env file.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("import-catalog") {
+        dotenv().ok();
+        return import_catalog::run(args[1..].to_vec()).await;
+    }
+
     dotenv().expect("Unable to find .env file");
 
     let port: u16 = match env::var("PORT") {
@@ -20,9 +49,7 @@ async fn main() -> io::Result<()> {
         Err(_) => 8000,
     };
 
-    let jwt_secret = env::var("JWT_SECRET").expect("No JWT secret found in .env file");
-    let jwt_issuer = env::var("JWT_ISSUER").expect("No JWT_ISSUER in .env file");
-    let jwt_audience = env::var("JWT_AUDIENCE").expect("No jwt audience in .env file");
+    let jwt_config = JwtConfig::from_env();
 
     let database_url = env::var("DATABASE_URL").expect("No DATABASE_URL found in .env file");
     // Here, we must initialize a database connection with seaorm.
@@ -35,31 +62,76 @@ async fn main() -> io::Result<()> {
     let redis_manager =
         RedisConnectionManager::new(redis_url).expect("Unable to connect to redis instance");
     let redis_pool = bb8::Pool::builder()
+        // Kept short so an outage falls back to the in-process cache quickly.
+        .connection_timeout(Duration::from_secs(2))
         .build(redis_manager)
         .await
         .expect("Unable to initialize redis pool");
+    let cache = cache::from_env(redis_pool);
+
+    // The catalog is kept in memory and reloaded in the background, starting right away so
+    // the first requests rarely have to wait for it.
+    let catalog = CatalogSnapshots::from_env(Catalog::new(db.clone(), cache.clone()));
+    catalog.spawn_refresh_task();
+
+    let geocoder = geocoder::from_env(cache.clone());
 
-    let pos_stack_key = env::var("POS_STACK_KEY").expect("No POS_STACK_KEY in .env file");
+    let google_auth = GoogleAuthConfig::from_env();
+
+    let admissions_llm_fallback =
+        env::var("ADMISSIONS_LLM_FALLBACK").is_ok_and(|value| value == "true" || value == "1");
+
+    let admin_token = env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
 
     // Now, we can create the universal app state.
     HttpServer::new(move || {
         App::new()
-            .wrap(Logger::new("%a %r %D"))
+            .wrap_fn(api_error::scope_request_id)
+            .wrap(Logger::new("%a %r %D %{x-request-id}o"))
+            // Malformed query strings get the same error envelope as everything else.
+            .app_data(web::QueryConfig::default().error_handler(|err, _req| {
+                ApiError::BadRequest(err.to_string()).into()
+            }))
             .app_data(web::Data::new(AppState {
                 db: db.clone(),
-                jwt_sec: jwt_secret.clone(),
-                jwt_iss: jwt_issuer.clone(),
-                jwt_aud: jwt_audience.clone(),
-                redis_pool: redis_pool.clone(),
-                pos_stack_key: pos_stack_key.clone(),
+                jwt: jwt_config.clone(),
+                cache: cache.clone(),
+                catalog: catalog.clone(),
+                geocoder: geocoder.clone(),
+                google_auth: google_auth.clone(),
+                admissions_llm_fallback,
+                admin_token: admin_token.clone(),
             }))
             .service(routes::handle_root_path)
+            .service(routes::handle_jwks)
+            .service(routes::handle_status)
+            .service(routes::admin::handle_catalog_status)
             .service(routes::auth::handle_google_login)
             .service(routes::auth::handle_verify_access_token)
+            .service(routes::auth::handle_refresh_token)
+            .service(routes::auth::handle_logout)
+            .service(routes::auth::handle_logout_all)
+            .service(routes::auth::handle_get_me)
             .service(routes::colleges::hande_list_all_colleges)
             .service(routes::colleges::handle_get_colleges_with_params)
+            .service(routes::colleges::handle_search_colleges)
+            .service(routes::colleges::handle_autocomplete_colleges)
+            .service(routes::map::handle_get_college_map)
+            .service(routes::map::handle_get_college_regions)
             .service(routes::colleges::handle_get_single_college_info)
+            .service(routes::colleges::handle_get_single_college_info_v2)
             .service(routes::colleges::handle_how_reviewed_route)
+            .service(routes::me::handle_get_saved_colleges)
+            .service(routes::me::handle_save_college)
+            .service(routes::me::handle_reorder_saved_colleges)
+            .service(routes::me::handle_delete_saved_college)
+            .service(routes::applications::handle_list_applications)
+            .service(routes::applications::handle_get_application)
+            .service(routes::applications::handle_create_application)
+            .service(routes::applications::handle_update_application)
+            .service(routes::applications::handle_delete_application)
+            .service(routes::applications::handle_add_checklist_item)
+            .service(routes::applications::handle_update_checklist_item)
     })
     .bind(("127.0.0.1", port))?
     .run()
@@ -0,0 +1,719 @@
+// Fetching every record from a paginated JSON API, a few pages at a time, with retries.
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use awc::{
+    http::header::{HeaderValue, RETRY_AFTER},
+    Client,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// Waiting longer than this for a rate limit to lift fails the page instead, so the crawl can be
+/// resumed later.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// The shape of a page, as served by opendatasoft's records API.
+#[derive(Deserialize)]
+struct Page<T> {
+    total_count: usize,
+    results: Vec<T>,
+}
+
+#[derive(Clone)]
+pub struct PagedFetchConfig {
+    pub page_size: usize,
+    /// How many pages are requested at once.
+    pub concurrency: usize,
+    /// Tries per page, including the first.
+    pub max_attempts: u32,
+    /// The backoff before the first retry, doubled for each one after.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for PagedFetchConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 100,
+            concurrency: 4,
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PageError {
+    Request(String),
+    Status(u16),
+    Decode(String),
+    /// The API asked us to wait longer than `MAX_RETRY_AFTER`.
+    RateLimited(Duration),
+}
+
+impl PageError {
+    /// Whether trying again might work.
+    fn is_transient(&self) -> bool {
+        match self {
+            PageError::Request(_) => true,
+            PageError::Status(status) => matches!(status, 408 | 429) || *status >= 500,
+            PageError::Decode(_) | PageError::RateLimited(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::Request(e) => write!(f, "request failed: {e}"),
+            PageError::Status(status) => write!(f, "unexpected status {status}"),
+            PageError::Decode(e) => write!(f, "unreadable page: {e}"),
+            PageError::RateLimited(wait) => {
+                write!(f, "rate limited for {}s", wait.as_secs())
+            }
+        }
+    }
+}
+
+/// The pages fetched by a crawl that did not finish, by offset.
+pub struct PartialFetch<T> {
+    total_count: usize,
+    page_size: usize,
+    pages: BTreeMap<usize, Vec<T>>,
+    started_at: Instant,
+}
+
+impl<T> PartialFetch<T> {
+    pub fn fetched(&self) -> usize {
+        self.pages.values().map(Vec::len).sum()
+    }
+
+    pub fn started_at(&self) -> Instant {
+        self.started_at
+    }
+}
+
+pub enum FetchError<T> {
+    /// A page still failed after its retries. The pages fetched so far are kept, when the total
+    /// was known, so a later crawl can pick up where this one stopped.
+    Incomplete {
+        partial: Option<PartialFetch<T>>,
+        offset: usize,
+        error: PageError,
+    },
+    /// Every page was fetched but they did not add up to the total the API reported, e.g.
+    /// because the data changed during the crawl.
+    CountMismatch { expected: usize, actual: usize },
+}
+
+impl<T> fmt::Display for FetchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Incomplete {
+                partial,
+                offset,
+                error,
+            } => {
+                write!(f, "page at offset {offset} failed: {error}")?;
+                if let Some(partial) = partial {
+                    write!(
+                        f,
+                        " ({} of {} records fetched)",
+                        partial.fetched(),
+                        partial.total_count
+                    )?;
+                }
+                Ok(())
+            }
+            FetchError::CountMismatch { expected, actual } => {
+                write!(f, "expected {expected} records, got {actual}")
+            }
+        }
+    }
+}
+
+/// Reads `Retry-After`, given either in seconds or as an HTTP date.
+fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        at.with_timezone(&Utc)
+            .signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Fetches every record behind a URL that takes `limit` and `offset` parameters.
+/// The first page gives the total; the rest are then fetched `concurrency` at a time.
+pub struct PagedFetcher {
+    client: Client,
+    base_url: String,
+    config: PagedFetchConfig,
+    // Every request waits until then once the API has asked us to slow down.
+    paused_until: Cell<Option<Instant>>,
+}
+
+impl PagedFetcher {
+    pub fn new(client: Client, base_url: impl Into<String>, config: PagedFetchConfig) -> Self {
+        Self {
+            client,
+            base_url: base_url.into(),
+            config: PagedFetchConfig {
+                page_size: config.page_size.max(1),
+                concurrency: config.concurrency.max(1),
+                max_attempts: config.max_attempts.max(1),
+                ..config
+            },
+            paused_until: Cell::new(None),
+        }
+    }
+
+    /// Fetches every record, in order. Pages already in `resume` are not fetched again, as long
+    /// as the total has not changed since.
+    pub async fn fetch_all<T: DeserializeOwned>(
+        &self,
+        resume: Option<PartialFetch<T>>,
+    ) -> Result<Vec<T>, FetchError<T>> {
+        let page_size = self.config.page_size;
+
+        // The first page is always fetched again, to learn the current total.
+        let first_page = match self.fetch_page_with_retries::<T>(0).await {
+            Ok(page) => page,
+            Err(error) => {
+                return Err(FetchError::Incomplete {
+                    partial: resume,
+                    offset: 0,
+                    error,
+                })
+            }
+        };
+        let total_count = first_page.total_count;
+        let mut partial = match resume {
+            Some(resume) if resume.total_count == total_count && resume.page_size == page_size => {
+                resume
+            }
+            _ => PartialFetch {
+                total_count,
+                page_size,
+                pages: BTreeMap::new(),
+                started_at: Instant::now(),
+            },
+        };
+        partial.pages.insert(0, first_page.results);
+
+        let missing: Vec<usize> = (page_size..total_count)
+            .step_by(page_size)
+            .filter(|offset| !partial.pages.contains_key(offset))
+            .collect();
+        let mut fetches = stream::iter(missing)
+            .map(|offset| async move { (offset, self.fetch_page_with_retries::<T>(offset).await) })
+            .buffer_unordered(self.config.concurrency);
+
+        let mut failure = None;
+        while let Some((offset, result)) = fetches.next().await {
+            match result {
+                Ok(page) => {
+                    partial.pages.insert(offset, page.results);
+                }
+                // The other pages are still fetched, so there is less to do when resuming.
+                Err(error) => {
+                    eprintln!("page at offset {offset} failed: {error}");
+                    failure.get_or_insert((offset, error));
+                }
+            }
+        }
+        drop(fetches);
+
+        if let Some((offset, error)) = failure {
+            return Err(FetchError::Incomplete {
+                partial: Some(partial),
+                offset,
+                error,
+            });
+        }
+
+        let records: Vec<T> = partial.pages.into_values().flatten().collect();
+        if records.len() != total_count {
+            return Err(FetchError::CountMismatch {
+                expected: total_count,
+                actual: records.len(),
+            });
+        }
+        Ok(records)
+    }
+
+    async fn fetch_page_with_retries<T: DeserializeOwned>(
+        &self,
+        offset: usize,
+    ) -> Result<Page<T>, PageError> {
+        let mut attempt = 1;
+        loop {
+            self.wait_for_pause().await;
+            let (error, retry_after) = match self.fetch_page(offset).await {
+                Ok(page) => return Ok(page),
+                Err(failure) => failure,
+            };
+            if !error.is_transient() || attempt >= self.config.max_attempts {
+                return Err(error);
+            }
+
+            let delay = match retry_after {
+                Some(retry_after) if retry_after > MAX_RETRY_AFTER => {
+                    return Err(PageError::RateLimited(retry_after))
+                }
+                // A rate limit applies to every request, not just this one.
+                Some(retry_after) => {
+                    self.pause_for(retry_after);
+                    retry_after
+                }
+                None => self.backoff(attempt),
+            };
+            eprintln!(
+                "page at offset {offset} failed ({error}), retrying in {}ms",
+                delay.as_millis()
+            );
+            actix_web::rt::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// A single try at a page, with how long the API asked us to wait if it did.
+    async fn fetch_page<T: DeserializeOwned>(
+        &self,
+        offset: usize,
+    ) -> Result<Page<T>, (PageError, Option<Duration>)> {
+        let separator = if self.base_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let url = format!(
+            "{}{separator}limit={}&offset={offset}",
+            self.base_url, self.config.page_size
+        );
+
+        let mut response = self
+            .client
+            .get(url)
+            .timeout(self.config.timeout)
+            .send()
+            .await
+            .map_err(|e| (PageError::Request(e.to_string()), None))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(parse_retry_after);
+            return Err((PageError::Status(status.as_u16()), retry_after));
+        }
+
+        let body = response
+            .body()
+            .await
+            .map_err(|e| (PageError::Request(e.to_string()), None))?;
+        serde_json::from_slice::<Page<T>>(&body)
+            .map_err(|e| (PageError::Decode(e.to_string()), None))
+    }
+
+    /// Exponential backoff with jitter, so concurrent retries do not all land at once.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .config
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.config.max_delay);
+        let half = exp / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+
+    fn pause_for(&self, wait: Duration) {
+        let until = Instant::now() + wait;
+        if self.paused_until.get().is_none_or(|paused| paused < until) {
+            self.paused_until.set(Some(until));
+        }
+    }
+
+    async fn wait_for_pause(&self) {
+        if let Some(until) = self.paused_until.get() {
+            let now = Instant::now();
+            if until > now {
+                actix_web::rt::time::sleep(until - now).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, VecDeque},
+        sync::{Arc, Mutex},
+    };
+
+    use actix_web::{
+        dev::ServerHandle, http::StatusCode, rt::System, web, App, HttpResponse, HttpServer,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const PAGE_SIZE: usize = 3;
+
+    /// What the fake upstream answers with instead of a page.
+    #[derive(Clone, Copy)]
+    enum Failure {
+        Status(u16, Option<&'static str>),
+        Garbage,
+    }
+
+    #[derive(Deserialize)]
+    struct PageQuery {
+        limit: usize,
+        offset: usize,
+    }
+
+    struct Upstream {
+        records: Vec<u32>,
+        total_count: usize,
+        // Answered in turn for the offset before any page is served.
+        failures: HashMap<usize, VecDeque<Failure>>,
+        requested: Vec<usize>,
+    }
+
+    /// A stand-in for the records API, serving `records` in pages and logging the offsets asked
+    /// for.
+    struct FakeUpstream {
+        upstream: Arc<Mutex<Upstream>>,
+        url: String,
+        server: ServerHandle,
+    }
+
+    impl FakeUpstream {
+        fn start(records: Vec<u32>) -> Self {
+            let upstream = Arc::new(Mutex::new(Upstream {
+                total_count: records.len(),
+                records,
+                failures: HashMap::new(),
+                requested: Vec::new(),
+            }));
+            let served = upstream.clone();
+            let server = HttpServer::new(move || {
+                let served = served.clone();
+                App::new().route(
+                    "/records",
+                    web::get().to(move |query: web::Query<PageQuery>| {
+                        let response = respond(&served, &query);
+                        async move { response }
+                    }),
+                )
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("bind fake upstream server");
+            let port = server.addrs()[0].port();
+            let server = server.run();
+            let handle = server.handle();
+            actix_web::rt::spawn(server);
+
+            Self {
+                upstream,
+                url: format!("http://127.0.0.1:{port}/records"),
+                server: handle,
+            }
+        }
+
+        fn fetcher(&self) -> PagedFetcher {
+            PagedFetcher::new(
+                Client::default(),
+                &self.url,
+                PagedFetchConfig {
+                    page_size: PAGE_SIZE,
+                    concurrency: 2,
+                    max_attempts: 3,
+                    base_delay: Duration::from_millis(10),
+                    max_delay: Duration::from_millis(50),
+                    timeout: Duration::from_secs(5),
+                },
+            )
+        }
+
+        fn fail(&self, offset: usize, failures: &[Failure]) {
+            let mut upstream = self.upstream.lock().unwrap();
+            upstream
+                .failures
+                .entry(offset)
+                .or_default()
+                .extend(failures.iter().copied());
+        }
+
+        fn set_records(&self, records: Vec<u32>) {
+            let mut upstream = self.upstream.lock().unwrap();
+            upstream.total_count = records.len();
+            upstream.records = records;
+        }
+
+        /// Offsets requested since the last call, sorted.
+        fn take_requested(&self) -> Vec<usize> {
+            let mut requested = std::mem::take(&mut self.upstream.lock().unwrap().requested);
+            requested.sort_unstable();
+            requested
+        }
+    }
+
+    fn respond(upstream: &Mutex<Upstream>, query: &PageQuery) -> HttpResponse {
+        let mut upstream = upstream.lock().unwrap();
+        upstream.requested.push(query.offset);
+        let failure = upstream
+            .failures
+            .get_mut(&query.offset)
+            .and_then(VecDeque::pop_front);
+        match failure {
+            Some(Failure::Status(status, retry_after)) => {
+                let mut response = HttpResponse::build(StatusCode::from_u16(status).unwrap());
+                if let Some(retry_after) = retry_after {
+                    response.insert_header((RETRY_AFTER, retry_after));
+                }
+                response.finish()
+            }
+            Some(Failure::Garbage) => HttpResponse::Ok()
+                .content_type("application/json")
+                .body(r#"{"total_count": "#),
+            None => {
+                let results: Vec<u32> = upstream
+                    .records
+                    .iter()
+                    .skip(query.offset)
+                    .take(query.limit)
+                    .copied()
+                    .collect();
+                HttpResponse::Ok().json(json!({
+                    "total_count": upstream.total_count,
+                    "results": results,
+                }))
+            }
+        }
+    }
+
+    fn expect_records(result: Result<Vec<u32>, FetchError<u32>>) -> Vec<u32> {
+        match result {
+            Ok(records) => records,
+            Err(e) => panic!("fetch failed: {e}"),
+        }
+    }
+
+    fn expect_incomplete(
+        result: Result<Vec<u32>, FetchError<u32>>,
+    ) -> (Option<PartialFetch<u32>>, usize, PageError) {
+        match result {
+            Err(FetchError::Incomplete {
+                partial,
+                offset,
+                error,
+            }) => (partial, offset, error),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(records) => panic!("expected a failure, got {} records", records.len()),
+        }
+    }
+
+    #[test]
+    fn every_page_is_fetched_in_order() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+
+            let records = expect_records(upstream.fetcher().fetch_all(None).await);
+
+            assert_eq!(records, (0..10).collect::<Vec<_>>());
+            assert_eq!(upstream.take_requested(), vec![0, 3, 6, 9]);
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn server_errors_and_rate_limits_are_retried() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+            upstream.fail(3, &[Failure::Status(503, None), Failure::Status(502, None)]);
+            upstream.fail(6, &[Failure::Status(429, Some("1"))]);
+
+            let started = Instant::now();
+            let records = expect_records(upstream.fetcher().fetch_all(None).await);
+
+            assert_eq!(records, (0..10).collect::<Vec<_>>());
+            assert_eq!(upstream.take_requested(), vec![0, 3, 3, 3, 6, 6, 9]);
+            // The retry waited as long as Retry-After asked, not just the short backoff.
+            assert!(started.elapsed() >= Duration::from_secs(1));
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn pages_fail_once_their_attempts_run_out() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+            upstream.fail(3, &[Failure::Status(500, None); 5]);
+
+            let (partial, offset, error) =
+                expect_incomplete(upstream.fetcher().fetch_all(None).await);
+
+            assert_eq!(offset, 3);
+            assert!(matches!(error, PageError::Status(500)));
+            assert_eq!(upstream.take_requested(), vec![0, 3, 3, 3, 6, 9]);
+            // The other pages were still fetched.
+            assert_eq!(partial.unwrap().fetched(), 7);
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn client_errors_and_unreadable_pages_are_not_retried() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+
+            upstream.fail(3, &[Failure::Status(404, None)]);
+            let (_, offset, error) = expect_incomplete(upstream.fetcher().fetch_all(None).await);
+            assert_eq!(offset, 3);
+            assert!(matches!(error, PageError::Status(404)));
+            assert_eq!(upstream.take_requested(), vec![0, 3, 6, 9]);
+
+            upstream.fail(6, &[Failure::Garbage]);
+            let (_, offset, error) = expect_incomplete(upstream.fetcher().fetch_all(None).await);
+            assert_eq!(offset, 6);
+            assert!(matches!(error, PageError::Decode(_)));
+            assert_eq!(upstream.take_requested(), vec![0, 3, 6, 9]);
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn long_rate_limits_fail_the_page() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+            upstream.fail(3, &[Failure::Status(429, Some("3600"))]);
+
+            let (_, offset, error) = expect_incomplete(upstream.fetcher().fetch_all(None).await);
+
+            assert_eq!(offset, 3);
+            assert!(
+                matches!(error, PageError::RateLimited(wait) if wait == Duration::from_secs(3600))
+            );
+            assert_eq!(upstream.take_requested(), vec![0, 3, 6, 9]);
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn resuming_skips_pages_already_fetched() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+            upstream.fail(6, &[Failure::Status(404, None)]);
+            let fetcher = upstream.fetcher();
+            let (partial, _, _) = expect_incomplete(fetcher.fetch_all(None).await);
+            upstream.take_requested();
+
+            let records = expect_records(fetcher.fetch_all(partial).await);
+
+            assert_eq!(records, (0..10).collect::<Vec<_>>());
+            // The first page is fetched again for the total, then only the one that failed.
+            assert_eq!(upstream.take_requested(), vec![0, 6]);
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn resuming_starts_over_when_the_total_changed() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..10).collect());
+            upstream.fail(6, &[Failure::Status(404, None)]);
+            let fetcher = upstream.fetcher();
+            let (partial, _, _) = expect_incomplete(fetcher.fetch_all(None).await);
+            upstream.take_requested();
+            upstream.set_records((100..112).collect());
+
+            let records = expect_records(fetcher.fetch_all(partial).await);
+
+            assert_eq!(records, (100..112).collect::<Vec<_>>());
+            assert_eq!(upstream.take_requested(), vec![0, 3, 6, 9]);
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn pages_that_fall_short_of_the_total_are_a_mismatch() {
+        System::new().block_on(async {
+            let upstream = FakeUpstream::start((0..8).collect());
+            upstream.upstream.lock().unwrap().total_count = 10;
+
+            match upstream.fetcher().fetch_all::<u32>(None).await {
+                Err(FetchError::CountMismatch { expected, actual }) => {
+                    assert_eq!((expected, actual), (10, 8));
+                }
+                Err(e) => panic!("unexpected error: {e}"),
+                Ok(records) => panic!("expected a mismatch, got {} records", records.len()),
+            }
+            upstream.server.stop(false).await;
+        });
+    }
+
+    #[test]
+    fn retry_after_is_read_as_seconds_or_a_date() {
+        let parse = |value: &str| parse_retry_after(&HeaderValue::from_str(value).unwrap());
+
+        assert_eq!(parse("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse(" 5 "), Some(Duration::from_secs(5)));
+
+        let in_a_minute = (Utc::now() + chrono::Duration::seconds(60))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let wait = parse(&in_a_minute).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+
+        // A date that has passed means no wait at all.
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+
+        assert_eq!(parse("soon"), None);
+        assert_eq!(parse("-1"), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max_with_jitter() {
+        let fetcher = PagedFetcher::new(
+            Client::default(),
+            "http://127.0.0.1:1/records",
+            PagedFetchConfig {
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(1),
+                ..Default::default()
+            },
+        );
+
+        for attempt in 1..=40 {
+            let exp = Duration::from_millis(100)
+                .saturating_mul(1 << (attempt - 1).min(16))
+                .min(Duration::from_secs(1));
+            for _ in 0..20 {
+                let delay = fetcher.backoff(attempt);
+                assert!(
+                    delay >= exp / 2 && delay <= exp,
+                    "attempt {attempt}: {delay:?} outside {:?}..={exp:?}",
+                    exp / 2
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+// Opaque refresh tokens that let clients renew their access token without signing in again.
+use base64::{engine::general_purpose, Engine};
+use chrono::{Duration, Utc};
+use entities::{
+    refresh_token::{self, Entity as RefreshToken},
+    user::{self, Entity as User},
+};
+use rand::{rngs::OsRng, RngCore};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone,
+    sea_query::{Expr, SimpleExpr},
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+    TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const REFRESH_TOKEN_EXP_DAYS: i64 = 30;
+const REFRESH_TOKEN_BYTES: usize = 32;
+
+#[derive(std::fmt::Debug)]
+pub enum RefreshTokenError {
+    NotFound,
+    Expired,
+    Reused,
+    Database,
+}
+
+impl RefreshTokenError {
+    pub fn msg(&self) -> &'static str {
+        match self {
+            RefreshTokenError::NotFound => "Invalid refresh token",
+            RefreshTokenError::Expired => "Refresh token has expired",
+            RefreshTokenError::Reused => "Refresh token reuse detected, please sign in again",
+            RefreshTokenError::Database => "Unable to make database query",
+        }
+    }
+}
+
+impl From<DbErr> for RefreshTokenError {
+    fn from(e: DbErr) -> Self {
+        eprintln!("error: {e}");
+        RefreshTokenError::Database
+    }
+}
+
+fn revoked_now() -> SimpleExpr {
+    Expr::value(DateTimeWithTimeZone::from(Utc::now()))
+}
+
+fn hash_refresh_token(token: &str) -> String {
+    general_purpose::STANDARD_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; REFRESH_TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+async fn insert_refresh_token<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    family_id: Uuid,
+    device_label: Option<String>,
+    rotated_from: Option<i32>,
+) -> Result<String, DbErr> {
+    let token = generate_refresh_token();
+    let now = Utc::now();
+
+    refresh_token::ActiveModel {
+        token_hash: ActiveValue::Set(hash_refresh_token(&token)),
+        user_id: ActiveValue::Set(user_id),
+        family_id: ActiveValue::Set(family_id),
+        device_label: ActiveValue::Set(device_label),
+        expires_at: ActiveValue::Set((now + Duration::days(REFRESH_TOKEN_EXP_DAYS)).into()),
+        created_at: ActiveValue::Set(now.into()),
+        rotated_from: ActiveValue::Set(rotated_from),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Starts a new token family for a fresh sign-in and returns the plain token.
+pub async fn issue_refresh_token<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    device_label: Option<String>,
+) -> Result<String, DbErr> {
+    insert_refresh_token(db, user_id, Uuid::new_v4(), device_label, None).await
+}
+
+/// Exchanges a refresh token for a new one in the same family.
+/// Presenting a token that was already rotated or revoked revokes the whole family,
+/// since it means the token has been copied.
+pub async fn rotate_refresh_token<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+    token: &str,
+) -> Result<(user::Model, String), RefreshTokenError> {
+    let existing = RefreshToken::find()
+        .filter(refresh_token::Column::TokenHash.eq(hash_refresh_token(token)))
+        .one(db)
+        .await?
+        .ok_or(RefreshTokenError::NotFound)?;
+
+    if existing.revoked_at.is_some() {
+        revoke_family(db, existing.family_id).await?;
+        return Err(RefreshTokenError::Reused);
+    }
+    if existing.expires_at < Utc::now() {
+        return Err(RefreshTokenError::Expired);
+    }
+
+    let txn = db.begin().await?;
+
+    // Only one concurrent request may consume the token; the loser is treated as a replay.
+    let consumed = RefreshToken::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, revoked_now())
+        .filter(refresh_token::Column::Id.eq(existing.id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if consumed.rows_affected != 1 {
+        txn.rollback().await?;
+        revoke_family(db, existing.family_id).await?;
+        return Err(RefreshTokenError::Reused);
+    }
+
+    let user = User::find_by_id(existing.user_id)
+        .one(&txn)
+        .await?
+        .ok_or(RefreshTokenError::NotFound)?;
+
+    let new_token = insert_refresh_token(
+        &txn,
+        existing.user_id,
+        existing.family_id,
+        existing.device_label,
+        Some(existing.id),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok((user, new_token))
+}
+
+/// Revokes every token descended from the same sign-in as the given token.
+pub async fn revoke_refresh_token<C: ConnectionTrait>(
+    db: &C,
+    token: &str,
+) -> Result<(), RefreshTokenError> {
+    let existing = RefreshToken::find()
+        .filter(refresh_token::Column::TokenHash.eq(hash_refresh_token(token)))
+        .one(db)
+        .await?
+        .ok_or(RefreshTokenError::NotFound)?;
+
+    revoke_family(db, existing.family_id).await?;
+    Ok(())
+}
+
+/// Revokes every active refresh token belonging to a user.
+pub async fn revoke_all_refresh_tokens<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<(), DbErr> {
+    RefreshToken::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, revoked_now())
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+async fn revoke_family<C: ConnectionTrait>(db: &C, family_id: Uuid) -> Result<(), DbErr> {
+    RefreshToken::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, revoked_now())
+        .filter(refresh_token::Column::FamilyId.eq(family_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::rt::System;
+    use sea_orm::{Database, DatabaseConnection, Schema};
+
+    use super::*;
+
+    /// An in-memory SQLite database with the tables refresh tokens need and one user in it.
+    async fn test_db() -> (DatabaseConnection, i32) {
+        let db = Database::connect("sqlite::memory:")
+            .await
+            .expect("open sqlite");
+        let backend = db.get_database_backend();
+        let schema = Schema::new(backend);
+        for table in [
+            schema.create_table_from_entity(User),
+            schema.create_table_from_entity(RefreshToken),
+        ] {
+            db.execute(backend.build(&table))
+                .await
+                .expect("create table");
+        }
+
+        let user = user::ActiveModel {
+            email: ActiveValue::Set("student@example.com".to_string()),
+            name: ActiveValue::Set("Test Student".to_string()),
+            picture: ActiveValue::Set(String::new()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .expect("insert user");
+        (db, user.id)
+    }
+
+    async fn find_token(db: &DatabaseConnection, token: &str) -> refresh_token::Model {
+        RefreshToken::find()
+            .filter(refresh_token::Column::TokenHash.eq(hash_refresh_token(token)))
+            .one(db)
+            .await
+            .expect("query refresh token")
+            .expect("refresh token exists")
+    }
+
+    #[test]
+    fn rotation_replaces_the_token_within_its_family() {
+        System::new().block_on(async {
+            let (db, user_id) = test_db().await;
+            let token = issue_refresh_token(&db, user_id, Some("phone".to_string()))
+                .await
+                .expect("issue");
+
+            let (user, rotated) = rotate_refresh_token(&db, &token).await.expect("rotate");
+            assert_eq!(user.id, user_id);
+            assert_ne!(rotated, token);
+
+            let old = find_token(&db, &token).await;
+            let new = find_token(&db, &rotated).await;
+            assert!(old.revoked_at.is_some());
+            assert!(new.revoked_at.is_none());
+            assert_eq!(new.family_id, old.family_id);
+            assert_eq!(new.rotated_from, Some(old.id));
+            assert_eq!(new.device_label.as_deref(), Some("phone"));
+
+            // The new token can be rotated in turn.
+            assert!(rotate_refresh_token(&db, &rotated).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn replaying_a_rotated_token_revokes_its_family() {
+        System::new().block_on(async {
+            let (db, user_id) = test_db().await;
+            let token = issue_refresh_token(&db, user_id, None)
+                .await
+                .expect("issue");
+            let other_sign_in = issue_refresh_token(&db, user_id, None)
+                .await
+                .expect("issue");
+            let (_, rotated) = rotate_refresh_token(&db, &token).await.expect("rotate");
+
+            assert!(matches!(
+                rotate_refresh_token(&db, &token).await,
+                Err(RefreshTokenError::Reused)
+            ));
+            assert!(find_token(&db, &rotated).await.revoked_at.is_some());
+            assert!(matches!(
+                rotate_refresh_token(&db, &rotated).await,
+                Err(RefreshTokenError::Reused)
+            ));
+
+            // Other sign-ins are left alone.
+            assert!(rotate_refresh_token(&db, &other_sign_in).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn expired_and_unknown_tokens_are_rejected() {
+        System::new().block_on(async {
+            let (db, user_id) = test_db().await;
+            let token = issue_refresh_token(&db, user_id, None)
+                .await
+                .expect("issue");
+            RefreshToken::update_many()
+                .col_expr(
+                    refresh_token::Column::ExpiresAt,
+                    Expr::value(DateTimeWithTimeZone::from(
+                        Utc::now() - Duration::minutes(1),
+                    )),
+                )
+                .exec(&db)
+                .await
+                .expect("expire token");
+
+            assert!(matches!(
+                rotate_refresh_token(&db, &token).await,
+                Err(RefreshTokenError::Expired)
+            ));
+            assert!(find_token(&db, &token).await.revoked_at.is_none());
+            assert!(matches!(
+                rotate_refresh_token(&db, &generate_refresh_token()).await,
+                Err(RefreshTokenError::NotFound)
+            ));
+        });
+    }
+}
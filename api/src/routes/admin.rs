@@ -0,0 +1,140 @@
+// Routes under the /admin path, for operators. Requests need `Authorization: Bearer <ADMIN_TOKEN>`.
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    api_error::ApiError, app_state::AppState, auth_user::get_bearer_token, catalog,
+    catalog_snapshot::SnapshotStatus,
+};
+
+fn require_admin(req: &HttpRequest, state: &AppState) -> Result<(), ApiError> {
+    // Without a token the admin routes don't exist, rather than existing but refusing everyone.
+    let Some(admin_token) = &state.admin_token else {
+        return Err(ApiError::NotFound("Not found"));
+    };
+    // Digests are compared so the time taken says nothing about the token.
+    match get_bearer_token(req) {
+        Some(given) if Sha256::digest(&given) == Sha256::digest(admin_token) => Ok(()),
+        Some(_) => Err(ApiError::Unauthorized {
+            message: "Invalid admin token",
+            reason: "invalid_admin_token",
+        }),
+        None => Err(ApiError::MissingToken("Missing bearer admin token")),
+    }
+}
+
+#[derive(Serialize)]
+pub struct CatalogStatusResp {
+    snapshot: SnapshotStatus,
+    // When the catalog in the database was last pulled from upstream.
+    imported_at: Option<DateTimeWithTimeZone>,
+}
+
+/// How the in-memory catalog's background refresh is doing.
+#[get("/admin/catalog/status")]
+pub async fn handle_catalog_status(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req, &state)?;
+
+    let imported_at = catalog::last_imported_at(&state.db).await.map_err(|e| {
+        eprintln!("error: {e}");
+        ApiError::Internal("Unable to make database query")
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(CatalogStatusResp {
+            snapshot: state.catalog.status(),
+            imported_at,
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        rt::System,
+        test::{call_service, init_service, TestRequest},
+        App,
+    };
+
+    use super::*;
+    use crate::app_state::tests::{test_college, test_state};
+
+    /// The status of a catalog status request with `authorization`, when the admin token is
+    /// `admin_token`, and its WWW-Authenticate header.
+    async fn catalog_status(
+        admin_token: Option<&str>,
+        authorization: Option<&str>,
+    ) -> (StatusCode, Option<String>) {
+        let mut state = test_state(vec![test_college(
+            "100",
+            "First College",
+            "MA",
+            42.0,
+            -71.0,
+        )])
+        .await;
+        state.admin_token = admin_token.map(str::to_string);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .service(handle_catalog_status),
+        )
+        .await;
+
+        let mut req = TestRequest::get().uri("/admin/catalog/status");
+        if let Some(authorization) = authorization {
+            req = req.insert_header((header::AUTHORIZATION, authorization));
+        }
+        let resp = call_service(&app, req.to_request()).await;
+        let challenge = resp
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_string());
+        (resp.status(), challenge)
+    }
+
+    #[test]
+    fn admin_routes_need_the_admin_token() {
+        System::new().block_on(async {
+            let token = Some("s3cret");
+            assert_eq!(
+                catalog_status(token, Some("Bearer s3cret")).await.0,
+                StatusCode::OK
+            );
+            assert_eq!(
+                catalog_status(token, Some("bearer s3cret")).await.0,
+                StatusCode::OK
+            );
+            assert_eq!(
+                catalog_status(token, None).await,
+                (StatusCode::UNAUTHORIZED, Some("Bearer".to_string()))
+            );
+            assert_eq!(
+                catalog_status(token, Some("Bearer wrong")).await,
+                (
+                    StatusCode::UNAUTHORIZED,
+                    Some(
+                        "Bearer error=\"invalid_token\", error_description=\"invalid_admin_token\""
+                            .to_string()
+                    )
+                )
+            );
+        });
+    }
+
+    #[test]
+    fn admin_routes_do_not_exist_without_a_token() {
+        System::new().block_on(async {
+            assert_eq!(
+                catalog_status(None, Some("Bearer s3cret")).await.0,
+                StatusCode::NOT_FOUND
+            );
+        });
+    }
+}
@@ -0,0 +1,650 @@
+// Routes under the /me/applications path, tracking the signed-in user's college applications.
+
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use chrono::{NaiveDate, Utc};
+use entities::{
+    application::{self, Entity as Application},
+    application_checklist_item::{self, Entity as ApplicationChecklistItem},
+    sea_orm_active_enums::{ApplicationStatus, DecisionType},
+};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection,
+    DbErr, EntityTrait, LoaderTrait, ModelTrait, QueryFilter, QueryOrder, QuerySelect, SqlErr,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    app_state::AppState,
+    auth_user::AuthenticatedUser,
+    routes::colleges::{get_all_colleges, get_single_college_info},
+};
+
+#[derive(Serialize)]
+pub struct ChecklistItemResp {
+    id: i32,
+    label: String,
+    done: bool,
+    done_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Serialize)]
+pub struct ApplicationResp {
+    id: i32,
+    ipedsid: String,
+    status: ApplicationStatus,
+    decision_type: Option<DecisionType>,
+    deadline: Option<NaiveDate>,
+    notes: Option<String>,
+    checklist: Vec<ChecklistItemResp>,
+    created_at: DateTimeWithTimeZone,
+    updated_at: DateTimeWithTimeZone,
+}
+
+impl ApplicationResp {
+    fn from(
+        model: application::Model,
+        mut checklist: Vec<application_checklist_item::Model>,
+    ) -> Self {
+        checklist.sort_by_key(|item| (item.position, item.id));
+        Self {
+            id: model.id,
+            ipedsid: model.ipedsid,
+            status: model.status,
+            decision_type: model.decision_type,
+            deadline: model.deadline,
+            notes: model.notes,
+            checklist: checklist
+                .into_iter()
+                .map(|item| ChecklistItemResp {
+                    id: item.id,
+                    label: item.label,
+                    done: item.done,
+                    done_at: item.done_at,
+                })
+                .collect(),
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ApplicationRespWrapper {
+    application: ApplicationResp,
+}
+
+#[derive(Serialize)]
+pub struct ApplicationListResp {
+    applications: Vec<ApplicationResp>,
+}
+
+/// Whether an application may move from one status to another.
+/// Students can step back while preparing, but decisions only move forward.
+fn is_valid_transition(from: ApplicationStatus, to: ApplicationStatus) -> bool {
+    use ApplicationStatus::*;
+
+    from == to
+        || matches!(
+            (from, to),
+            (Researching, InProgress)
+                | (InProgress, Researching)
+                | (InProgress, Submitted)
+                | (Submitted, InProgress)
+                | (Submitted, Admitted)
+                | (Submitted, Waitlisted)
+                | (Submitted, Rejected)
+                | (Waitlisted, Admitted)
+                | (Waitlisted, Rejected)
+                | (Admitted, Enrolled)
+        )
+}
+
+/// Whether an application may be created with a status. A decision can only be recorded on an
+/// application that has been tracked since before it came in.
+fn is_initial_status(status: ApplicationStatus) -> bool {
+    use ApplicationStatus::*;
+
+    matches!(status, Researching | InProgress | Submitted)
+}
+
+/// Finds one of the user's applications, answering 404 for other users' applications.
+async fn find_user_application(
+    db: &DatabaseConnection,
+    user_id: i32,
+    application_id: i32,
+) -> Result<application::Model, ApiError> {
+    Application::find_by_id(application_id)
+        .filter(application::Column::UserId.eq(user_id))
+        .one(db)
+        .await?
+        .ok_or(ApiError::NotFound("Application not found"))
+}
+
+async fn respond_with_application(
+    db: &DatabaseConnection,
+    model: application::Model,
+) -> Result<HttpResponse, ApiError> {
+    let checklist = model.find_related(ApplicationChecklistItem).all(db).await?;
+    Ok(HttpResponse::Ok().json(ApplicationRespWrapper {
+        application: ApplicationResp::from(model, checklist),
+    }))
+}
+
+/// Logs a database error and describes what failed for the response.
+fn db_failure(msg: &'static str) -> impl Fn(DbErr) -> ApiError {
+    move |e| {
+        eprintln!("error: {e}");
+        ApiError::Internal(msg)
+    }
+}
+
+#[get("/me/applications")]
+pub async fn handle_list_applications(
+    auth: AuthenticatedUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let applications = Application::find()
+        .filter(application::Column::UserId.eq(auth.user.id))
+        .order_by_asc(application::Column::Deadline)
+        .order_by_asc(application::Column::Id)
+        .all(&state.db)
+        .await?;
+
+    let checklists = applications
+        .load_many(ApplicationChecklistItem, &state.db)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApplicationListResp {
+        applications: applications
+            .into_iter()
+            .zip(checklists)
+            .map(|(model, checklist)| ApplicationResp::from(model, checklist))
+            .collect(),
+    }))
+}
+
+#[get("/me/applications/{application_id}")]
+pub async fn handle_get_application(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let model = find_user_application(&state.db, auth.user.id, *path).await?;
+    respond_with_application(&state.db, model).await
+}
+
+#[derive(Deserialize)]
+pub struct CreateApplicationReqBody {
+    ipedsid: String,
+    status: Option<ApplicationStatus>,
+    decision_type: Option<DecisionType>,
+    deadline: Option<NaiveDate>,
+    notes: Option<String>,
+}
+
+#[post("/me/applications")]
+pub async fn handle_create_application(
+    auth: AuthenticatedUser,
+    body: web::Json<CreateApplicationReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let status = body.status.unwrap_or(ApplicationStatus::Researching);
+    if !is_initial_status(status) {
+        return Err(ApiError::BadRequest(
+            "Applications cannot be created with a decision".to_string(),
+        ));
+    }
+
+    // The college name is needed to look up its application requirements.
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    let college_name = catalog
+        .colleges
+        .iter()
+        .find(|college| college.ipedsid == body.ipedsid)
+        .map(|college| college.name.clone())
+        .ok_or(ApiError::NotFound("No college with the given ipedsid"))?;
+
+    let existing = Application::find()
+        .filter(application::Column::UserId.eq(auth.user.id))
+        .filter(application::Column::Ipedsid.eq(&body.ipedsid))
+        .one(&state.db)
+        .await?;
+    if existing.is_some() {
+        return Err(ApiError::Conflict(
+            "An application for this college already exists",
+        ));
+    }
+
+    // The checklist starts from the college's application requirements. If they can't be
+    // fetched right now, the application is still created and items can be added by hand.
+    let requirements = match get_single_college_info(&body.ipedsid, &college_name, &state).await {
+        Ok(info) => info.application_reqs,
+        Err(e) => {
+            eprintln!("unable to seed application checklist: {e}");
+            Vec::new()
+        }
+    };
+
+    let now: DateTimeWithTimeZone = Utc::now().into();
+    let txn = state.db.begin().await?;
+
+    let model = (application::ActiveModel {
+        user_id: ActiveValue::Set(auth.user.id),
+        ipedsid: ActiveValue::Set(body.ipedsid.clone()),
+        status: ActiveValue::Set(status),
+        decision_type: ActiveValue::Set(body.decision_type),
+        deadline: ActiveValue::Set(body.deadline),
+        notes: ActiveValue::Set(body.notes.clone()),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        ..Default::default()
+    })
+    .insert(&txn)
+    .await
+    .map_err(|e| match e.sql_err() {
+        // Another request created the same application since the check above.
+        Some(SqlErr::UniqueConstraintViolation(_)) => {
+            ApiError::Conflict("An application for this college already exists")
+        }
+        _ => db_failure("Unable to create application")(e),
+    })?;
+
+    let checklist_items: Vec<application_checklist_item::ActiveModel> = requirements
+        .into_iter()
+        .enumerate()
+        .map(
+            |(position, label)| application_checklist_item::ActiveModel {
+                application_id: ActiveValue::Set(model.id),
+                label: ActiveValue::Set(label),
+                done: ActiveValue::Set(false),
+                done_at: ActiveValue::Set(None),
+                position: ActiveValue::Set(position as i32),
+                ..Default::default()
+            },
+        )
+        .collect();
+    if !checklist_items.is_empty() {
+        ApplicationChecklistItem::insert_many(checklist_items)
+            .exec(&txn)
+            .await
+            .map_err(db_failure("Unable to create application checklist"))?;
+    }
+
+    txn.commit()
+        .await
+        .map_err(db_failure("Unable to create application"))?;
+
+    respond_with_application(&state.db, model).await
+}
+
+#[derive(Deserialize)]
+pub struct UpdateApplicationReqBody {
+    status: Option<ApplicationStatus>,
+    decision_type: Option<DecisionType>,
+    deadline: Option<NaiveDate>,
+    notes: Option<String>,
+}
+
+#[patch("/me/applications/{application_id}")]
+pub async fn handle_update_application(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    body: web::Json<UpdateApplicationReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let model = find_user_application(&state.db, auth.user.id, *path).await?;
+
+    if let Some(status) = body.status {
+        if !is_valid_transition(model.status, status) {
+            return Err(ApiError::BadRequest(
+                "Application cannot move to the requested status".to_string(),
+            ));
+        }
+    }
+
+    let mut active: application::ActiveModel = model.into();
+    if let Some(status) = body.status {
+        active.status = ActiveValue::Set(status);
+    }
+    if body.decision_type.is_some() {
+        active.decision_type = ActiveValue::Set(body.decision_type);
+    }
+    if body.deadline.is_some() {
+        active.deadline = ActiveValue::Set(body.deadline);
+    }
+    if body.notes.is_some() {
+        active.notes = ActiveValue::Set(body.notes.clone());
+    }
+    active.updated_at = ActiveValue::Set(Utc::now().into());
+
+    let model = active
+        .update(&state.db)
+        .await
+        .map_err(db_failure("Unable to update application"))?;
+    respond_with_application(&state.db, model).await
+}
+
+#[delete("/me/applications/{application_id}")]
+pub async fn handle_delete_application(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let res = Application::delete_many()
+        .filter(application::Column::Id.eq(*path))
+        .filter(application::Column::UserId.eq(auth.user.id))
+        .exec(&state.db)
+        .await?;
+    if res.rows_affected == 0 {
+        return Err(ApiError::NotFound("Application not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct AddChecklistItemReqBody {
+    label: String,
+}
+
+#[post("/me/applications/{application_id}/checklist")]
+pub async fn handle_add_checklist_item(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    body: web::Json<AddChecklistItemReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    if body.label.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "Checklist item label cannot be empty".to_string(),
+        ));
+    }
+
+    let model = find_user_application(&state.db, auth.user.id, *path).await?;
+
+    // New items go to the end of the checklist.
+    let position = ApplicationChecklistItem::find()
+        .select_only()
+        .column_as(
+            application_checklist_item::Column::Position.max(),
+            "max_position",
+        )
+        .filter(application_checklist_item::Column::ApplicationId.eq(model.id))
+        .into_tuple::<Option<i32>>()
+        .one(&state.db)
+        .await?
+        .flatten()
+        .map_or(0, |max| max + 1);
+
+    (application_checklist_item::ActiveModel {
+        application_id: ActiveValue::Set(model.id),
+        label: ActiveValue::Set(body.label.trim().to_string()),
+        done: ActiveValue::Set(false),
+        done_at: ActiveValue::Set(None),
+        position: ActiveValue::Set(position),
+        ..Default::default()
+    })
+    .insert(&state.db)
+    .await
+    .map_err(db_failure("Unable to add checklist item"))?;
+
+    respond_with_application(&state.db, model).await
+}
+
+#[derive(Deserialize)]
+pub struct UpdateChecklistItemReqBody {
+    done: bool,
+}
+
+#[patch("/me/applications/{application_id}/checklist/{item_id}")]
+pub async fn handle_update_checklist_item(
+    auth: AuthenticatedUser,
+    path: web::Path<(i32, i32)>,
+    body: web::Json<UpdateChecklistItemReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (application_id, item_id) = path.into_inner();
+
+    let model = find_user_application(&state.db, auth.user.id, application_id).await?;
+
+    let item = ApplicationChecklistItem::find_by_id(item_id)
+        .filter(application_checklist_item::Column::ApplicationId.eq(model.id))
+        .one(&state.db)
+        .await?
+        .ok_or(ApiError::NotFound("Checklist item not found"))?;
+
+    let mut active: application_checklist_item::ActiveModel = item.into();
+    active.done = ActiveValue::Set(body.done);
+    active.done_at = ActiveValue::Set(body.done.then(|| Utc::now().into()));
+    active
+        .update(&state.db)
+        .await
+        .map_err(db_failure("Unable to update checklist item"))?;
+
+    respond_with_application(&state.db, model).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{
+        http::{header, StatusCode},
+        rt::System,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::app_state::tests::{sign_in, test_college, test_state};
+
+    const STATUSES: [ApplicationStatus; 7] = [
+        ApplicationStatus::Researching,
+        ApplicationStatus::InProgress,
+        ApplicationStatus::Submitted,
+        ApplicationStatus::Admitted,
+        ApplicationStatus::Waitlisted,
+        ApplicationStatus::Rejected,
+        ApplicationStatus::Enrolled,
+    ];
+
+    #[test]
+    fn only_listed_transitions_are_allowed() {
+        use ApplicationStatus::*;
+
+        // Every move between two different statuses that is allowed; all others are not.
+        let allowed = [
+            (Researching, InProgress),
+            (InProgress, Researching),
+            (InProgress, Submitted),
+            (Submitted, InProgress),
+            (Submitted, Admitted),
+            (Submitted, Waitlisted),
+            (Submitted, Rejected),
+            (Waitlisted, Admitted),
+            (Waitlisted, Rejected),
+            (Admitted, Enrolled),
+        ];
+        for from in STATUSES {
+            for to in STATUSES {
+                let expected = from == to || allowed.contains(&(from, to));
+                assert_eq!(
+                    is_valid_transition(from, to),
+                    expected,
+                    "{from:?} -> {to:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decisions_only_move_forward() {
+        use ApplicationStatus::*;
+
+        for (from, to) in [
+            (Admitted, Submitted),
+            (Rejected, Waitlisted),
+            (Rejected, Admitted),
+            (Enrolled, Admitted),
+            (Researching, Submitted),
+            (Researching, Admitted),
+            (Waitlisted, Enrolled),
+        ] {
+            assert!(!is_valid_transition(from, to), "{from:?} -> {to:?}");
+        }
+    }
+
+    #[test]
+    fn applications_start_before_a_decision() {
+        use ApplicationStatus::*;
+
+        for (status, expected) in [
+            (Researching, true),
+            (InProgress, true),
+            (Submitted, true),
+            (Admitted, false),
+            (Waitlisted, false),
+            (Rejected, false),
+            (Enrolled, false),
+        ] {
+            assert_eq!(is_initial_status(status), expected, "{status:?}");
+        }
+    }
+
+    /// State whose college "100" has known application requirements, so creating an application
+    /// for it never scrapes College Navigator.
+    async fn state() -> (web::Data<AppState>, String) {
+        let state = test_state(vec![test_college(
+            "100",
+            "First College",
+            "MA",
+            42.0,
+            -71.0,
+        )])
+        .await;
+        let info = json!({
+            "admissions_url": null,
+            "apply_url": null,
+            "finaid_url": null,
+            "admission_info": {},
+            "application_reqs": ["Transcript", "Two recommendations", "Essay"],
+        });
+        state
+            .cache
+            .set(
+                "COLLEGE_DATA_V2_100",
+                &info.to_string(),
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        let (_, auth) = sign_in(&state, "student@example.com").await;
+        (web::Data::new(state), auth)
+    }
+
+    fn create(auth: &str, body: Value) -> TestRequest {
+        TestRequest::post()
+            .uri("/me/applications")
+            .insert_header((header::AUTHORIZATION, auth))
+            .set_json(body)
+    }
+
+    #[test]
+    fn checklists_are_seeded_from_the_requirements_in_order() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(
+                App::new()
+                    .app_data(state)
+                    .service(handle_create_application),
+            )
+            .await;
+
+            let resp =
+                call_service(&app, create(&auth, json!({"ipedsid": "100"})).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body: Value = read_body_json(resp).await;
+
+            let application = &body["application"];
+            assert_eq!(application["status"], "researching");
+            let checklist: Vec<(&str, bool)> = application["checklist"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| {
+                    (
+                        item["label"].as_str().unwrap(),
+                        item["done"].as_bool().unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                checklist,
+                vec![
+                    ("Transcript", false),
+                    ("Two recommendations", false),
+                    ("Essay", false),
+                ]
+            );
+
+            // One application per college.
+            let resp =
+                call_service(&app, create(&auth, json!({"ipedsid": "100"})).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+        });
+    }
+
+    #[test]
+    fn applications_cannot_be_created_with_a_decision() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(
+                App::new()
+                    .app_data(state)
+                    .service(handle_create_application),
+            )
+            .await;
+
+            let body = json!({"ipedsid": "100", "status": "admitted"});
+            let resp = call_service(&app, create(&auth, body).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        });
+    }
+
+    #[test]
+    fn updates_follow_the_transitions() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(
+                App::new()
+                    .app_data(state)
+                    .service(handle_create_application)
+                    .service(handle_update_application),
+            )
+            .await;
+            let body = json!({"ipedsid": "100", "status": "submitted"});
+            let resp = call_service(&app, create(&auth, body).to_request()).await;
+            let body: Value = read_body_json(resp).await;
+            let id = body["application"]["id"].as_i64().unwrap();
+
+            let update = |status: &str| {
+                TestRequest::patch()
+                    .uri(&format!("/me/applications/{id}"))
+                    .insert_header((header::AUTHORIZATION, auth.as_str()))
+                    .set_json(json!({ "status": status }))
+                    .to_request()
+            };
+            let resp = call_service(&app, update("admitted")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let resp = call_service(&app, update("submitted")).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let resp = call_service(&app, update("enrolled")).await;
+            let body: Value = read_body_json(resp).await;
+            assert_eq!(body["application"]["status"], "enrolled");
+        });
+    }
+}
@@ -1,184 +1,137 @@
 // Routes under the /auth path
 
 use actix_web::{get, post, web, HttpResponse};
-use base64::{engine::general_purpose, Engine};
 use entities::user::{self, Entity as User};
 use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    api_error::ApiError,
     app_state::AppState,
+    auth_user::AuthenticatedUser,
+    google_auth,
     jwt::{self, AccessTokenClaims},
+    refresh_token,
 };
 
 #[derive(Deserialize)]
 pub struct GoogleLoginReqBody {
     gid_token: String,
+    device_label: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct GoogleLoginRespBody<'a> {
-    access_token: Option<&'a str>,
-    msg: Option<&'a str>,
-}
-
-impl GoogleLoginRespBody<'_> {
-    pub fn access_token<'a>(token: &'a str) -> GoogleLoginRespBody<'a> {
-        GoogleLoginRespBody {
-            access_token: Some(token),
-            msg: None,
-        }
-    }
-
-    pub fn msg<'a>(msg: &'a str) -> GoogleLoginRespBody<'a> {
-        GoogleLoginRespBody {
-            access_token: None,
-            msg: Some(msg),
-        }
-    }
+    access_token: &'a str,
+    refresh_token: &'a str,
 }
 
 #[post("/auth/google-login")]
 pub async fn handle_google_login(
     body: web::Json<GoogleLoginReqBody>,
     state: web::Data<AppState>,
-) -> HttpResponse {
-    // First get the Google Id Token Payload
-    let encoded_gid_payload = match get_gid_payload(&body.gid_token) {
-        Some(payload) => payload,
-        None => {
-            return HttpResponse::Unauthorized().json(GoogleLoginRespBody::msg(
-                "Unable to get Google Id Token Payload",
-            ))
-        }
-    };
-    // Now, decode the payload
-    let gid_payload = match decode_gid_payload(&encoded_gid_payload) {
-        Some(payload) => payload,
-        None => {
-            return HttpResponse::Unauthorized().json(GoogleLoginRespBody::msg(
-                "Unable to deserialize Google Id Token Payload",
-            ))
-        }
-    };
-
-    // Once the payload has been parsed, we need to check for sufficient Google OAuth scopes.
-    // If the email is present, then the rest of them will also be.
-    if let None = gid_payload.email {
-        return HttpResponse::Unauthorized().json(GoogleLoginRespBody::msg(
-            "Insufficient Google OAuth scopes.",
-        ));
-    }
-
-    let user_email = gid_payload.email.unwrap();
+) -> Result<HttpResponse, ApiError> {
+    // The token must be signed by Google and issued to one of our clients before we trust any claims.
+    let gid_payload =
+        google_auth::verify_gid_token(&body.gid_token, &state.google_auth, &*state.cache).await?;
+
+    // Once the payload has been verified, we need to check for sufficient Google OAuth scopes.
+    // The email and profile scopes give us the email, name and picture.
+    let (user_email, user_name, user_picture) =
+        match (gid_payload.email, gid_payload.name, gid_payload.picture) {
+            (Some(email), Some(name), Some(picture)) => (email, name, picture),
+            _ => {
+                return Err(ApiError::Unauthorized {
+                    message: "Insufficient Google OAuth scopes.",
+                    reason: "insufficient_scopes",
+                })
+            }
+        };
 
     // Now, we must check if the user with the given email has already been registered.
-    let maybe_registed_user: Option<user::Model> = match User::find()
-        .filter(user::Column::Email.contains(&user_email))
+    let maybe_registed_user: Option<user::Model> = User::find()
+        .filter(user::Column::Email.eq(&user_email))
         .one(&state.db)
-        .await
-    {
-        Ok(data) => data,
-        Err(e) => {
-            eprintln!("error: {e}");
-            return HttpResponse::InternalServerError()
-                .json(GoogleLoginRespBody::msg("Unable to make database query"));
-        }
-    };
+        .await?;
 
-    if let Some(user) = maybe_registed_user {
-        match jwt::create_access_token(
-            &state.jwt_sec,
-            &state.jwt_iss,
-            &state.jwt_aud,
-            user.id,
-            &user_email,
-            &user.name,
-            &user.picture,
-        ) {
-            Some(token) => HttpResponse::Ok().json(GoogleLoginRespBody::access_token(&token)),
-            None => HttpResponse::InternalServerError()
-                .json(GoogleLoginRespBody::msg("Unable to generate access token")),
+    let user = match maybe_registed_user {
+        Some(user) => user,
+        None => {
+            // If the user does not exist, we must make an entity.
+            let new_user_model = user::ActiveModel {
+                email: ActiveValue::Set(user_email),
+                picture: ActiveValue::Set(user_picture),
+                name: ActiveValue::Set(user_name),
+                ..Default::default()
+            };
+
+            // Now, we can save the user.
+            new_user_model.insert(&state.db).await.map_err(|e| {
+                eprintln!("error: {e}");
+                ApiError::Internal("Unable to make database insertion")
+            })?
         }
-    } else {
-        // If the user does not exist, we must make an entity.
-        let new_user_model = user::ActiveModel {
-            email: ActiveValue::Set(user_email.clone()),
-            picture: ActiveValue::Set(gid_payload.picture.unwrap()),
-            name: ActiveValue::Set(gid_payload.name.unwrap()),
-            ..Default::default()
-        };
+    };
 
-        // Now, we can save the user.
-        let user: user::Model = match new_user_model.insert(&state.db).await {
-            Ok(model) => model,
-            Err(e) => {
+    // A Google sign-in starts a new refresh token family for this device.
+    let refresh_token =
+        refresh_token::issue_refresh_token(&state.db, user.id, body.device_label.clone())
+            .await
+            .map_err(|e| {
                 eprintln!("error: {e}");
-                return HttpResponse::InternalServerError().json(GoogleLoginRespBody::msg(
-                    "Unable to make database insertion",
-                ));
-            }
-        };
+                ApiError::Internal("Unable to generate refresh token")
+            })?;
 
-        match jwt::create_access_token(
-            &state.jwt_sec,
-            &state.jwt_iss,
-            &state.jwt_aud,
-            user.id,
-            &user_email,
-            &user.name,
-            &user.picture,
-        ) {
-            Some(token) => HttpResponse::Ok().json(GoogleLoginRespBody::access_token(&token)),
-            None => HttpResponse::InternalServerError()
-                .json(GoogleLoginRespBody::msg("Unable to generate access token")),
-        }
-    }
+    respond_with_tokens(&state, &user, &refresh_token)
 }
 
-fn get_gid_payload<'a>(gid_token: &'a str) -> Option<&'a str> {
-    let frags: Vec<&str> = gid_token.split('.').collect();
-    if frags.len() < 2 {
-        None
-    } else {
-        Some(frags[1])
-    }
+fn respond_with_tokens(
+    state: &AppState,
+    user: &user::Model,
+    refresh_token: &str,
+) -> Result<HttpResponse, ApiError> {
+    let access_token =
+        jwt::create_access_token(&state.jwt, user.id, &user.email, &user.name, &user.picture)
+            .ok_or(ApiError::Internal("Unable to generate access token"))?;
+    Ok(HttpResponse::Ok().json(GoogleLoginRespBody {
+        access_token: &access_token,
+        refresh_token,
+    }))
 }
 
-#[derive(Deserialize, std::fmt::Debug)]
-struct GoogleIdTokenPayload {
-    iss: String,
-    sub: String,
-    azp: String,
-    aud: String,
-    iat: u128,
-    exp: u128,
-
-    email: Option<String>,
-    email_verified: Option<bool>,
-    name: Option<String>,
-    picture: Option<String>,
-    given_name: Option<String>,
-    family_name: Option<String>,
-    locale: Option<String>,
+#[derive(Deserialize)]
+pub struct RefreshTokenReqBody {
+    refresh_token: String,
 }
 
-fn decode_gid_payload(gid_payload: &str) -> Option<GoogleIdTokenPayload> {
-    let decoded_bytes = match general_purpose::STANDARD_NO_PAD.decode(gid_payload) {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            eprintln!("error decoding gid payload: {e}");
-            return None;
-        }
-    };
+#[post("/auth/refresh")]
+pub async fn handle_refresh_token(
+    body: web::Json<RefreshTokenReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    // The presented token is consumed and replaced by a new one in the same family.
+    let (user, new_refresh_token) =
+        refresh_token::rotate_refresh_token(&state.db, &body.refresh_token).await?;
+    respond_with_tokens(&state, &user, &new_refresh_token)
+}
 
-    match serde_json::from_slice::<GoogleIdTokenPayload>(&decoded_bytes) {
-        Ok(val) => Some(val),
-        Err(e) => {
-            eprintln!("unable to deserialize payload: {e}");
-            None
-        }
-    }
+#[post("/auth/logout")]
+pub async fn handle_logout(
+    body: web::Json<RefreshTokenReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    refresh_token::revoke_refresh_token(&state.db, &body.refresh_token).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/auth/logout-all")]
+pub async fn handle_logout_all(
+    auth: AuthenticatedUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    // Ends the user's sessions on every device.
+    refresh_token::revoke_all_refresh_tokens(&state.db, auth.user.id).await?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 #[derive(Serialize)]
@@ -195,19 +148,23 @@ pub struct VerifyTokenReqQuery {
 pub async fn handle_verify_access_token(
     state: web::Data<AppState>,
     query: web::Query<VerifyTokenReqQuery>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     // The "token" query param must be there.
-    let access_token = match &query.token {
-        Some(token) => token,
-        None => {
-            return HttpResponse::Unauthorized().json(VerifyTokenResp { claims: None });
-        }
-    };
+    let access_token = query
+        .token
+        .as_ref()
+        .ok_or(ApiError::MissingToken("Missing access token"))?;
 
-    let decode_result =
-        jwt::decode_access_token(access_token, &state.jwt_sec, &state.jwt_iss, &state.jwt_aud);
+    let decode_result = jwt::decode_access_token(access_token, &state.jwt);
+
+    Ok(HttpResponse::Ok().json(VerifyTokenResp {
+        claims: decode_result.ok(),
+    }))
+}
 
+#[get("/auth/me")]
+pub async fn handle_get_me(auth: AuthenticatedUser) -> HttpResponse {
     HttpResponse::Ok().json(VerifyTokenResp {
-        claims: decode_result,
+        claims: Some(auth.claims),
     })
 }
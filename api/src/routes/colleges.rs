@@ -1,158 +1,265 @@
 // Routes under the /colleges path
 
-use std::{f64::consts::PI, time::Duration, str::FromStr};
+use std::{ops::RangeInclusive, sync::Arc, time::Duration};
 
 use actix_web::{get, web, HttpResponse};
 use awc::Client;
-use bb8_redis::{bb8, redis::cmd, RedisConnectionManager};
+use base64::{engine::general_purpose, Engine};
 use serde::{Deserialize, Serialize};
-use tl::ParserOptions;
+use serde_json::{Map, Value};
 
 use crate::{
+    api_error::ApiError,
     app_state::AppState,
+    catalog,
+    catalog_snapshot::CatalogSnapshot,
+    college_filter::{CollegeFilter, CollegeFilterQuery, FieldError},
+    indexes::{
+        autocomplete::MAX_SUGGESTIONS,
+        spatial::{calculate_distance_between_coords, DistanceUnit, MAX_DISTANCE_MILES},
+    },
+    scraper::{
+        admissions::{parse_admissions, AdmissionStats},
+        college_navigator::fetch_college_page,
+        ScrapeError,
+    },
     structures::{CollegeCoord, CollegeStruct},
 };
 
-const COLLEGE_LIST_EXP: usize = 24 * 60 * 60;
+const COLLEGE_LIST_EXP: Duration = Duration::from_secs(24 * 60 * 60);
+// Bumped whenever GetSingleCollegeResp changes shape, so stale entries are not misread.
+const COLLEGE_DATA_KEY_PREFIX: &str = "COLLEGE_DATA_V2_";
+
+const DEFAULT_PAGE_LIMIT: usize = 50;
+const MAX_PAGE_LIMIT: usize = 500;
+const MAX_NEAREST: usize = 100;
+const COLLEGE_FIELDS: [&str; 12] = [
+    "ipedsid",
+    "name",
+    "address",
+    "city",
+    "state",
+    "zip",
+    "geo_point_2d",
+    "naics_desc",
+    "acceptance_rate",
+    "sat_avg",
+    "act_avg",
+    "distance",
+];
 
 #[derive(Serialize)]
 pub struct CollegeListResp {
-    colleges: Option<Vec<CollegeStruct>>,
+    colleges: Vec<Map<String, Value>>,
+    total: usize,
+    next_cursor: Option<String>,
 }
 
-impl CollegeListResp {
-    pub fn from(colleges: Vec<CollegeStruct>) -> Self {
-        Self {
-            colleges: Some(colleges),
-        }
-    }
+/// Paging, ordering and projection shared by the college list endpoints.
+/// Without `limit` or `cursor` the whole list is returned, as before.
+#[derive(Deserialize)]
+pub struct CollegeListQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub fields: Option<String>,
+}
 
-    pub fn empty() -> Self {
-        Self { colleges: None }
-    }
+enum CollegeSort {
+    Name,
+    State,
+    Distance,
 }
 
-#[get("/colleges/list-all")]
-pub async fn hande_list_all_colleges(state: web::Data<AppState>) -> HttpResponse {
-    match get_all_colleges(&state.redis_pool).await {
-        Some(colleges) => HttpResponse::Ok().json(CollegeListResp::from(colleges)),
-        None => HttpResponse::InternalServerError().json(CollegeListResp::empty()),
-    }
+/// A college that passed the filters, with its distance from the starting point if one was
+/// given.
+pub struct ListedCollege<'a> {
+    pub college: &'a CollegeStruct,
+    pub distance: Option<f64>,
 }
 
-#[derive(Deserialize)]
-pub struct CollegeAPIResp {
-    total_count: usize,
-    results: Vec<CollegeStruct>,
-}
-
-async fn get_all_colleges(
-    redis_pool: &bb8::Pool<RedisConnectionManager>,
-) -> Option<Vec<CollegeStruct>> {
-    // First, we will try to hit the cache.
-    // If the cache misses due to any reason, we will default to retrieving the data from the API.
-    if let Ok(mut redis_conn) = redis_pool.get().await {
-        if let Ok(redis_cache_attempt) = cmd("GET")
-            .arg("@COLLEGE_LIST/CACHE")
-            .query_async::<_, Option<String>>(&mut *redis_conn)
-            .await
-        {
-            // If the value is present, we can deserialize and return.
-            if let Some(cached) = redis_cache_attempt {
-                if let Ok(deserialized_cache) = serde_json::from_str::<Vec<CollegeStruct>>(&cached)
-                {
-                    return Some(deserialized_cache);
-                } else {
-                    eprintln!("unable to deserialize cache");
-                }
-            }
-        } else {
-            eprintln!("unable to make redis query");
-        }
-    } else {
-        eprintln!("unable to get redis connection from pool");
-    }
+// Cursors are opaque to clients; today they just wrap the offset of the next page.
+fn encode_cursor(offset: usize) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(format!("o:{offset}"))
+}
 
-    let mut all_colleges: Vec<CollegeStruct> = Vec::new();
-    let awc_client = Client::default();
+fn decode_cursor(cursor: &str) -> Option<usize> {
+    let decoded = general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    std::str::from_utf8(&decoded)
+        .ok()?
+        .strip_prefix("o:")?
+        .parse()
+        .ok()
+}
 
-    // The first request will give us the total number of colleges.
-    let (total_count, mut first_list)= match awc_client.get("https://public.opendatasoft.com/api/explore/v2.1/catalog/datasets/us-colleges-and-universities/records?where=naics_desc%20like%20%22COLLEGES%2C%20UNIVERSITIES%2C%20AND%20PROFESSIONAL%20SCHOOLS%22&limit=100&offset=0").send().await {
-        Ok(mut response) => {
-            let response_body = match response.body().await {
-                Ok(body) => body,
-                Err(e) => {
-                    eprintln!("error: {e}");
-                    return None;
-                }
-            };
-            match serde_json::from_slice::<CollegeAPIResp>(&response_body) {
-                Ok(deserialized_resp) => (deserialized_resp.total_count, deserialized_resp.results),
-                Err(e) => {
-                    eprintln!("error: {e}");
-                    return None;
-                }
+/// The paging, ordering and projection asked for, checked before any filtering so the same
+/// query always gets the same errors.
+struct ListParams<'q> {
+    sort: Option<CollegeSort>,
+    fields: Option<Vec<&'q str>>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl<'q> ListParams<'q> {
+    /// `has_origin` tells whether distances will be known, which sorting by them needs.
+    fn parse(query: &'q CollegeListQuery, has_origin: bool) -> Result<Self, Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let sort = match query.sort.as_deref() {
+            None => None,
+            Some("name") => Some(CollegeSort::Name),
+            Some("state") => Some(CollegeSort::State),
+            Some("distance") if has_origin => Some(CollegeSort::Distance),
+            Some("distance") => {
+                errors.push(FieldError::new(
+                    "sort",
+                    "distance requires a starting_point or lat and lon",
+                ));
+                None
             }
+            Some(_) => {
+                errors.push(FieldError::new(
+                    "sort",
+                    "must be one of name, state or distance",
+                ));
+                None
+            }
+        };
+
+        let fields: Option<Vec<&str>> = query.fields.as_deref().map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .collect()
+        });
+        if let Some(unknown) = fields
+            .iter()
+            .flatten()
+            .find(|field| !COLLEGE_FIELDS.contains(field))
+        {
+            errors.push(FieldError::new(
+                "fields",
+                format!("unknown field \"{unknown}\""),
+            ));
         }
-        Err(e) => {
-            eprintln!("error: {e}");
-            return None;
-        }
-    };
 
-    // Now, we must append the result to the main vector.
-    all_colleges.reserve(total_count);
-    all_colleges.append(&mut first_list);
-
-    let mut offset: i32 = 100;
-
-    // Now, we need to keep going until we have all of them.
-    while all_colleges.len() < total_count {
-        let mut resp = match awc_client.get(format!("https://public.opendatasoft.com/api/explore/v2.1/catalog/datasets/us-colleges-and-universities/records?where=naics_desc%20like%20%22COLLEGES%2C%20UNIVERSITIES%2C%20AND%20PROFESSIONAL%20SCHOOLS%22&limit=100&offset={offset}")).send().await {
-            Ok(mut response) => {
-                let response_body = match response.body().await {
-                    Ok(body) => body,
-                    Err(e) => {
-                        eprintln!("error: {e}");
-                        return None;
-                    }
-                };
-                match serde_json::from_slice::<CollegeAPIResp>(&response_body) {
-                    Ok(deserialized_resp) => deserialized_resp,
-                    Err(e) => {
-                        eprintln!("error: {e}");
-                        return None;
-                    }
-                }
+        let offset = match query.cursor.as_deref().map(decode_cursor) {
+            None => 0,
+            Some(Some(offset)) => offset,
+            Some(None) => {
+                errors.push(FieldError::new("cursor", "is invalid"));
+                0
             }
-            Err(e) => {
-                eprintln!("error: {e}");
-                return None;
+        };
+        let limit = match query.limit {
+            Some(0) => {
+                errors.push(FieldError::new("limit", "must be positive"));
+                None
             }
+            Some(limit) => Some(limit.min(MAX_PAGE_LIMIT)),
+            None if query.cursor.is_some() => Some(DEFAULT_PAGE_LIMIT),
+            None => None,
         };
 
-        all_colleges.append(&mut resp.results);
-        offset += 100;
-    }
-
-    // Once the colleges have been obtained, we can cache it for future use.
-    if let Ok(serialized_colleges) = serde_json::to_string(&all_colleges) {
-        if let Ok(mut redis_conn) = redis_pool.get().await {
-            let _cache_store_resp = cmd("SET")
-                .arg("@COLLEGE_LIST/CACHE")
-                .arg(serialized_colleges)
-                .arg("EX")
-                .arg(COLLEGE_LIST_EXP)
-                .query_async::<_, String>(&mut *redis_conn)
-                .await;
+        if errors.is_empty() {
+            Ok(Self {
+                sort,
+                fields,
+                offset,
+                limit,
+            })
         } else {
-            eprintln!("unable to get redis connection while serializing");
+            Err(errors)
         }
-    } else {
-        eprintln!("unable to serialize college data");
     }
+}
 
-    Some(all_colleges)
+/// Sorts, pages and projects the colleges into a list response.
+fn list_response(
+    mut colleges: Vec<ListedCollege<'_>>,
+    params: ListParams<'_>,
+    default_sort: CollegeSort,
+) -> HttpResponse {
+    let ListParams {
+        sort,
+        fields,
+        offset,
+        limit,
+    } = params;
+
+    match sort.unwrap_or(default_sort) {
+        CollegeSort::Name => colleges.sort_by(|a, b| a.college.name.cmp(&b.college.name)),
+        CollegeSort::State => colleges.sort_by(|a, b| {
+            (&a.college.state, &a.college.name).cmp(&(&b.college.state, &b.college.name))
+        }),
+        CollegeSort::Distance => colleges.sort_by(|a, b| {
+            a.distance
+                .unwrap_or_default()
+                .total_cmp(&b.distance.unwrap_or_default())
+        }),
+    }
+
+    let total = colleges.len();
+    let end = match limit {
+        Some(limit) => offset.saturating_add(limit).min(total),
+        None => total,
+    };
+    let next_cursor = (end < total).then(|| encode_cursor(end));
+
+    let page = colleges
+        .into_iter()
+        .skip(offset)
+        .take(end.saturating_sub(offset))
+        .map(|listed| {
+            let mut college = match serde_json::to_value(listed.college) {
+                Ok(Value::Object(college)) => college,
+                _ => Map::new(),
+            };
+            if let Some(distance) = listed.distance {
+                college.insert("distance".to_string(), Value::from(distance));
+            }
+            if let Some(fields) = &fields {
+                college.retain(|key, _| fields.contains(&key.as_str()));
+            }
+            college
+        })
+        .collect();
+
+    HttpResponse::Ok().json(CollegeListResp {
+        colleges: page,
+        total,
+        next_cursor,
+    })
+}
+
+#[get("/colleges/list-all")]
+pub async fn hande_list_all_colleges(
+    state: web::Data<AppState>,
+    list_query: web::Query<CollegeListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let params = ListParams::parse(&list_query, false).map_err(ApiError::InvalidParams)?;
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    Ok(list_response(
+        catalog
+            .colleges
+            .iter()
+            .map(|college| ListedCollege {
+                college,
+                distance: None,
+            })
+            .collect(),
+        params,
+        CollegeSort::Name,
+    ))
+}
+
+/// Gets the whole college catalog, with its indexes.
+pub async fn get_all_colleges(state: &AppState) -> Option<Arc<CatalogSnapshot>> {
+    state.catalog.get().await
 }
 
 #[derive(Deserialize)]
@@ -160,178 +267,413 @@ pub struct CollegeParamReqQuery {
     pub name: Option<String>,
     pub max_distance: Option<String>,
     pub starting_point: Option<String>,
+    // A known position, e.g. from the phone's GPS. Takes precedence over starting_point.
+    pub lat: Option<String>,
+    pub lon: Option<String>,
+    // "mi" (the default) or "km", for max_distance and the distances returned.
+    pub unit: Option<String>,
+    // Only the closest this many colleges, e.g. nearest=10.
+    pub nearest: Option<String>,
+    #[serde(flatten)]
+    pub filters: CollegeFilterQuery,
+}
+
+const LATITUDES: RangeInclusive<f64> = -90.0..=90.0;
+const LONGITUDES: RangeInclusive<f64> = -180.0..=180.0;
+
+fn parse_coordinate(value: &str, range: RangeInclusive<f64>) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|val| range.contains(val))
 }
 
 #[get("/colleges/with-params")]
 pub async fn handle_get_colleges_with_params(
     state: web::Data<AppState>,
     query: web::Query<CollegeParamReqQuery>,
-) -> HttpResponse {
-    // Now, we must get all the colleges.
-    let mut college_list = match get_all_colleges(&state.redis_pool).await {
-        Some(college_list) => college_list,
-        None => return HttpResponse::InternalServerError().json(CollegeListResp::empty()),
+    list_query: web::Query<CollegeListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    // Every parameter is validated up front so a bad one is reported instead of ignored.
+    let mut errors = Vec::new();
+    let filter = match CollegeFilter::from_query(&query.filters) {
+        Ok(filter) => Some(filter),
+        Err(mut filter_errors) => {
+            errors.append(&mut filter_errors);
+            None
+        }
     };
-
-    // First, if a name is present, we must filter through the college list.
-    if let Some(name_fragment) = &query.name {
-        let college_list_iter = college_list.into_iter();
-        let filtered = college_list_iter.filter(|college| {
-            college
-                .name
-                .to_lowercase()
-                .contains(&name_fragment.to_lowercase())
-        });
-        college_list = filtered.collect();
+    let unit = match query.unit.as_deref().map(DistanceUnit::parse) {
+        None => DistanceUnit::Miles,
+        Some(Some(unit)) => unit,
+        Some(None) => {
+            errors.push(FieldError::new("unit", "must be mi or km"));
+            DistanceUnit::Miles
+        }
+    };
+    let lat = query
+        .lat
+        .as_deref()
+        .map(|lat| parse_coordinate(lat, LATITUDES));
+    if lat == Some(None) {
+        errors.push(FieldError::new(
+            "lat",
+            "must be a number between -90 and 90",
+        ));
     }
+    let lon = query
+        .lon
+        .as_deref()
+        .map(|lon| parse_coordinate(lon, LONGITUDES));
+    if lon == Some(None) {
+        errors.push(FieldError::new(
+            "lon",
+            "must be a number between -180 and 180",
+        ));
+    }
+    let coords = match (lat, lon) {
+        (Some(Some(lat)), Some(Some(lon))) => Some(CollegeCoord { lon, lat }),
+        (Some(_), None) => {
+            errors.push(FieldError::new("lon", "is required with lat"));
+            None
+        }
+        (None, Some(_)) => {
+            errors.push(FieldError::new("lat", "is required with lon"));
+            None
+        }
+        _ => None,
+    };
+    let has_origin = query.starting_point.is_some() || query.lat.is_some() || query.lon.is_some();
+
+    let max_distance = match query.max_distance.as_deref().map(str::parse::<f64>) {
+        None => None,
+        // Nothing is further away than half way round the earth, so larger distances match
+        // everything.
+        Some(Ok(val)) if val.is_finite() && val >= 0.0 => {
+            Some(unit.to_miles(val).min(MAX_DISTANCE_MILES))
+        }
+        Some(_) => {
+            errors.push(FieldError::new(
+                "max_distance",
+                "must be a non-negative number",
+            ));
+            None
+        }
+    };
+    if max_distance.is_some() && !has_origin {
+        errors.push(FieldError::new(
+            "max_distance",
+            "requires a starting_point or lat and lon",
+        ));
+    }
+    let nearest = match query.nearest.as_deref().map(str::parse::<usize>) {
+        None => None,
+        Some(Ok(k)) if (1..=MAX_NEAREST).contains(&k) => Some(k),
+        Some(_) => {
+            errors.push(FieldError::new(
+                "nearest",
+                format!("must be a number between 1 and {MAX_NEAREST}"),
+            ));
+            None
+        }
+    };
+    if nearest.is_some() && !has_origin {
+        errors.push(FieldError::new(
+            "nearest",
+            "requires a starting_point or lat and lon",
+        ));
+    }
+    let list_params = match ListParams::parse(&list_query, has_origin) {
+        Ok(list_params) => Some(list_params),
+        Err(mut list_errors) => {
+            errors.append(&mut list_errors);
+            None
+        }
+    };
+    let (filter, list_params) = match (filter, list_params) {
+        (Some(filter), Some(list_params)) if errors.is_empty() => (filter, list_params),
+        _ => return Err(ApiError::InvalidParams(errors)),
+    };
 
-    // Now, we need to check for a within
-    if let Some(max_distance) = &query.max_distance {
-        if let Some(starting_point) = &query.starting_point {
-            let college_list_iter = college_list.into_iter();
-            let max_distance = match max_distance.parse::<f64>() {
-                Ok(val) => val,
-                Err(_) => return HttpResponse::BadRequest().json(CollegeListResp::empty()),
-            };
+    // Now, we must get all the colleges.
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    let college_list = &catalog.colleges;
+
+    let name_fragment = query.name.as_ref().map(|name| name.to_lowercase());
+    let keep = |college: &CollegeStruct| {
+        filter.matches(college)
+            && name_fragment
+                .as_ref()
+                .is_none_or(|name| college.name.to_lowercase().contains(name))
+    };
 
-            let starting_point_coords =
-                match get_geolocation_coords(starting_point, &state.pos_stack_key).await {
-                    Some(coords) => coords,
-                    None => {
-                        return HttpResponse::InternalServerError().json(CollegeListResp::empty())
-                    }
-                };
+    // Positions given directly skip geocoding.
+    let origin = match (coords, &query.starting_point) {
+        (Some(coords), _) => Some(coords),
+        (None, Some(starting_point)) => match state.geocoder.geocode(starting_point).await {
+            Ok(Some(coords)) => Some(coords),
+            Ok(None) => {
+                return Err(ApiError::InvalidParams(vec![FieldError::new(
+                    "starting_point",
+                    "location not found",
+                )]))
+            }
+            Err(_) => return Err(ApiError::Upstream("Unable to geocode starting_point")),
+        },
+        (None, None) => None,
+    };
 
-            let filtered = college_list_iter.filter(|college| {
-                calculate_distance_between_coords(&college.geo_point_2d, &starting_point_coords)
-                    < max_distance
-            });
-            college_list = filtered.collect();
+    // Distances are measured from the origin whenever one is given, so results can be
+    // sorted by them even without a max_distance.
+    let (matches, default_sort): (Vec<(usize, Option<f64>)>, CollegeSort) = match origin {
+        Some(starting_point_coords) => {
+            let spatial = &catalog.indexes.spatial;
+
+            let hits = match (nearest, max_distance) {
+                (Some(k), _) => spatial.nearest(&starting_point_coords, k, max_distance, |idx| {
+                    keep(&college_list[idx])
+                }),
+                (None, Some(max_distance)) => spatial
+                    .within(&starting_point_coords, max_distance)
+                    .into_iter()
+                    .filter(|(idx, _)| keep(&college_list[*idx]))
+                    .collect(),
+                (None, None) => college_list
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, college)| keep(college))
+                    .map(|(idx, college)| {
+                        (
+                            idx,
+                            calculate_distance_between_coords(
+                                &college.geo_point_2d,
+                                &starting_point_coords,
+                            ),
+                        )
+                    })
+                    .collect(),
+            };
+            let default_sort = if nearest.is_some() {
+                CollegeSort::Distance
+            } else {
+                CollegeSort::Name
+            };
+            (
+                hits.into_iter()
+                    .map(|(idx, distance)| (idx, Some(unit.convert_miles(distance))))
+                    .collect(),
+                default_sort,
+            )
         }
-    }
+        None => (
+            college_list
+                .iter()
+                .enumerate()
+                .filter(|(_, college)| keep(college))
+                .map(|(idx, _)| (idx, None))
+                .collect(),
+            CollegeSort::Name,
+        ),
+    };
 
-    HttpResponse::Ok().json(CollegeListResp::from(college_list))
+    let listed = matches
+        .into_iter()
+        .filter_map(|(idx, distance)| {
+            Some(ListedCollege {
+                college: college_list.get(idx)?,
+                distance,
+            })
+        })
+        .collect();
+
+    Ok(list_response(listed, list_params, default_sort))
 }
 
-const R_EARTH: f64 = 3956.0;
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
 
-fn calculate_distance_between_coords(p1: &CollegeCoord, p2: &CollegeCoord) -> f64 {
-    let lat1 = convert_to_radians(p1.lat);
-    let long1 = convert_to_radians(p1.lon);
-    let lat2 = convert_to_radians(p2.lat);
-    let long2 = convert_to_radians(p2.lon);
-
-    // Haversine formula
-    let dlong = long2 - long1;
-    let dlat = lat2 - lat1;
-
-    let half_dlat = dlat / 2.0;
-    let half_dlong = dlong / 2.0;
+#[derive(Deserialize)]
+pub struct CollegeSearchQuery {
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
 
-    let result = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlong.sin().powi(2);
-    let result = 2.0 * result.sqrt().asin();
+#[derive(Serialize)]
+pub struct CollegeSearchHit<'a> {
+    score: f64,
+    college: &'a CollegeStruct,
+}
 
-    result * R_EARTH
+#[derive(Serialize)]
+pub struct CollegeSearchResp<'a> {
+    results: Vec<CollegeSearchHit<'a>>,
 }
 
-const ONE_DEG_TO_RAD: f64 = PI / 180.0;
+#[get("/colleges/search")]
+pub async fn handle_search_colleges(
+    state: web::Data<AppState>,
+    query: web::Query<CollegeSearchQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let q = match query.q.as_deref().map(str::trim) {
+        Some(q) if !q.is_empty() => q,
+        _ => {
+            return Err(ApiError::InvalidParams(vec![FieldError::new(
+                "q",
+                "is required",
+            )]))
+        }
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
 
-#[inline(always)]
-fn convert_to_radians(angle: f64) -> f64 {
-    angle * ONE_DEG_TO_RAD
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+
+    let hits = catalog.indexes.search.search(q, limit);
+
+    // Hits point into the list the index was built from, best first.
+    let results = hits
+        .into_iter()
+        .filter_map(|hit| {
+            Some(CollegeSearchHit {
+                score: hit.score,
+                college: catalog.colleges.get(hit.college)?,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(CollegeSearchResp { results }))
 }
 
 #[derive(Deserialize)]
-pub struct PosStackResp {
-    pub data: Vec<PosStackCoord>,
+pub struct AutocompleteQuery {
+    pub prefix: Option<String>,
+    pub limit: Option<usize>,
 }
 
-#[derive(Deserialize)]
-pub struct PosStackCoord {
-    pub latitude: f64,
-    pub longitude: f64,
+#[derive(Serialize)]
+pub struct AutocompleteSuggestion {
+    ipedsid: String,
+    name: String,
+    city: String,
+    state: String,
 }
 
-async fn get_geolocation_coords(location: &str, position_stack_key: &str) -> Option<CollegeCoord> {
-    let awc_client = Client::default();
+#[derive(Serialize)]
+pub struct AutocompleteResp {
+    suggestions: Vec<AutocompleteSuggestion>,
+}
 
-    let query = [
-        ("access_key", position_stack_key),
-        ("query", location),
-        ("output", "json"),
-        ("limit", "1"),
-    ];
-
-    match awc_client
-        .get("http://api.positionstack.com/v1/forward")
-        .query(&query)
-        .unwrap()
-        .send()
+#[get("/colleges/autocomplete")]
+pub async fn handle_autocomplete_colleges(
+    state: web::Data<AppState>,
+    query: web::Query<AutocompleteQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let prefix = query.prefix.as_deref().unwrap_or_default();
+    let limit = query
+        .limit
+        .unwrap_or(MAX_SUGGESTIONS)
+        .clamp(1, MAX_SUGGESTIONS);
+
+    let catalog = get_all_colleges(&state)
         .await
-    {
-        Ok(mut resp) => {
-            let resp_body = match resp.body().await {
-                Ok(body) => body,
-                Err(e) => {
-                    eprintln!("error: {e}");
-                    return None;
-                }
-            };
-
-            match serde_json::from_slice::<PosStackResp>(&resp_body) {
-                Ok(val) => {
-                    let coord_obj = &val.data[0];
-                    Some(CollegeCoord {
-                        lon: coord_obj.longitude,
-                        lat: coord_obj.latitude,
-                    })
-                }
-                Err(e) => {
-                    eprintln!("error: {e}");
-                    None
-                }
-            }
-        }
-        Err(e) => {
-            eprintln!("error: {e}");
-            None
-        }
-    }
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+
+    let matches = catalog.indexes.autocomplete.complete(prefix, limit);
+    let suggestions = matches
+        .into_iter()
+        .filter_map(|idx| catalog.colleges.get(idx))
+        .map(|college| AutocompleteSuggestion {
+            ipedsid: college.ipedsid.clone(),
+            name: college.name.clone(),
+            city: college.city.clone(),
+            state: college.state.clone(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(AutocompleteResp { suggestions }))
 }
 
+/// The version of the college info schema served at /v2/college/info.
+const COLLEGE_INFO_VERSION: u8 = 2;
+
 #[derive(Serialize)]
-pub struct GetSingleCollegeRespWrapper<'a> {
-    college: Option<GetSingleCollegeResp>,
-    msg: Option<&'a str>,
+pub struct GetSingleCollegeRespWrapper<T> {
+    // Absent from v1 responses, which predate versioning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u8>,
+    college: T,
 }
 
-impl GetSingleCollegeRespWrapper<'_> {
-    pub fn from_msg<'a>(msg: &'a str) -> GetSingleCollegeRespWrapper<'a> {
+impl<T> GetSingleCollegeRespWrapper<T> {
+    pub fn from_college_data(college: T) -> Self {
         GetSingleCollegeRespWrapper {
-            msg: Some(msg),
-            college: None,
+            version: None,
+            college,
         }
     }
 
-    pub fn from_college_data<'a>(college: GetSingleCollegeResp) -> GetSingleCollegeRespWrapper<'a> {
-        GetSingleCollegeRespWrapper {
-            college: Some(college),
-            msg: None,
+    pub fn with_version(self, version: u8) -> Self {
+        Self {
+            version: Some(version),
+            ..self
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetSingleCollegeResp {
+    admissions_url: Option<String>,
+    apply_url: Option<String>,
+    finaid_url: Option<String>,
+    admission_info: CollegeAdmissionInfo,
+    pub application_reqs: Vec<String>,
+}
+
+/// Admission statistics. Anything the college does not report is null.
+#[derive(Deserialize, Serialize, Default)]
+pub struct CollegeAdmissionInfo {
+    total_applicants: Option<u32>,
+    total_male_applicants: Option<u32>,
+    total_female_applicants: Option<u32>,
+    // Percentages from 0 to 100.
+    total_percent_admitted: Option<f64>,
+    total_percent_males_admitted: Option<f64>,
+    total_percent_females_admitted: Option<f64>,
+    // The percent of admitted students who enrolled.
+    yield_percent: Option<f64>,
+    total_enrollment: Option<u32>,
+    undergraduate_enrollment: Option<u32>,
+    sat_ebrw_25th: Option<u32>,
+    sat_ebrw_50th: Option<u32>,
+    sat_ebrw_75th: Option<u32>,
+    sat_math_25th: Option<u32>,
+    sat_math_50th: Option<u32>,
+    sat_math_75th: Option<u32>,
+    act_composite_25th: Option<u32>,
+    act_composite_50th: Option<u32>,
+    act_composite_75th: Option<u32>,
+}
+
+/// The original college info, with every statistic as display text. Served at /college/info
+/// for clients that predate the typed schema.
+#[derive(Serialize)]
+pub struct GetSingleCollegeRespV1 {
     admissions_url: String,
     apply_url: String,
     finaid_url: String,
-    admission_info: CollegeAdmissionInfo,
+    admission_info: CollegeAdmissionInfoV1,
     application_reqs: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct CollegeAdmissionInfo {
+pub struct CollegeAdmissionInfoV1 {
     total_applicants: String,
     total_male_applicants: String,
     total_female_applicants: String,
@@ -343,6 +685,126 @@ pub struct CollegeAdmissionInfo {
     act_avg: String,
 }
 
+/// SAT section scores run from 200 to 800 and ACT composites from 1 to 36. Scores outside these
+/// ranges are misreads and are dropped rather than stored.
+const SAT_SECTION_SCORES: RangeInclusive<u32> = 200..=800;
+const ACT_COMPOSITE_SCORES: RangeInclusive<u32> = 1..=36;
+
+fn sat_section(score: Option<u32>) -> Option<u32> {
+    score.filter(|score| SAT_SECTION_SCORES.contains(score))
+}
+
+fn act_composite(score: Option<u32>) -> Option<u32> {
+    score.filter(|score| ACT_COMPOSITE_SCORES.contains(score))
+}
+
+/// Reads the leading number out of values like "45%" or "12,345".
+fn parse_stat(value: &str) -> Option<f64> {
+    let number: String = value
+        .trim()
+        .chars()
+        .filter(|c| *c != ',')
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    number.parse().ok()
+}
+
+impl CollegeAdmissionInfo {
+    /// The acceptance rate, combined median SAT and median ACT, where they are known.
+    fn metrics(&self) -> (Option<f64>, Option<i32>, Option<i32>) {
+        let sat_avg = match (self.sat_ebrw_50th, self.sat_math_50th) {
+            (Some(ebrw), Some(math)) => ebrw
+                .checked_add(math)
+                .and_then(|sat| i32::try_from(sat).ok()),
+            _ => None,
+        };
+        let act_avg = self
+            .act_composite_50th
+            .and_then(|act| i32::try_from(act).ok());
+        (self.total_percent_admitted, sat_avg, act_avg)
+    }
+}
+
+impl From<&AdmissionStats> for CollegeAdmissionInfo {
+    fn from(stats: &AdmissionStats) -> Self {
+        CollegeAdmissionInfo {
+            total_applicants: stats.applicants.total,
+            total_male_applicants: stats.applicants.men,
+            total_female_applicants: stats.applicants.women,
+            total_percent_admitted: stats.percent_admitted.total,
+            total_percent_males_admitted: stats.percent_admitted.men,
+            total_percent_females_admitted: stats.percent_admitted.women,
+            yield_percent: stats.percent_admitted_enrolled.total,
+            total_enrollment: None,
+            undergraduate_enrollment: None,
+            sat_ebrw_25th: sat_section(stats.sat_ebrw.p25),
+            sat_ebrw_50th: sat_section(stats.sat_ebrw.p50),
+            sat_ebrw_75th: sat_section(stats.sat_ebrw.p75),
+            sat_math_25th: sat_section(stats.sat_math.p25),
+            sat_math_50th: sat_section(stats.sat_math.p50),
+            sat_math_75th: sat_section(stats.sat_math.p75),
+            act_composite_25th: act_composite(stats.act_composite.p25),
+            act_composite_50th: act_composite(stats.act_composite.p50),
+            act_composite_75th: act_composite(stats.act_composite.p75),
+        }
+    }
+}
+
+impl From<&CollegeAdmissionInfoV1> for CollegeAdmissionInfo {
+    /// Reads the text the AI microservice gives back. Its SAT and ACT figures are medians.
+    fn from(info: &CollegeAdmissionInfoV1) -> Self {
+        let count = |value: &str| parse_stat(value).map(|value| value.round() as u32);
+        let percent = |value: &str| parse_stat(value).filter(|value| *value <= 100.0);
+
+        CollegeAdmissionInfo {
+            total_applicants: count(&info.total_applicants),
+            total_male_applicants: count(&info.total_male_applicants),
+            total_female_applicants: count(&info.total_female_applicants),
+            total_percent_admitted: percent(&info.total_percent_admitted),
+            total_percent_males_admitted: percent(&info.total_percent_males_admitted),
+            total_percent_females_admitted: percent(&info.total_percent_females_admitted),
+            sat_ebrw_50th: sat_section(count(&info.sat_avg_english)),
+            sat_math_50th: sat_section(count(&info.sat_avg_math)),
+            act_composite_50th: act_composite(count(&info.act_avg)),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&CollegeAdmissionInfo> for CollegeAdmissionInfoV1 {
+    /// Fills the fields the way the AI microservice does: medians for test scores and blanks
+    /// for anything not reported.
+    fn from(info: &CollegeAdmissionInfo) -> Self {
+        let count = |value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+        let percent =
+            |value: Option<f64>| value.map(|value| format!("{value}%")).unwrap_or_default();
+
+        CollegeAdmissionInfoV1 {
+            total_applicants: count(info.total_applicants),
+            total_male_applicants: count(info.total_male_applicants),
+            total_female_applicants: count(info.total_female_applicants),
+            total_percent_admitted: percent(info.total_percent_admitted),
+            total_percent_males_admitted: percent(info.total_percent_males_admitted),
+            total_percent_females_admitted: percent(info.total_percent_females_admitted),
+            sat_avg_english: count(info.sat_ebrw_50th),
+            sat_avg_math: count(info.sat_math_50th),
+            act_avg: count(info.act_composite_50th),
+        }
+    }
+}
+
+impl From<GetSingleCollegeResp> for GetSingleCollegeRespV1 {
+    fn from(college: GetSingleCollegeResp) -> Self {
+        GetSingleCollegeRespV1 {
+            admissions_url: college.admissions_url.unwrap_or_default(),
+            apply_url: college.apply_url.unwrap_or_default(),
+            finaid_url: college.finaid_url.unwrap_or_default(),
+            admission_info: CollegeAdmissionInfoV1::from(&college.admission_info),
+            application_reqs: college.application_reqs,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct GetSingleCollegeQuery {
     pub name: String,
@@ -353,175 +815,155 @@ pub async fn handle_get_single_college_info(
     path: web::Path<String>,
     query: web::Query<GetSingleCollegeQuery>,
     data: web::Data<AppState>,
-) -> HttpResponse {
-    // If the cache is missed, we must first get the data.
-    let mut redis_conn = data.redis_pool.get().await.unwrap();
-    let redis_resp: Option<String> = cmd("GET")
-        .arg(format!("COLLEGE_DATA_{}", path))
-        .query_async(&mut *redis_conn)
-        .await
-        .unwrap();
+) -> Result<HttpResponse, ApiError> {
+    let college = get_single_college_info(&path, &query.name, &data).await?;
+    Ok(
+        HttpResponse::Ok().json(GetSingleCollegeRespWrapper::from_college_data(
+            GetSingleCollegeRespV1::from(college),
+        )),
+    )
+}
 
-    if let Some(resp) = redis_resp {
-        let parsed = serde_json::from_str::<GetSingleCollegeResp>(&resp).unwrap();
-        return HttpResponse::Ok().json(GetSingleCollegeRespWrapper::from_college_data(parsed));
-    }
+#[get("/v2/college/info/{ipedsid}")]
+pub async fn handle_get_single_college_info_v2(
+    path: web::Path<String>,
+    query: web::Query<GetSingleCollegeQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let college = get_single_college_info(&path, &query.name, &data).await?;
+    Ok(HttpResponse::Ok().json(
+        GetSingleCollegeRespWrapper::from_college_data(college).with_version(COLLEGE_INFO_VERSION),
+    ))
+}
 
-    let awc_client = Client::default();
-    let req_query = [("id", path.as_str())];
-    let resp_contents = match awc_client
-        .get("https://nces.ed.gov/collegenavigator")
-        .query(&req_query)
-        .unwrap()
-        .send()
+/// Asks the AI microservice to read the statistics out of the admissions section, for pages the
+/// parser cannot make sense of.
+async fn get_llm_admission_info(
+    awc_client: &Client,
+    admissions_html: &str,
+) -> Result<CollegeAdmissionInfo, ApiError> {
+    let admissions_body_req = serde_json::json!({
+        "input": admissions_html.replace('"', "\\\""),
+    });
+    let mut get_admissions_data_request = awc_client
+        .post("http://localhost:8001/get-application-statistics")
+        .timeout(Duration::from_secs(30))
+        .send_json(&admissions_body_req)
         .await
-    {
-        Ok(mut resp) => match resp.body().await {
-            Ok(contents) => contents,
-            Err(e) => {
-                eprintln!("error: {e}");
-                return HttpResponse::InternalServerError().json(
-                    GetSingleCollegeRespWrapper::from_msg("Unable to parse html response"),
-                );
-            }
-        },
-        Err(e) => {
+        .map_err(|e| {
             eprintln!("error: {e}");
-            return HttpResponse::InternalServerError().json(
-                GetSingleCollegeRespWrapper::from_msg("Unable to fetch html data"),
-            );
-        }
-    };
+            ApiError::Upstream("Unable to get admission statistics")
+        })?;
 
-    let dom = match tl::parse(
-        unsafe { std::str::from_utf8_unchecked(&resp_contents) },
-        ParserOptions::default(),
-    ) {
-        Ok(dom) => dom,
+    let get_admissions_response_body = get_admissions_data_request.body().await.map_err(|e| {
+        eprintln!("error: {e}");
+        ApiError::Upstream("Unable to get admission statistics")
+    })?;
+
+    match serde_json::from_slice::<CollegeAdmissionInfoV1>(&get_admissions_response_body) {
+        Ok(info) => Ok(CollegeAdmissionInfo::from(&info)),
         Err(e) => {
             eprintln!("error: {e}");
-            return HttpResponse::InternalServerError().json(
-                GetSingleCollegeRespWrapper::from_msg("Unable to parse html data"),
-            );
+            Err(ApiError::Upstream("Unable to parse admission statistics"))
         }
-    };
+    }
+}
 
-    let dom_parser = dom.parser();
+/// Reads a cached value. An unreachable cache is only logged and treated as a miss.
+async fn cache_get(data: &AppState, key: &str) -> Option<String> {
+    data.cache
+        .get(key)
+        .await
+        .map_err(|e| eprintln!("error: {e}"))
+        .ok()?
+}
+
+/// Caches a value for `COLLEGE_LIST_EXP`. Failures are only logged.
+async fn cache_set(data: &AppState, key: &str, value: &str) {
+    if let Err(e) = data.cache.set(key, value, COLLEGE_LIST_EXP).await {
+        eprintln!("error: {e}");
+    }
+}
 
-    let mut general_info_handle = match dom
-        .query_selector("div#divctl00_cphCollegeNavBody_ucInstitutionMain_dtpGeneral")
+/// Gets the College Navigator links, admission statistics and application requirements for a
+/// college, from the cache when possible.
+pub async fn get_single_college_info(
+    ipedsid: &str,
+    name: &str,
+    data: &AppState,
+) -> Result<GetSingleCollegeResp, ApiError> {
+    let cache_key = format!("{COLLEGE_DATA_KEY_PREFIX}{ipedsid}");
+    if let Some(Ok(parsed)) = cache_get(data, &cache_key)
+        .await
+        .map(|resp| serde_json::from_str::<GetSingleCollegeResp>(&resp))
     {
-        Some(el) => el,
-        None => {
-            return HttpResponse::InternalServerError().json(GetSingleCollegeRespWrapper::from_msg(
-                "Unable to get general info handle from html data",
-            ))
-        }
-    };
+        return Ok(parsed);
+    }
 
-    let children = general_info_handle
-        .next()
-        .unwrap()
-        .get(dom_parser)
-        .unwrap()
-        .children()
-        .unwrap()
-        .all(dom_parser);
-    //let (admissions_url, apply_url, finaid_url) = get_general_info_urls(general_info_handle, dom_parser);
-    let admissions_url = children[9].inner_html(dom_parser).to_string();
-    let apply_url = children[16].inner_html(dom_parser).to_string();
-    let finaid_url = children[24].inner_html(dom_parser).to_string();
-
-    let finaid_el_handle = match dom.get_element_by_id("finaid") {
-        Some(el) => el,
-        None => {
-            return HttpResponse::InternalServerError().json(GetSingleCollegeRespWrapper::from_msg(
-                "Unable to get finaid handle from html data",
-            ))
-        }
+    // If the cache is missed, we must first get the data.
+    let page = fetch_college_page(ipedsid).await?;
+    // Open admission colleges have no admissions section, and so no statistics.
+    let admission_stats = match &page.admissions_html {
+        Some(admissions_html) => parse_admissions(admissions_html),
+        None => Some(AdmissionStats::default()),
     };
 
-    let finaid_el = match finaid_el_handle.get(dom_parser) {
-        Some(el) => el,
-        None => {
-            return HttpResponse::InternalServerError().json(GetSingleCollegeRespWrapper::from_msg(
-                "Unable to get finaid from html data",
-            ))
+    let awc_client = Client::default();
+    let mut college_admission_info = match (admission_stats, &page.admissions_html) {
+        (Some(stats), _) => CollegeAdmissionInfo::from(&stats),
+        (None, Some(admissions_html)) if data.admissions_llm_fallback => {
+            get_llm_admission_info(&awc_client, admissions_html).await?
         }
+        (None, _) => return Err(ScrapeError::UnreadableAdmissions.into()),
     };
+    college_admission_info.total_enrollment = page.total_enrollment;
+    college_admission_info.undergraduate_enrollment = page.undergraduate_enrollment;
 
-    let admissions_el_handle = match dom
-        .get_element_by_id("divctl00_cphCollegeNavBody_ucInstitutionMain_ctl04")
+    // Keep the numeric metrics on the catalog row so lists can be filtered by them.
+    let (acceptance_rate, sat_avg, act_avg) = college_admission_info.metrics();
+    if let Err(e) =
+        catalog::update_admission_metrics(&data.db, ipedsid, acceptance_rate, sat_avg, act_avg)
+            .await
     {
-        Some(el) => el,
-        None => {
-            return HttpResponse::InternalServerError().json(GetSingleCollegeRespWrapper::from_msg(
-                "Unable to get applications handle from html data",
-            ))
-        }
-    };
-
-    let applications_el = match admissions_el_handle.get(dom_parser) {
-        Some(el) => el,
-        None => {
-            return HttpResponse::InternalServerError().json(GetSingleCollegeRespWrapper::from_msg(
-                "Unable to get applications from html data",
-            ))
-        }
-    };
-
-    let admissions_html = applications_el
-        .inner_html(dom_parser)
-        .to_string()
-        .replace("\"", "\\\"");
-    let admissions_body_req = serde_json::json!({
-        "input": admissions_html,
-    });
-    let mut get_admissions_data_request = awc_client
-        .post("http://localhost:8001/get-application-statistics")
-        .timeout(Duration::from_secs(30))
-        .send_json(&admissions_body_req)
-        .await
-        .unwrap();
-
-    let get_admissions_response_body = get_admissions_data_request.body().await.unwrap();
-
-    let college_admission_info =
-        serde_json::from_slice::<CollegeAdmissionInfo>(&get_admissions_response_body).unwrap();
+        eprintln!("error: {e}");
+    }
 
     let reqs_body_req = serde_json::json!({
-        "name": query.name
+        "name": name
     });
 
+    let requirements_unavailable = |e: &dyn std::fmt::Display| {
+        eprintln!("error: {e}");
+        ApiError::Upstream("Unable to get application requirements")
+    };
     let mut get_req_data_req = awc_client
         .post("http://localhost:8001/get-application-requirements")
         .timeout(Duration::from_secs(30))
         .send_json(&reqs_body_req)
         .await
-        .unwrap();
+        .map_err(|e| requirements_unavailable(&e))?;
 
-    let get_req_data_body = get_req_data_req.body().await.unwrap();
+    let get_req_data_body = get_req_data_req
+        .body()
+        .await
+        .map_err(|e| requirements_unavailable(&e))?;
 
-    let college_reqs = serde_json::from_slice::<Vec<String>>(&get_req_data_body).unwrap();
+    let college_reqs = serde_json::from_slice::<Vec<String>>(&get_req_data_body)
+        .map_err(|e| requirements_unavailable(&e))?;
 
     let resp = GetSingleCollegeResp {
-        admissions_url,
-        apply_url,
-        finaid_url,
+        admissions_url: page.admissions_url,
+        apply_url: page.apply_url,
+        finaid_url: page.finaid_url,
         admission_info: college_admission_info,
         application_reqs: college_reqs,
     };
 
     // Cache it.
-    let _result: Option<String> = cmd("SET")
-        .arg(format!("COLLEGE_DATA_{}", path))
-        .arg(serde_json::to_string(&resp).unwrap())
-        .arg("EX")
-        .arg(COLLEGE_LIST_EXP)
-        .query_async(&mut *redis_conn)
-        .await
-        .unwrap();
-    HttpResponse::Ok().json(GetSingleCollegeRespWrapper::from_college_data(resp))
+    if let Ok(serialized) = serde_json::to_string(&resp) {
+        cache_set(data, &cache_key, &serialized).await;
+    }
+    Ok(resp)
 }
 
 #[derive(Deserialize, Serialize)]
@@ -533,43 +975,378 @@ pub struct HowReviewedResp {
 pub async fn handle_how_reviewed_route(
     query: web::Query<GetSingleCollegeQuery>,
     data: web::Data<AppState>,
-) -> HttpResponse {
-    let mut redis_conn = data.redis_pool.get().await.unwrap();
-    let redis_resp: Option<String> = cmd("GET")
-        .arg(format!("COLLEGE_REVIEWED_NAME_{}", query.name))
-        .query_async(&mut *redis_conn)
+) -> Result<HttpResponse, ApiError> {
+    let cache_key = format!("COLLEGE_REVIEWED_NAME_{}", query.name);
+    if let Some(Ok(parsed)) = cache_get(&data, &cache_key)
         .await
-        .unwrap();
-
-    if let Some(resp) = redis_resp {
-        let parsed = serde_json::from_str::<HowReviewedResp>(&resp).unwrap();
-        return HttpResponse::Ok().json(parsed);
+        .map(|resp| serde_json::from_str::<HowReviewedResp>(&resp))
+    {
+        return Ok(HttpResponse::Ok().json(parsed));
     }
 
-    let mut how_reviewed_req = serde_json::json!({
+    let how_reviewed_req = serde_json::json!({
         "name": query.name
     });
 
-    let how_reviewed_req = Client::default()
+    let how_reviewed_unavailable = |e: &dyn std::fmt::Display| {
+        eprintln!("error: {e}");
+        ApiError::Upstream("Unable to get how applications are reviewed")
+    };
+    let how_reviewed_body = Client::default()
         .post("http://localhost:8001/get-how-reviewed")
         .timeout(Duration::from_secs(30))
         .send_json(&how_reviewed_req)
         .await
-        .unwrap()
+        .map_err(|e| how_reviewed_unavailable(&e))?
         .body()
         .await
-        .unwrap();
+        .map_err(|e| how_reviewed_unavailable(&e))?;
 
-        let parsed_resp = HowReviewedResp {how_reviewed: String::from_str(unsafe {std::str::from_utf8_unchecked(&how_reviewed_req)}).unwrap()};
+    let parsed_resp = HowReviewedResp {
+        how_reviewed: String::from_utf8(how_reviewed_body.to_vec())
+            .map_err(|e| how_reviewed_unavailable(&e))?,
+    };
 
-    let _result: Option<String> = cmd("SET")
-        .arg(format!("COLLEGE_REVIEWED_NAME_{}", query.name))
-        .arg(serde_json::to_string(&parsed_resp).unwrap())
-        .arg("EX")
-        .arg(COLLEGE_LIST_EXP)
-        .query_async(&mut *redis_conn)
-        .await
-        .unwrap();
+    if let Ok(serialized) = serde_json::to_string(&parsed_resp) {
+        cache_set(&data, &cache_key, &serialized).await;
+    }
+
+    Ok(HttpResponse::Ok().json(parsed_resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::admissions::{Breakdown, Percentiles};
+
+    fn v1(sat_english: &str, sat_math: &str, act: &str) -> CollegeAdmissionInfoV1 {
+        CollegeAdmissionInfoV1 {
+            total_applicants: "12,345".to_string(),
+            total_male_applicants: "6,000".to_string(),
+            total_female_applicants: "6,345".to_string(),
+            total_percent_admitted: "45%".to_string(),
+            total_percent_males_admitted: "140%".to_string(),
+            total_percent_females_admitted: String::new(),
+            sat_avg_english: sat_english.to_string(),
+            sat_avg_math: sat_math.to_string(),
+            act_avg: act.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_v1_display_text() {
+        let info = CollegeAdmissionInfo::from(&v1("650", "700", "31"));
+        assert_eq!(info.total_applicants, Some(12_345));
+        assert_eq!(info.total_percent_admitted, Some(45.0));
+        // Percentages over 100 and blanks are not figures.
+        assert_eq!(info.total_percent_males_admitted, None);
+        assert_eq!(info.total_percent_females_admitted, None);
+        assert_eq!(info.metrics(), (Some(45.0), Some(1350), Some(31)));
+    }
+
+    #[test]
+    fn drops_test_scores_out_of_range() {
+        let info = CollegeAdmissionInfo::from(&v1("1450", "190", "40"));
+        assert_eq!(
+            (
+                info.sat_ebrw_50th,
+                info.sat_math_50th,
+                info.act_composite_50th
+            ),
+            (None, None, None)
+        );
+        assert_eq!(info.metrics(), (Some(45.0), None, None));
+
+        let info = CollegeAdmissionInfo::from(&v1("800", "200", "1"));
+        assert_eq!(info.metrics().1, Some(1000));
+        assert_eq!(info.metrics().2, Some(1));
+
+        let stats = AdmissionStats {
+            sat_ebrw: Percentiles {
+                p25: Some(199),
+                p50: Some(600),
+                p75: Some(801),
+            },
+            act_composite: Percentiles {
+                p25: Some(0),
+                p50: Some(u32::MAX),
+                p75: Some(36),
+            },
+            ..Default::default()
+        };
+        let info = CollegeAdmissionInfo::from(&stats);
+        assert_eq!(
+            (info.sat_ebrw_25th, info.sat_ebrw_50th, info.sat_ebrw_75th),
+            (None, Some(600), None)
+        );
+        assert_eq!(
+            (
+                info.act_composite_25th,
+                info.act_composite_50th,
+                info.act_composite_75th
+            ),
+            (None, None, Some(36))
+        );
+    }
+
+    #[test]
+    fn metrics_do_not_overflow() {
+        let info = CollegeAdmissionInfo {
+            sat_ebrw_50th: Some(u32::MAX),
+            sat_math_50th: Some(1),
+            act_composite_50th: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert_eq!(info.metrics(), (None, None, None));
+    }
+
+    #[test]
+    fn converts_to_v1_display_text() {
+        let info = CollegeAdmissionInfo::from(&AdmissionStats {
+            applicants: Breakdown {
+                total: Some(1200),
+                men: None,
+                women: Some(700),
+            },
+            percent_admitted: Breakdown {
+                total: Some(12.5),
+                ..Default::default()
+            },
+            sat_math: Percentiles {
+                p25: Some(680),
+                p50: Some(720),
+                p75: Some(780),
+            },
+            ..Default::default()
+        });
+        let v1 = CollegeAdmissionInfoV1::from(&info);
+
+        assert_eq!(v1.total_applicants, "1200");
+        assert_eq!(v1.total_male_applicants, "");
+        assert_eq!(v1.total_female_applicants, "700");
+        assert_eq!(v1.total_percent_admitted, "12.5%");
+        assert_eq!(v1.sat_avg_math, "720");
+        assert_eq!(v1.sat_avg_english, "");
+        assert_eq!(v1.act_avg, "");
+
+        // And back again.
+        let info = CollegeAdmissionInfo::from(&v1);
+        assert_eq!(info.total_applicants, Some(1200));
+        assert_eq!(info.total_percent_admitted, Some(12.5));
+        assert_eq!(info.sat_math_50th, Some(720));
+    }
+
+    mod lists {
+        use actix_web::{
+            http::StatusCode,
+            rt::System,
+            test::{call_service, init_service, read_body_json, TestRequest},
+            App,
+        };
+
+        use super::*;
+        use crate::app_state::tests::{test_college, test_state};
+
+        /// The status and body of a GET to `uri` on the college list routes.
+        async fn get(uri: &str) -> (StatusCode, Value) {
+            let state = test_state(vec![
+                test_college("100", "Charlie College", "MA", 42.36, -71.06),
+                test_college("200", "Alpha University", "NY", 40.71, -74.01),
+                test_college("300", "Bravo Institute", "CA", 34.05, -118.24),
+            ])
+            .await;
+            let app = init_service(
+                App::new()
+                    .app_data(web::Data::new(state))
+                    .service(hande_list_all_colleges)
+                    .service(handle_get_colleges_with_params),
+            )
+            .await;
+            let resp = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            let status = resp.status();
+            (status, read_body_json(resp).await)
+        }
+
+        fn names(body: &Value) -> Vec<&str> {
+            body["colleges"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|college| college["name"].as_str().unwrap())
+                .collect()
+        }
 
-    HttpResponse::Ok().json(parsed_resp)
+        fn error_fields(body: &Value) -> Vec<&str> {
+            body["details"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|error| error["field"].as_str().unwrap())
+                .collect()
+        }
+
+        #[test]
+        fn cursors_round_trip() {
+            for offset in [0, 1, 50, usize::MAX] {
+                assert_eq!(decode_cursor(&encode_cursor(offset)), Some(offset));
+            }
+        }
+
+        #[test]
+        fn pages_follow_the_cursor_to_the_end() {
+            System::new().block_on(async {
+                let (status, body) = get("/colleges/list-all?limit=2").await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(body["total"], 3);
+                assert_eq!(names(&body), ["Alpha University", "Bravo Institute"]);
+
+                let cursor = body["next_cursor"].as_str().unwrap().to_string();
+                let (status, body) =
+                    get(&format!("/colleges/list-all?limit=2&cursor={cursor}")).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(names(&body), ["Charlie College"]);
+                assert_eq!(body["next_cursor"], Value::Null);
+            });
+        }
+
+        #[test]
+        fn rejects_tampered_cursors() {
+            System::new().block_on(async {
+                let not_an_offset = general_purpose::URL_SAFE_NO_PAD.encode("o:-1");
+                let wrong_prefix = general_purpose::URL_SAFE_NO_PAD.encode("x:1");
+                for cursor in ["not*base64", not_an_offset.as_str(), wrong_prefix.as_str()] {
+                    let (status, body) = get(&format!("/colleges/list-all?cursor={cursor}")).await;
+                    assert_eq!(status, StatusCode::BAD_REQUEST, "{cursor}");
+                    assert_eq!(error_fields(&body), ["cursor"]);
+                }
+            });
+        }
+
+        #[test]
+        fn caps_the_limit() {
+            let parse = |limit, cursor: Option<&str>| {
+                let query = CollegeListQuery {
+                    limit,
+                    cursor: cursor.map(str::to_string),
+                    sort: None,
+                    fields: None,
+                };
+                ListParams::parse(&query, false).map(|params| (params.offset, params.limit))
+            };
+            assert_eq!(
+                parse(Some(10_000), None).ok(),
+                Some((0, Some(MAX_PAGE_LIMIT)))
+            );
+            assert_eq!(parse(Some(7), None).ok(), Some((0, Some(7))));
+            assert_eq!(parse(None, None).ok(), Some((0, None)));
+            let cursor = encode_cursor(4);
+            assert_eq!(
+                parse(None, Some(&cursor)).ok(),
+                Some((4, Some(DEFAULT_PAGE_LIMIT)))
+            );
+
+            System::new().block_on(async {
+                let (status, body) = get("/colleges/list-all?limit=0").await;
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(error_fields(&body), ["limit"]);
+            });
+        }
+
+        #[test]
+        fn projects_the_requested_fields() {
+            System::new().block_on(async {
+                let (status, body) = get("/colleges/list-all?fields=name,%20state").await;
+                assert_eq!(status, StatusCode::OK);
+                let first = body["colleges"][0].as_object().unwrap();
+                let mut keys: Vec<&str> = first.keys().map(String::as_str).collect();
+                keys.sort_unstable();
+                assert_eq!(keys, ["name", "state"]);
+
+                let (status, body) =
+                    get("/colleges/with-params?lat=42.36&lon=-71.06&sort=distance&fields=ipedsid,distance")
+                        .await;
+                assert_eq!(status, StatusCode::OK);
+                let first = &body["colleges"][0];
+                assert_eq!(first["ipedsid"], "100");
+                assert!(first["distance"].as_f64().unwrap() < 1.0);
+                assert_eq!(first.as_object().unwrap().len(), 2);
+
+                let (status, body) = get("/colleges/list-all?fields=name,ranking").await;
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(error_fields(&body), ["fields"]);
+            });
+        }
+
+        #[test]
+        fn distance_sort_needs_an_origin_even_when_nothing_matches() {
+            System::new().block_on(async {
+                for uri in [
+                    "/colleges/list-all?sort=distance",
+                    "/colleges/with-params?sort=distance",
+                    "/colleges/with-params?sort=distance&name=nowhere",
+                ] {
+                    let (status, body) = get(uri).await;
+                    assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+                    assert_eq!(error_fields(&body), ["sort"]);
+                }
+
+                let (status, body) =
+                    get("/colleges/with-params?sort=distance&name=nowhere&lat=0&lon=0").await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(body["total"], 0);
+            });
+        }
+
+        #[test]
+        fn reads_distances_in_the_unit_given() {
+            System::new().block_on(async {
+                let boston = "/colleges/with-params?lat=42.36&lon=-71.06&sort=distance";
+                let (status, miles) = get(&format!("{boston}&max_distance=200")).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(names(&miles), ["Charlie College", "Alpha University"]);
+                let (status, default) = get(&format!("{boston}&max_distance=200&unit=mi")).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(default, miles);
+
+                // 200 km is only about 124 miles, short of New York.
+                let (status, km) = get(&format!("{boston}&max_distance=200&unit=km")).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(names(&km), ["Charlie College"]);
+
+                let (_, km) = get(&format!("{boston}&max_distance=400&unit=km")).await;
+                let nyc_miles = miles["colleges"][1]["distance"].as_f64().unwrap();
+                let nyc_km = km["colleges"][1]["distance"].as_f64().unwrap();
+                assert!((nyc_km - nyc_miles * 1.609_344).abs() < 1e-6);
+
+                for unit in ["ft", "KM", ""] {
+                    let (status, body) = get(&format!("{boston}&unit={unit}")).await;
+                    assert_eq!(status, StatusCode::BAD_REQUEST, "{unit}");
+                    assert_eq!(error_fields(&body), ["unit"]);
+                }
+            });
+        }
+
+        #[test]
+        fn reports_each_bad_coordinate() {
+            System::new().block_on(async {
+                for (query, fields) in [
+                    ("lat=91&lon=181", vec!["lat", "lon"]),
+                    ("lat=north&lon=west", vec!["lat", "lon"]),
+                    ("lat=-90.5&lon=0", vec!["lat"]),
+                    ("lat=0&lon=-180.5", vec!["lon"]),
+                    ("lat=NaN&lon=0", vec!["lat"]),
+                    ("lat=42", vec!["lon"]),
+                    ("lon=-71", vec!["lat"]),
+                    ("lat=95", vec!["lat", "lon"]),
+                ] {
+                    let (status, body) = get(&format!("/colleges/with-params?{query}")).await;
+                    assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+                    assert_eq!(error_fields(&body), fields, "{query}");
+                }
+
+                let (status, _) = get("/colleges/with-params?lat=90&lon=-180").await;
+                assert_eq!(status, StatusCode::OK);
+            });
+        }
+    }
 }
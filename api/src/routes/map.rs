@@ -0,0 +1,380 @@
+// Map-shaped views of the college catalog, under the /colleges/map path.
+use std::collections::HashMap;
+
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    api_error::ApiError,
+    app_state::AppState,
+    clustering::{cluster_points, MAX_ZOOM},
+    college_filter::{CollegeFilter, CollegeFilterQuery, FieldError},
+    structures::{CollegeCoord, CollegeStruct},
+};
+
+use super::colleges::get_all_colleges;
+
+/// How many college ids each cluster or region lists, for previews.
+const MAX_SAMPLE_IDS: usize = 5;
+
+#[derive(Clone, Copy)]
+enum MapFormat {
+    Json,
+    GeoJson,
+}
+
+impl MapFormat {
+    fn parse(value: Option<&str>, errors: &mut Vec<FieldError>) -> Self {
+        match value {
+            None | Some("json") => Self::Json,
+            Some("geojson") => Self::GeoJson,
+            Some(_) => {
+                errors.push(FieldError::new("format", "must be json or geojson"));
+                Self::Json
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MapCluster {
+    lat: f64,
+    lon: f64,
+    count: usize,
+    sample_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct MapResp<'a> {
+    clusters: Vec<MapCluster>,
+    colleges: Vec<&'a CollegeStruct>,
+    total: usize,
+}
+
+#[derive(Serialize)]
+pub struct GeoJsonPoint {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    // GeoJSON orders positions longitude first.
+    coordinates: [f64; 2],
+}
+
+#[derive(Serialize)]
+pub struct GeoJsonFeature {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    geometry: GeoJsonPoint,
+    properties: Map<String, Value>,
+}
+
+#[derive(Serialize)]
+pub struct GeoJsonFeatureCollection {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    features: Vec<GeoJsonFeature>,
+}
+
+impl GeoJsonFeature {
+    fn point(coords: &CollegeCoord, properties: Map<String, Value>) -> Self {
+        Self {
+            kind: "Feature",
+            geometry: GeoJsonPoint {
+                kind: "Point",
+                coordinates: [coords.lon, coords.lat],
+            },
+            properties,
+        }
+    }
+
+    /// A college, with its fields other than the location as properties.
+    fn college(college: &CollegeStruct) -> Self {
+        let mut properties = match serde_json::to_value(college) {
+            Ok(Value::Object(properties)) => properties,
+            _ => Map::new(),
+        };
+        properties.remove("geo_point_2d");
+        properties.insert("cluster".to_string(), Value::Bool(false));
+        Self::point(&college.geo_point_2d, properties)
+    }
+
+    /// A group of colleges drawn as one point, with the same properties as a cluster in the
+    /// JSON format. `point_count` is the name map SDKs look for.
+    fn group(
+        centroid: &CollegeCoord,
+        extra: Map<String, Value>,
+        count: usize,
+        sample_ids: Vec<String>,
+    ) -> Self {
+        let mut properties = extra;
+        properties.insert("cluster".to_string(), Value::Bool(true));
+        properties.insert("point_count".to_string(), Value::from(count));
+        properties.insert("sample_ids".to_string(), Value::from(sample_ids));
+        Self::point(centroid, properties)
+    }
+}
+
+impl GeoJsonFeatureCollection {
+    fn from(features: Vec<GeoJsonFeature>) -> Self {
+        Self {
+            kind: "FeatureCollection",
+            features,
+        }
+    }
+}
+
+fn sample_ids(members: &[usize], college_list: &[CollegeStruct]) -> Vec<String> {
+    members
+        .iter()
+        .take(MAX_SAMPLE_IDS)
+        .map(|idx| college_list[*idx].ipedsid.clone())
+        .collect()
+}
+
+fn filter_from_query(
+    filters: &CollegeFilterQuery,
+    errors: &mut Vec<FieldError>,
+) -> Option<CollegeFilter> {
+    match CollegeFilter::from_query(filters) {
+        Ok(filter) => Some(filter),
+        Err(mut filter_errors) => {
+            errors.append(&mut filter_errors);
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MapQuery {
+    // Without a zoom level every college in the viewport is returned on its own.
+    pub zoom: Option<String>,
+    // "json" (the default) or "geojson".
+    pub format: Option<String>,
+    // The viewport is the bbox filter, which is required here.
+    #[serde(flatten)]
+    pub filters: CollegeFilterQuery,
+}
+
+/// The colleges in a map viewport, grouped into clusters at the given zoom level.
+/// A cluster of one college is returned as the college itself.
+#[get("/colleges/map")]
+pub async fn handle_get_college_map(
+    state: web::Data<AppState>,
+    query: web::Query<MapQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let mut errors = Vec::new();
+    let filter = filter_from_query(&query.filters, &mut errors);
+    if query.filters.bbox.is_none() {
+        errors.push(FieldError::new("bbox", "is required"));
+    }
+    let zoom = match query.zoom.as_deref().map(|zoom| zoom.trim().parse::<u8>()) {
+        None => None,
+        Some(Ok(zoom)) if zoom <= MAX_ZOOM => Some(zoom),
+        Some(_) => {
+            errors.push(FieldError::new(
+                "zoom",
+                format!("must be a whole number between 0 and {MAX_ZOOM}"),
+            ));
+            None
+        }
+    };
+    let format = MapFormat::parse(query.format.as_deref(), &mut errors);
+    let (filter, bbox) = match filter {
+        Some(filter) if errors.is_empty() => match filter.bbox() {
+            Some(bbox) => (filter, bbox),
+            None => return Err(ApiError::InvalidParams(errors)),
+        },
+        _ => return Err(ApiError::InvalidParams(errors)),
+    };
+
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    let college_list = &catalog.colleges;
+
+    let in_view: Vec<usize> = catalog
+        .indexes
+        .spatial
+        .in_bbox(&bbox)
+        .into_iter()
+        .filter(|idx| filter.matches(&college_list[*idx]))
+        .collect();
+    let total = in_view.len();
+
+    let clusters = match zoom {
+        Some(zoom) => cluster_points(
+            in_view
+                .iter()
+                .map(|idx| (*idx, &college_list[*idx].geo_point_2d)),
+            zoom,
+        ),
+        None => Vec::new(),
+    };
+    let (clusters, singles): (Vec<_>, Vec<_>) = clusters
+        .into_iter()
+        .partition(|cluster| cluster.members.len() > 1);
+    let singles: Vec<usize> = match zoom {
+        Some(_) => singles
+            .into_iter()
+            .map(|cluster| cluster.members[0])
+            .collect(),
+        None => in_view,
+    };
+
+    match format {
+        MapFormat::Json => {
+            let clusters = clusters
+                .iter()
+                .map(|cluster| MapCluster {
+                    lat: cluster.centroid.lat,
+                    lon: cluster.centroid.lon,
+                    count: cluster.members.len(),
+                    sample_ids: sample_ids(&cluster.members, college_list),
+                })
+                .collect();
+
+            let colleges = singles
+                .iter()
+                .filter_map(|idx| college_list.get(*idx))
+                .collect();
+
+            Ok(HttpResponse::Ok().json(MapResp {
+                clusters,
+                colleges,
+                total,
+            }))
+        }
+        MapFormat::GeoJson => {
+            let features = clusters
+                .iter()
+                .map(|cluster| {
+                    GeoJsonFeature::group(
+                        &cluster.centroid,
+                        Map::new(),
+                        cluster.members.len(),
+                        sample_ids(&cluster.members, college_list),
+                    )
+                })
+                .chain(
+                    singles
+                        .iter()
+                        .map(|idx| GeoJsonFeature::college(&college_list[*idx])),
+                )
+                .collect();
+            Ok(HttpResponse::Ok().json(GeoJsonFeatureCollection::from(features)))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RegionQuery {
+    // "state" (the default) or "metro".
+    pub by: Option<String>,
+    pub format: Option<String>,
+    #[serde(flatten)]
+    pub filters: CollegeFilterQuery,
+}
+
+/// A state and, when grouping by metro, a city in it.
+type RegionKey<'a> = (&'a str, Option<&'a str>);
+
+#[derive(Serialize)]
+pub struct MapRegion {
+    state: String,
+    // Only set when grouping by metro.
+    city: Option<String>,
+    lat: f64,
+    lon: f64,
+    count: usize,
+    sample_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RegionResp {
+    regions: Vec<MapRegion>,
+}
+
+/// College counts per state, or per metro area, each placed at the middle of its colleges.
+/// The catalog has no metro areas of its own, so a metro is a city and its state.
+#[get("/colleges/map/regions")]
+pub async fn handle_get_college_regions(
+    state: web::Data<AppState>,
+    query: web::Query<RegionQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let mut errors = Vec::new();
+    let filter = filter_from_query(&query.filters, &mut errors);
+    let by_metro = match query.by.as_deref() {
+        None | Some("state") => false,
+        Some("metro") => true,
+        Some(_) => {
+            errors.push(FieldError::new("by", "must be state or metro"));
+            false
+        }
+    };
+    let format = MapFormat::parse(query.format.as_deref(), &mut errors);
+    let filter = match filter {
+        Some(filter) if errors.is_empty() => filter,
+        _ => return Err(ApiError::InvalidParams(errors)),
+    };
+
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    let college_list = &catalog.colleges;
+
+    // Per region: the members and the sums of their coordinates.
+    let mut groups: HashMap<RegionKey, (Vec<usize>, f64, f64)> = HashMap::new();
+    for (idx, college) in college_list.iter().enumerate() {
+        if !filter.matches(college) {
+            continue;
+        }
+        let city = by_metro.then_some(college.city.as_str());
+        let group = groups.entry((college.state.as_str(), city)).or_default();
+        group.0.push(idx);
+        group.1 += college.geo_point_2d.lat;
+        group.2 += college.geo_point_2d.lon;
+    }
+
+    let mut regions: Vec<MapRegion> = groups
+        .into_iter()
+        .map(|((state, city), (members, lat_sum, lon_sum))| MapRegion {
+            state: state.to_string(),
+            city: city.map(str::to_string),
+            lat: lat_sum / members.len() as f64,
+            lon: lon_sum / members.len() as f64,
+            count: members.len(),
+            sample_ids: sample_ids(&members, college_list),
+        })
+        .collect();
+    regions.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (&a.state, &a.city).cmp(&(&b.state, &b.city)))
+    });
+
+    match format {
+        MapFormat::Json => Ok(HttpResponse::Ok().json(RegionResp { regions })),
+        MapFormat::GeoJson => {
+            let features = regions
+                .into_iter()
+                .map(|region| {
+                    let mut properties = Map::new();
+                    properties.insert("state".to_string(), Value::from(region.state));
+                    if let Some(city) = region.city {
+                        properties.insert("city".to_string(), Value::from(city));
+                    }
+                    GeoJsonFeature::group(
+                        &CollegeCoord {
+                            lon: region.lon,
+                            lat: region.lat,
+                        },
+                        properties,
+                        region.count,
+                        region.sample_ids,
+                    )
+                })
+                .collect();
+            Ok(HttpResponse::Ok().json(GeoJsonFeatureCollection::from(features)))
+        }
+    }
+}
@@ -0,0 +1,444 @@
+// Routes under the /me path, scoped to the signed-in user.
+
+use std::collections::HashMap;
+
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::Utc;
+use entities::saved_college::{self, Entity as SavedCollege};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection,
+    DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect, SqlErr, TransactionTrait,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    api_error::ApiError, app_state::AppState, auth_user::AuthenticatedUser,
+    routes::colleges::get_all_colleges, structures::CollegeStruct,
+};
+
+#[derive(Serialize)]
+pub struct SavedCollegeResp<'a> {
+    ipedsid: String,
+    note: Option<String>,
+    position: i32,
+    saved_at: DateTimeWithTimeZone,
+    // None when the college is no longer in the catalog.
+    college: Option<&'a CollegeStruct>,
+}
+
+#[derive(Serialize)]
+pub struct SavedCollegeListResp<'a> {
+    colleges: Vec<SavedCollegeResp<'a>>,
+}
+
+/// Loads the user's saved colleges in order and joins them with the college catalog.
+async fn get_saved_colleges(state: &AppState, user_id: i32) -> Result<HttpResponse, ApiError> {
+    let saved = SavedCollege::find()
+        .filter(saved_college::Column::UserId.eq(user_id))
+        .order_by_asc(saved_college::Column::Position)
+        .order_by_asc(saved_college::Column::Id)
+        .all(&state.db)
+        .await?;
+
+    let catalog = get_all_colleges(state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    let colleges_by_id: HashMap<&str, &CollegeStruct> = catalog
+        .colleges
+        .iter()
+        .map(|college| (college.ipedsid.as_str(), college))
+        .collect();
+
+    let saved_colleges = saved
+        .into_iter()
+        .map(|model| SavedCollegeResp {
+            college: colleges_by_id.get(model.ipedsid.as_str()).copied(),
+            ipedsid: model.ipedsid,
+            note: model.note,
+            position: model.position,
+            saved_at: model.created_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SavedCollegeListResp {
+        colleges: saved_colleges,
+    }))
+}
+
+#[get("/me/colleges")]
+pub async fn handle_get_saved_colleges(
+    auth: AuthenticatedUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    get_saved_colleges(&state, auth.user.id).await
+}
+
+/// Tells a field sent as `null` apart from one left out: `Some(None)` and `None`.
+fn explicit_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+pub struct SaveCollegeReqBody {
+    ipedsid: String,
+    // Left out keeps the saved note; `null` clears it.
+    #[serde(default, deserialize_with = "explicit_null")]
+    note: Option<Option<String>>,
+    position: Option<i32>,
+}
+
+/// Updates the note and position of a saved college with those given in `body`.
+async fn update_saved_college(
+    db: &DatabaseConnection,
+    model: saved_college::Model,
+    body: &SaveCollegeReqBody,
+) -> Result<(), DbErr> {
+    let mut active: saved_college::ActiveModel = model.into();
+    if let Some(note) = &body.note {
+        active.note = ActiveValue::Set(note.clone());
+    }
+    if let Some(position) = body.position {
+        active.position = ActiveValue::Set(position);
+    }
+    active.update(db).await.map(|_| ())
+}
+
+async fn find_saved_college(
+    db: &DatabaseConnection,
+    user_id: i32,
+    ipedsid: &str,
+) -> Result<Option<saved_college::Model>, DbErr> {
+    SavedCollege::find()
+        .filter(saved_college::Column::UserId.eq(user_id))
+        .filter(saved_college::Column::Ipedsid.eq(ipedsid))
+        .one(db)
+        .await
+}
+
+#[post("/me/colleges")]
+pub async fn handle_save_college(
+    auth: AuthenticatedUser,
+    body: web::Json<SaveCollegeReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    // Only colleges in the catalog can be saved.
+    let catalog = get_all_colleges(&state)
+        .await
+        .ok_or(ApiError::Internal("Unable to get college list"))?;
+    if !catalog
+        .colleges
+        .iter()
+        .any(|college| college.ipedsid == body.ipedsid)
+    {
+        return Err(ApiError::NotFound("No college with the given ipedsid"));
+    }
+
+    let save_failed = |e: DbErr| {
+        eprintln!("error: {e}");
+        ApiError::Internal("Unable to save college")
+    };
+
+    // Saving an already saved college updates its note and position.
+    if let Some(model) = find_saved_college(&state.db, auth.user.id, &body.ipedsid).await? {
+        update_saved_college(&state.db, model, &body)
+            .await
+            .map_err(save_failed)?;
+        return get_saved_colleges(&state, auth.user.id).await;
+    }
+
+    // New colleges go to the end of the list unless a position is given.
+    let position = match body.position {
+        Some(position) => position,
+        None => SavedCollege::find()
+            .select_only()
+            .column_as(saved_college::Column::Position.max(), "max_position")
+            .filter(saved_college::Column::UserId.eq(auth.user.id))
+            .into_tuple::<Option<i32>>()
+            .one(&state.db)
+            .await?
+            .flatten()
+            .map_or(0, |max| max + 1),
+    };
+
+    let inserted = saved_college::ActiveModel {
+        user_id: ActiveValue::Set(auth.user.id),
+        ipedsid: ActiveValue::Set(body.ipedsid.clone()),
+        note: ActiveValue::Set(body.note.clone().flatten()),
+        position: ActiveValue::Set(position),
+        created_at: ActiveValue::Set(Utc::now().into()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await;
+
+    match inserted {
+        Ok(_) => {}
+        // A concurrent save of the same college got there first, so this one updates it.
+        Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+            let model = find_saved_college(&state.db, auth.user.id, &body.ipedsid)
+                .await?
+                .ok_or(ApiError::Conflict("College was unsaved while saving it"))?;
+            update_saved_college(&state.db, model, &body)
+                .await
+                .map_err(save_failed)?;
+        }
+        Err(e) => return Err(save_failed(e)),
+    }
+
+    get_saved_colleges(&state, auth.user.id).await
+}
+
+#[derive(Deserialize)]
+pub struct ReorderSavedCollegesReqBody {
+    ipedsids: Vec<String>,
+}
+
+#[put("/me/colleges/order")]
+pub async fn handle_reorder_saved_colleges(
+    auth: AuthenticatedUser,
+    body: web::Json<ReorderSavedCollegesReqBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    // Positions follow the order of the given ids; saved colleges left out keep their relative
+    // order after them.
+    let saved = SavedCollege::find()
+        .filter(saved_college::Column::UserId.eq(auth.user.id))
+        .order_by_asc(saved_college::Column::Position)
+        .order_by_asc(saved_college::Column::Id)
+        .all(&state.db)
+        .await?;
+
+    let mut ordered: Vec<saved_college::Model> = Vec::with_capacity(saved.len());
+    let mut remaining = saved;
+    for ipedsid in &body.ipedsids {
+        let Some(idx) = remaining.iter().position(|model| &model.ipedsid == ipedsid) else {
+            return Err(ApiError::BadRequest(
+                "Order contains a college that is not saved".to_string(),
+            ));
+        };
+        ordered.push(remaining.remove(idx));
+    }
+    ordered.append(&mut remaining);
+
+    let reorder_failed = |e: DbErr| {
+        eprintln!("error: {e}");
+        ApiError::Internal("Unable to reorder colleges")
+    };
+    let txn = state.db.begin().await?;
+    for (position, model) in ordered.into_iter().enumerate() {
+        let mut active: saved_college::ActiveModel = model.into();
+        active.position = ActiveValue::Set(position as i32);
+        active.update(&txn).await.map_err(reorder_failed)?;
+    }
+    txn.commit().await.map_err(reorder_failed)?;
+
+    get_saved_colleges(&state, auth.user.id).await
+}
+
+#[delete("/me/colleges/{ipedsid}")]
+pub async fn handle_delete_saved_college(
+    auth: AuthenticatedUser,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let res = SavedCollege::delete_many()
+        .filter(saved_college::Column::UserId.eq(auth.user.id))
+        .filter(saved_college::Column::Ipedsid.eq(path.as_str()))
+        .exec(&state.db)
+        .await?;
+    if res.rows_affected == 0 {
+        return Err(ApiError::NotFound("College is not saved"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{header, StatusCode},
+        rt::System,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::app_state::tests::{sign_in, test_college, test_state};
+
+    async fn state() -> (web::Data<AppState>, String) {
+        let state = test_state(vec![
+            test_college("100", "First College", "MA", 42.0, -71.0),
+            test_college("200", "Second College", "NY", 43.0, -75.0),
+            test_college("300", "Third College", "CA", 37.0, -122.0),
+        ])
+        .await;
+        let (_, auth) = sign_in(&state, "student@example.com").await;
+        (web::Data::new(state), auth)
+    }
+
+    fn save(auth: &str, body: Value) -> TestRequest {
+        TestRequest::post()
+            .uri("/me/colleges")
+            .insert_header((header::AUTHORIZATION, auth))
+            .set_json(body)
+    }
+
+    /// The saved colleges in a list response, as (ipedsid, note, position).
+    fn saved(body: &Value) -> Vec<(String, Value, i64)> {
+        body["colleges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|saved| {
+                (
+                    saved["ipedsid"].as_str().unwrap().to_string(),
+                    saved["note"].clone(),
+                    saved["position"].as_i64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saved_colleges_are_listed_in_order_with_their_details() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(
+                App::new()
+                    .app_data(state)
+                    .service(handle_save_college)
+                    .service(handle_get_saved_colleges),
+            )
+            .await;
+
+            let resp =
+                call_service(&app, save(&auth, json!({"ipedsid": "200"})).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body = json!({"ipedsid": "100", "note": "Reach"});
+            call_service(&app, save(&auth, body).to_request()).await;
+
+            let req = TestRequest::get()
+                .uri("/me/colleges")
+                .insert_header((header::AUTHORIZATION, auth.as_str()))
+                .to_request();
+            let body: Value = read_body_json(call_service(&app, req).await).await;
+            assert_eq!(
+                saved(&body),
+                vec![
+                    ("200".to_string(), Value::Null, 0),
+                    ("100".to_string(), json!("Reach"), 1),
+                ]
+            );
+            assert_eq!(body["colleges"][1]["college"]["name"], "First College");
+        });
+    }
+
+    #[test]
+    fn saving_again_updates_the_note_and_position() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(App::new().app_data(state).service(handle_save_college)).await;
+            let body = json!({"ipedsid": "100", "note": "Reach"});
+            call_service(&app, save(&auth, body).to_request()).await;
+
+            // A note left out is kept.
+            let resp = call_service(
+                &app,
+                save(&auth, json!({"ipedsid": "100", "position": 5})).to_request(),
+            )
+            .await;
+            let body: Value = read_body_json(resp).await;
+            assert_eq!(saved(&body), vec![("100".to_string(), json!("Reach"), 5)]);
+
+            // A null note clears it.
+            let resp = call_service(
+                &app,
+                save(&auth, json!({"ipedsid": "100", "note": null})).to_request(),
+            )
+            .await;
+            let body: Value = read_body_json(resp).await;
+            assert_eq!(saved(&body), vec![("100".to_string(), Value::Null, 5)]);
+        });
+    }
+
+    #[test]
+    fn concurrent_saves_of_one_college_both_succeed() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(App::new().app_data(state).service(handle_save_college)).await;
+
+            let first = call_service(&app, save(&auth, json!({"ipedsid": "100"})).to_request());
+            let body = json!({"ipedsid": "100", "note": "Safety"});
+            let second = call_service(&app, save(&auth, body).to_request());
+            let (first, second) = futures_util::future::join(first, second).await;
+
+            assert_eq!(first.status(), StatusCode::OK);
+            assert_eq!(second.status(), StatusCode::OK);
+            let body: Value = read_body_json(second).await;
+            assert_eq!(saved(&body), vec![("100".to_string(), json!("Safety"), 0)]);
+        });
+    }
+
+    #[test]
+    fn only_catalog_colleges_can_be_saved() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(App::new().app_data(state).service(handle_save_college)).await;
+
+            let resp =
+                call_service(&app, save(&auth, json!({"ipedsid": "999"})).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+            let req = TestRequest::post()
+                .uri("/me/colleges")
+                .set_json(json!({"ipedsid": "100"}))
+                .to_request();
+            assert_eq!(
+                call_service(&app, req).await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+        });
+    }
+
+    #[test]
+    fn deleting_unsaves_the_college_once() {
+        System::new().block_on(async {
+            let (state, auth) = state().await;
+            let app = init_service(
+                App::new()
+                    .app_data(state)
+                    .service(handle_save_college)
+                    .service(handle_get_saved_colleges)
+                    .service(handle_delete_saved_college),
+            )
+            .await;
+            call_service(&app, save(&auth, json!({"ipedsid": "100"})).to_request()).await;
+
+            let delete = || {
+                TestRequest::delete()
+                    .uri("/me/colleges/100")
+                    .insert_header((header::AUTHORIZATION, auth.as_str()))
+                    .to_request()
+            };
+            assert_eq!(
+                call_service(&app, delete()).await.status(),
+                StatusCode::NO_CONTENT
+            );
+            assert_eq!(
+                call_service(&app, delete()).await.status(),
+                StatusCode::NOT_FOUND
+            );
+
+            let req = TestRequest::get()
+                .uri("/me/colleges")
+                .insert_header((header::AUTHORIZATION, auth.as_str()))
+                .to_request();
+            let body: Value = read_body_json(call_service(&app, req).await).await;
+            assert!(saved(&body).is_empty());
+        });
+    }
+}
@@ -1,10 +1,52 @@
 // Routes under the root path.
-use actix_web::{get, Responder};
+use actix_web::{get, http::header, web, HttpResponse, Responder};
+use serde::Serialize;
 
+use crate::{app_state::AppState, cache::CacheStatus};
+
+pub mod admin;
+pub mod applications;
 pub mod auth;
 pub mod colleges;
+pub mod map;
+pub mod me;
 
 #[get("/")]
 pub async fn handle_root_path() -> impl Responder {
     "App 2023 API v1"
 }
+
+// Verifiers refresh on this interval, so a newly added key should be published at least this long
+// before it starts signing tokens.
+const JWKS_MAX_AGE: u32 = 60 * 60;
+
+#[get("/.well-known/jwks.json")]
+pub async fn handle_jwks(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={JWKS_MAX_AGE}"),
+        ))
+        .json(state.jwt.keys.jwks())
+}
+
+#[derive(Serialize)]
+pub struct StatusResp {
+    // "ok", or "degraded" while a dependency is down but the API is still serving.
+    status: &'static str,
+    cache: Vec<CacheStatus>,
+}
+
+/// The health of the API's dependencies, so outages are visible.
+#[get("/status")]
+pub async fn handle_status(state: web::Data<AppState>) -> HttpResponse {
+    let cache = state.cache.status().await;
+    let status = if cache.iter().all(|tier| tier.healthy) {
+        "ok"
+    } else {
+        "degraded"
+    };
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(StatusResp { status, cache })
+}
@@ -0,0 +1,331 @@
+// Admission statistics from the admissions section of a College Navigator page.
+//
+// The section is a handful of tables with a header row naming the columns ("Total", "Men",
+// "Women" or "25th Percentile" and so on) and one row per statistic. Columns are found by
+// their headers and rows by their labels, so added or reordered rows and columns are fine.
+use tl::{HTMLTag, Node, Parser, ParserOptions};
+
+use super::clean_text;
+
+/// A statistic reported overall and by gender.
+#[derive(std::fmt::Debug, Default, PartialEq, Clone, Copy)]
+pub struct Breakdown<T> {
+    pub total: Option<T>,
+    pub men: Option<T>,
+    pub women: Option<T>,
+}
+
+/// Test scores at the 25th, 50th and 75th percentiles of enrolled students.
+#[derive(std::fmt::Debug, Default, PartialEq, Clone, Copy)]
+pub struct Percentiles {
+    pub p25: Option<u32>,
+    pub p50: Option<u32>,
+    pub p75: Option<u32>,
+}
+
+#[derive(std::fmt::Debug, Default, PartialEq, Clone, Copy)]
+pub struct AdmissionStats {
+    pub applicants: Breakdown<u32>,
+    // Percentages from 0 to 100.
+    pub percent_admitted: Breakdown<f64>,
+    pub percent_admitted_enrolled: Breakdown<f64>,
+    pub sat_ebrw: Percentiles,
+    pub sat_math: Percentiles,
+    pub act_composite: Percentiles,
+}
+
+#[derive(Clone, Copy)]
+enum Column {
+    Total,
+    Men,
+    Women,
+    P25,
+    P50,
+    P75,
+}
+
+impl Column {
+    fn from_header(header: &str) -> Option<Self> {
+        match header {
+            "total" => Some(Self::Total),
+            "men" | "male" => Some(Self::Men),
+            "women" | "female" => Some(Self::Women),
+            _ if header.starts_with("25th") => Some(Self::P25),
+            _ if header.starts_with("50th") => Some(Self::P50),
+            _ if header.starts_with("75th") => Some(Self::P75),
+            _ => None,
+        }
+    }
+}
+
+enum Stat {
+    Applicants,
+    PercentAdmitted,
+    PercentAdmittedEnrolled,
+    SatEbrw,
+    SatMath,
+    ActComposite,
+}
+
+impl Stat {
+    fn from_label(label: &str) -> Option<Self> {
+        if label.starts_with("number of applicants") {
+            Some(Self::Applicants)
+        } else if label.starts_with("percent admitted who enrolled") {
+            Some(Self::PercentAdmittedEnrolled)
+        } else if label.starts_with("percent admitted") {
+            Some(Self::PercentAdmitted)
+        } else if label.starts_with("sat")
+            && (label.contains("reading") || label.contains("verbal"))
+        {
+            Some(Self::SatEbrw)
+        } else if label.starts_with("sat math") {
+            Some(Self::SatMath)
+        } else if label.starts_with("act composite") {
+            Some(Self::ActComposite)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads values like "26,914" or "5%". Cells with no data hold a dash or nothing.
+fn parse_number(value: &str) -> Option<f64> {
+    let number: String = value
+        .trim()
+        .trim_end_matches('%')
+        .chars()
+        .filter(|c| *c != ',')
+        .collect();
+    number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|n: &f64| n.is_finite() && *n >= 0.0)
+}
+
+fn parse_count(value: &str) -> Option<u32> {
+    parse_number(value).map(|n| n.round() as u32)
+}
+
+fn parse_percent(value: &str) -> Option<f64> {
+    parse_number(value).filter(|n| *n <= 100.0)
+}
+
+fn cells(row: &HTMLTag, parser: &Parser) -> Vec<String> {
+    let children = row.children();
+    children
+        .top()
+        .iter()
+        .filter_map(|cell| cell.get(parser)?.as_tag())
+        .filter(|cell| matches!(cell.name().as_bytes(), b"td" | b"th"))
+        .map(|cell| clean_text(&cell.inner_text(parser)))
+        .collect()
+}
+
+impl AdmissionStats {
+    fn set_breakdown<T>(
+        breakdown: &mut Breakdown<T>,
+        columns: &[Option<Column>],
+        values: &[String],
+        parse: fn(&str) -> Option<T>,
+    ) {
+        for (column, value) in columns.iter().zip(values) {
+            let slot = match column {
+                Some(Column::Total) => &mut breakdown.total,
+                Some(Column::Men) => &mut breakdown.men,
+                Some(Column::Women) => &mut breakdown.women,
+                _ => continue,
+            };
+            if slot.is_none() {
+                *slot = parse(value);
+            }
+        }
+    }
+
+    fn set_percentiles(
+        percentiles: &mut Percentiles,
+        columns: &[Option<Column>],
+        values: &[String],
+    ) {
+        for (column, value) in columns.iter().zip(values) {
+            let slot = match column {
+                Some(Column::P25) => &mut percentiles.p25,
+                Some(Column::P50) => &mut percentiles.p50,
+                Some(Column::P75) => &mut percentiles.p75,
+                _ => continue,
+            };
+            if slot.is_none() {
+                *slot = parse_count(value);
+            }
+        }
+    }
+
+    fn add_row(&mut self, stat: Stat, columns: &[Option<Column>], values: &[String]) {
+        match stat {
+            Stat::Applicants => {
+                Self::set_breakdown(&mut self.applicants, columns, values, parse_count)
+            }
+            Stat::PercentAdmitted => {
+                Self::set_breakdown(&mut self.percent_admitted, columns, values, parse_percent)
+            }
+            Stat::PercentAdmittedEnrolled => Self::set_breakdown(
+                &mut self.percent_admitted_enrolled,
+                columns,
+                values,
+                parse_percent,
+            ),
+            Stat::SatEbrw => Self::set_percentiles(&mut self.sat_ebrw, columns, values),
+            Stat::SatMath => Self::set_percentiles(&mut self.sat_math, columns, values),
+            Stat::ActComposite => Self::set_percentiles(&mut self.act_composite, columns, values),
+        }
+    }
+}
+
+/// Reads the statistics out of the admissions section's markup.
+/// Returns `None` when none of them could be found, e.g. because the layout has changed.
+pub fn parse_admissions(html: &str) -> Option<AdmissionStats> {
+    let dom = tl::parse(html, ParserOptions::default()).ok()?;
+    let parser = dom.parser();
+
+    let mut stats = AdmissionStats::default();
+    for table in dom
+        .query_selector("table")?
+        .filter_map(|table| table.get(parser)?.as_tag())
+    {
+        let Some(rows) = table.query_selector(parser, "tr") else {
+            continue;
+        };
+        // The first row naming a known column is the header; rows before it are captions.
+        let mut columns: Option<Vec<Option<Column>>> = None;
+        for row in rows.filter_map(|row| row.get(parser).and_then(Node::as_tag)) {
+            let row_cells = cells(row, parser);
+            let Some((label, values)) = row_cells.split_first() else {
+                continue;
+            };
+
+            match &columns {
+                None => {
+                    let header: Vec<Option<Column>> = values
+                        .iter()
+                        .map(|value| Column::from_header(&value.to_lowercase()))
+                        .collect();
+                    if header.iter().any(Option::is_some) {
+                        columns = Some(header);
+                    }
+                }
+                Some(columns) => {
+                    if let Some(stat) = Stat::from_label(&label.to_lowercase()) {
+                        stats.add_row(stat, columns, values);
+                    }
+                }
+            }
+        }
+    }
+
+    (stats != AdmissionStats::default()).then_some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::college_navigator::parse_college_page;
+
+    fn fixture_admissions(html: &str) -> Option<AdmissionStats> {
+        let page = parse_college_page(html.as_bytes()).expect("fixture should parse");
+        parse_admissions(&page.admissions_html.expect("admissions section"))
+    }
+
+    #[test]
+    fn reads_every_statistic() {
+        let stats = fixture_admissions(include_str!("fixtures/mit.html")).expect("statistics");
+
+        assert_eq!(
+            stats.applicants,
+            Breakdown {
+                total: Some(26_914),
+                men: Some(16_040),
+                women: Some(10_874),
+            }
+        );
+        assert_eq!(
+            stats.percent_admitted,
+            Breakdown {
+                total: Some(5.0),
+                men: Some(3.0),
+                women: Some(7.0),
+            }
+        );
+        assert_eq!(stats.percent_admitted_enrolled.total, Some(85.0));
+        assert_eq!(
+            stats.sat_ebrw,
+            Percentiles {
+                p25: Some(740),
+                p50: Some(760),
+                p75: Some(780),
+            }
+        );
+        assert_eq!(stats.sat_math.p50, Some(790));
+        assert_eq!(
+            stats.act_composite,
+            Percentiles {
+                p25: Some(35),
+                p50: Some(35),
+                p75: Some(36),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_another_institution() {
+        let stats = fixture_admissions(include_str!("fixtures/umich.html")).expect("statistics");
+
+        assert_eq!(stats.applicants.total, Some(84_289));
+        assert_eq!(stats.percent_admitted.women, Some(19.0));
+        assert_eq!(stats.sat_math.p75, Some(780));
+        assert_eq!(stats.act_composite.p50, Some(33));
+    }
+
+    #[test]
+    fn missing_tables_are_left_empty() {
+        let stats =
+            fixture_admissions(include_str!("fixtures/relabeled.html")).expect("statistics");
+
+        assert_eq!(stats.applicants.total, Some(6_530));
+        assert_eq!(stats.percent_admitted.total, Some(39.0));
+        assert_eq!(stats.percent_admitted_enrolled, Breakdown::default());
+        assert_eq!(stats.sat_math, Percentiles::default());
+    }
+
+    #[test]
+    fn columns_are_found_by_header() {
+        let html = r#"<table>
+            <tr><th>Applicants</th><th>Women</th><th>Total</th><th>Men</th></tr>
+            <tr><td>Number of applicants</td><td>120</td><td>200</td><td>-</td></tr>
+            <tr><td>Percent admitted</td><td>50%</td><td>40%</td><td>&nbsp;</td></tr>
+        </table>"#;
+        let stats = parse_admissions(html).expect("statistics");
+
+        assert_eq!(
+            stats.applicants,
+            Breakdown {
+                total: Some(200),
+                men: None,
+                women: Some(120),
+            }
+        );
+        assert_eq!(stats.percent_admitted.total, Some(40.0));
+    }
+
+    #[test]
+    fn unrecognized_markup_is_none() {
+        assert_eq!(
+            parse_admissions("<p>Admissions information is not available.</p>"),
+            None
+        );
+        assert_eq!(
+            parse_admissions("<table><tr><td>Number of applicants</td><td>10</td></tr></table>"),
+            None
+        );
+    }
+}
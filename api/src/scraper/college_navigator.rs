@@ -0,0 +1,398 @@
+// The institution pages of College Navigator (nces.ed.gov/collegenavigator).
+//
+// Fields are found by the labels and ids the page shows rather than by position, and each has
+// a fallback for when the page layout changes, so a missing field does not fail the whole page.
+use std::time::Duration;
+
+use awc::Client;
+use tl::{HTMLTag, Node, NodeHandle, Parser, ParserOptions};
+
+use super::{clean_text, decode_entities, ScrapeError};
+
+const COLLEGE_NAVIGATOR_URL: &str = "https://nces.ed.gov/collegenavigator";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+const GENERAL_SECTION_ID: &str = "divctl00_cphCollegeNavBody_ucInstitutionMain_dtpGeneral";
+const ADMISSIONS_SECTION_ID: &str = "divctl00_cphCollegeNavBody_ucInstitutionMain_ctl04";
+/// Every collapsible section of the page has an id of this form.
+const SECTION_ID_PREFIX: &str = "divctl00_cphCollegeNavBody_ucInstitutionMain_ctl";
+
+/// A link in the general information section, found by the label of its row or, failing that,
+/// by words in the link itself.
+struct LinkField {
+    labels: &'static [&'static str],
+    keywords: &'static [&'static str],
+}
+
+const ADMISSIONS_LINK: LinkField = LinkField {
+    labels: &["admissions", "admissions office", "admissions website"],
+    keywords: &["admission"],
+};
+const APPLY_LINK: LinkField = LinkField {
+    labels: &["apply online", "online application", "apply"],
+    keywords: &["apply", "application"],
+};
+const FINAID_LINK: LinkField = LinkField {
+    labels: &[
+        "financial aid",
+        "financial aid office",
+        "financial aid website",
+    ],
+    keywords: &["finaid", "financial"],
+};
+
+/// What the scraper could read from an institution's page.
+#[derive(std::fmt::Debug)]
+pub struct CollegeNavigatorPage {
+    pub admissions_url: Option<String>,
+    pub apply_url: Option<String>,
+    pub finaid_url: Option<String>,
+    // The markup of the admissions section; open admission colleges have none.
+    pub admissions_html: Option<String>,
+    pub total_enrollment: Option<u32>,
+    pub undergraduate_enrollment: Option<u32>,
+}
+
+/// Fetches and parses the page of the institution with this IPEDS id.
+pub async fn fetch_college_page(ipedsid: &str) -> Result<CollegeNavigatorPage, ScrapeError> {
+    let request = Client::default()
+        .get(COLLEGE_NAVIGATOR_URL)
+        .timeout(FETCH_TIMEOUT)
+        .query(&[("id", ipedsid)])
+        .map_err(|e| {
+            eprintln!("error: {e}");
+            ScrapeError::Fetch
+        })?;
+
+    let mut resp = request.send().await.map_err(|e| {
+        eprintln!("error: {e}");
+        ScrapeError::Fetch
+    })?;
+    if !resp.status().is_success() {
+        eprintln!("error: College Navigator responded with {}", resp.status());
+        return Err(ScrapeError::Fetch);
+    }
+
+    // Institution pages are well over the default body limit.
+    let body = resp.body().limit(8 * 1024 * 1024).await.map_err(|e| {
+        eprintln!("error: {e}");
+        ScrapeError::Fetch
+    })?;
+
+    parse_college_page(&body)
+}
+
+pub fn parse_college_page(body: &[u8]) -> Result<CollegeNavigatorPage, ScrapeError> {
+    let html = std::str::from_utf8(body).map_err(|_| ScrapeError::InvalidEncoding)?;
+    let dom = tl::parse(html, ParserOptions::default()).map_err(|e| {
+        eprintln!("error: {e}");
+        ScrapeError::InvalidHtml
+    })?;
+    let parser = dom.parser();
+
+    let general = dom
+        .get_element_by_id(GENERAL_SECTION_ID)
+        .and_then(|handle| handle.get(parser))
+        .and_then(Node::as_tag)
+        .ok_or(ScrapeError::MissingGeneralInfo)?;
+
+    let rows = general
+        .query_selector(parser, "tr")
+        .map(|rows| labeled_rows(rows, parser))
+        .unwrap_or_default();
+    let link = |field: &LinkField| {
+        field
+            .labels
+            .iter()
+            .find_map(|label| {
+                rows.iter()
+                    .find(|(row_label, _)| row_label == label)
+                    .and_then(|(_, cell)| cell_link(cell, parser))
+            })
+            .or_else(|| keyword_link(general, parser, field.keywords))
+    };
+
+    // The summary at the top of the page gives enrollment as "11,920 (4,657 undergraduate)".
+    let (total_enrollment, undergraduate_enrollment) = dom
+        .query_selector("tr")
+        .map(|rows| labeled_rows(rows, parser))
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(label, _)| label == "student population")
+        .map(|(_, cell)| parse_student_population(&clean_text(&cell.inner_text(parser))))
+        .unwrap_or_default();
+
+    Ok(CollegeNavigatorPage {
+        admissions_url: link(&ADMISSIONS_LINK),
+        apply_url: link(&APPLY_LINK),
+        finaid_url: link(&FINAID_LINK),
+        admissions_html: admissions_section(&dom, parser).map(|section| section.inner_html(parser)),
+        total_enrollment,
+        undergraduate_enrollment,
+    })
+}
+
+/// Rows of two or more cells, keyed by the first cell's text, e.g. "apply online" for a
+/// cell reading "Apply Online:".
+fn labeled_rows<'p, 'a>(
+    rows: impl Iterator<Item = NodeHandle>,
+    parser: &'p Parser<'a>,
+) -> Vec<(String, &'p HTMLTag<'a>)> {
+    rows.filter_map(|row| row.get(parser)?.as_tag())
+        .filter_map(|row| {
+            let children = row.children();
+            let mut cells = children
+                .top()
+                .iter()
+                .filter_map(|cell| cell.get(parser)?.as_tag())
+                .filter(|cell| matches!(cell.name().as_bytes(), b"td" | b"th"));
+            let label = cells.next()?;
+            let value = cells.next()?;
+            let label = clean_text(&label.inner_text(parser))
+                .trim_end_matches(':')
+                .trim()
+                .to_lowercase();
+            Some((label, value))
+        })
+        .collect()
+}
+
+fn parse_student_population(text: &str) -> (Option<u32>, Option<u32>) {
+    let numbers: Vec<(u32, &str)> = text
+        .split(['(', ')'])
+        .filter_map(|part| {
+            let part = part.trim();
+            let (number, rest) = part.split_once(' ').unwrap_or((part, ""));
+            Some((number.replace(',', "").parse().ok()?, rest))
+        })
+        .collect();
+
+    let total = numbers.first().map(|(number, _)| *number);
+    let undergraduate = numbers
+        .iter()
+        .skip(1)
+        .find(|(_, rest)| rest.starts_with("undergraduate"))
+        .map(|(number, _)| *number)
+        .or_else(|| {
+            text.contains("(all undergraduate)")
+                .then_some(total)
+                .flatten()
+        });
+    (total, undergraduate)
+}
+
+/// The target of the first usable link in a cell, falling back to link texts and then the
+/// cell text, since the page sometimes prints the address without linking it.
+fn cell_link(cell: &HTMLTag, parser: &Parser) -> Option<String> {
+    let anchors: Vec<&HTMLTag> = cell
+        .query_selector(parser, "a")
+        .map(|anchors| {
+            anchors
+                .filter_map(|anchor| anchor.get(parser)?.as_tag())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    anchors
+        .iter()
+        .find_map(|anchor| normalize_url(&anchor_href(anchor)?))
+        .or_else(|| {
+            anchors
+                .iter()
+                .find_map(|anchor| normalize_url(&clean_text(&anchor.inner_text(parser))))
+        })
+        .or_else(|| normalize_url(&clean_text(&cell.inner_text(parser))))
+}
+
+/// The first link in the section whose target or text contains one of the keywords.
+fn keyword_link(section: &HTMLTag, parser: &Parser, keywords: &[&str]) -> Option<String> {
+    section
+        .query_selector(parser, "a")?
+        .filter_map(|anchor| anchor.get(parser)?.as_tag())
+        .find_map(|anchor| {
+            let href = anchor_href(anchor)?;
+            let text = clean_text(&anchor.inner_text(parser));
+            let haystack = format!("{href} {text}").to_lowercase();
+            keywords
+                .iter()
+                .any(|keyword| haystack.contains(keyword))
+                .then(|| normalize_url(&href))
+                .flatten()
+        })
+}
+
+fn anchor_href(anchor: &HTMLTag) -> Option<String> {
+    let href = anchor.attributes().get("href")??;
+    Some(decode_entities(&href.as_utf8_str()))
+}
+
+/// Accepts web addresses only, adding the scheme when the page leaves it out.
+fn normalize_url(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() || value.contains('@') || value.chars().any(char::is_whitespace) {
+        return None;
+    }
+
+    let lower = value.to_lowercase();
+    let url = if lower.starts_with("http://") || lower.starts_with("https://") {
+        value.to_string()
+    } else if lower.contains(':') || lower.starts_with('/') || lower.starts_with('#') {
+        // mailto:, javascript: and links within College Navigator itself.
+        return None;
+    } else {
+        format!("https://{value}")
+    };
+
+    let host = url.split("://").nth(1)?.split(['/', '?', '#']).next()?;
+    host.contains('.').then_some(url)
+}
+
+/// The admissions section by its id, or else the section that reports applicant numbers.
+fn admissions_section<'p, 'a>(
+    dom: &'p tl::VDom<'a>,
+    parser: &'p Parser<'a>,
+) -> Option<&'p HTMLTag<'a>> {
+    if let Some(section) = dom
+        .get_element_by_id(ADMISSIONS_SECTION_ID)
+        .and_then(|handle| handle.get(parser))
+        .and_then(Node::as_tag)
+    {
+        return Some(section);
+    }
+
+    let selector = format!("div[id^={SECTION_ID_PREFIX}]");
+    dom.query_selector(&selector)?
+        .filter_map(|handle| handle.get(parser)?.as_tag())
+        .map(|section| (section, section.inner_text(parser).to_lowercase()))
+        .filter(|(_, text)| text.contains("applicants") && text.contains("admitted"))
+        .min_by_key(|(_, text)| text.len())
+        .map(|(section, _)| section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_fixture(html: &str) -> CollegeNavigatorPage {
+        parse_college_page(html.as_bytes()).expect("fixture should parse")
+    }
+
+    #[test]
+    fn reads_links_and_admissions_from_a_full_page() {
+        let page = parse_fixture(include_str!("fixtures/mit.html"));
+
+        assert_eq!(
+            page.admissions_url.as_deref(),
+            Some("https://mitadmissions.org/")
+        );
+        assert_eq!(
+            page.apply_url.as_deref(),
+            Some("https://apply.mitadmissions.org/")
+        );
+        assert_eq!(page.finaid_url.as_deref(), Some("https://sfs.mit.edu/"));
+
+        let admissions_html = page.admissions_html.expect("admissions section");
+        assert!(admissions_html.contains("Number of applicants"));
+        assert!(admissions_html.contains("26,914"));
+        // Only the admissions section is taken, not the financial aid one after it.
+        assert!(!admissions_html.contains("All undergraduate students"));
+
+        assert_eq!(page.total_enrollment, Some(11_920));
+        assert_eq!(page.undergraduate_enrollment, Some(4_657));
+    }
+
+    #[test]
+    fn finds_links_by_label_when_rows_move() {
+        let page = parse_fixture(include_str!("fixtures/umich.html"));
+
+        assert_eq!(
+            page.admissions_url.as_deref(),
+            Some("https://admissions.umich.edu/")
+        );
+        assert_eq!(
+            page.apply_url.as_deref(),
+            Some("https://admissions.umich.edu/apply")
+        );
+        assert_eq!(
+            page.finaid_url.as_deref(),
+            Some("https://finaid.umich.edu/")
+        );
+        assert!(page
+            .admissions_html
+            .is_some_and(|html| html.contains("84,289")));
+    }
+
+    #[test]
+    fn open_admission_college_has_no_admissions_section() {
+        let page = parse_fixture(include_str!("fixtures/santa_monica_college.html"));
+
+        assert_eq!(
+            page.admissions_url.as_deref(),
+            Some("https://www.smc.edu/admission-aid/")
+        );
+        assert_eq!(
+            page.finaid_url.as_deref(),
+            Some("https://www.smc.edu/admission-aid/financial-aid-scholarships/")
+        );
+        // The admissions and financial aid links must not be mistaken for an application link.
+        assert_eq!(page.apply_url, None);
+        assert_eq!(page.admissions_html, None);
+        assert_eq!(page.total_enrollment, None);
+    }
+
+    #[test]
+    fn reads_student_population() {
+        assert_eq!(
+            parse_student_population("11,920 (4,657 undergraduate)"),
+            (Some(11_920), Some(4_657))
+        );
+        assert_eq!(
+            parse_student_population("846 (all undergraduate)"),
+            (Some(846), Some(846))
+        );
+        assert_eq!(parse_student_population("Not reported"), (None, None));
+    }
+
+    #[test]
+    fn falls_back_when_the_layout_changes() {
+        let page = parse_fixture(include_str!("fixtures/relabeled.html"));
+
+        // Printed without a link or scheme.
+        assert_eq!(
+            page.admissions_url.as_deref(),
+            Some("https://www.reed.edu/apply/")
+        );
+        // Found by its wording outside any labeled row.
+        assert_eq!(
+            page.apply_url.as_deref(),
+            Some("https://apply.commonapp.org/login?college=reed")
+        );
+        // The e-mail link is skipped and entities in the address are decoded.
+        assert_eq!(
+            page.finaid_url.as_deref(),
+            Some("https://www.reed.edu/financial_aid/?src=cn&tab=overview")
+        );
+        assert!(page
+            .admissions_html
+            .is_some_and(|html| html.contains("6,530")));
+    }
+
+    #[test]
+    fn page_without_an_institution_is_an_error() {
+        let search_page = r#"<html><body><div id="ctl00_cphCollegeNavBody_ucResultsMain_divMsg">
+            Your search returned 0 results.</div></body></html>"#;
+
+        assert_eq!(
+            parse_college_page(search_page.as_bytes()).unwrap_err(),
+            ScrapeError::MissingGeneralInfo
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        assert_eq!(
+            parse_college_page(b"<html>\xff\xfe</html>").unwrap_err(),
+            ScrapeError::InvalidEncoding
+        );
+    }
+}
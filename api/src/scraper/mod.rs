@@ -0,0 +1,93 @@
+// Scraping of institution pages that have no API of their own.
+pub mod admissions;
+pub mod college_navigator;
+
+#[derive(std::fmt::Debug, PartialEq)]
+pub enum ScrapeError {
+    Fetch,
+    InvalidEncoding,
+    InvalidHtml,
+    MissingGeneralInfo,
+    UnreadableAdmissions,
+}
+
+impl ScrapeError {
+    pub fn msg(&self) -> &'static str {
+        match self {
+            ScrapeError::Fetch => "Unable to fetch html data",
+            ScrapeError::InvalidEncoding => "Unable to parse html response",
+            ScrapeError::InvalidHtml => "Unable to parse html data",
+            ScrapeError::MissingGeneralInfo => "Unable to get general info from html data",
+            ScrapeError::UnreadableAdmissions => "Unable to read admissions from html data",
+        }
+    }
+}
+
+/// Decodes the character references pages commonly use in text and attribute values.
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| (&rest[1..end], end));
+        let replacement = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            }
+        });
+
+        match (replacement, entity) {
+            (Some(c), Some((_, end))) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decodes entities and collapses runs of whitespace, as a browser displays text.
+pub fn clean_text(text: &str) -> String {
+    decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_entities_and_keeps_stray_ampersands() {
+        assert_eq!(
+            decode_entities("Texas A&amp;M &#8211; &#x27;Aggies&#39; &bogus; & more"),
+            "Texas A&M \u{2013} 'Aggies' &bogus; & more"
+        );
+    }
+
+    #[test]
+    fn cleans_whitespace() {
+        assert_eq!(clean_text("\n  Apply&nbsp;Online:\n\t"), "Apply Online:");
+    }
+}
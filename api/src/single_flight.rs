@@ -0,0 +1,167 @@
+// Coalescing of concurrent calls for the same key into a single call.
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::watch;
+
+/// Runs at most one call per key at a time. Callers arriving while one is in flight wait for it
+/// and get a copy of its result.
+/// Waiters may be on other workers; only the caller that started the call polls it.
+pub struct SingleFlight<T> {
+    in_flight: Arc<Mutex<HashMap<String, watch::Receiver<Option<T>>>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Arc::default(),
+        }
+    }
+}
+
+enum Flight<T> {
+    Lead(watch::Sender<Option<T>>),
+    Wait(watch::Receiver<Option<T>>),
+    Alone,
+}
+
+/// Removes the key once the call finishes or is dropped, so a cancelled call does not leave
+/// waiters stuck.
+struct FlightGuard<T> {
+    in_flight: Arc<Mutex<HashMap<String, watch::Receiver<Option<T>>>>>,
+    key: String,
+}
+
+impl<T> Drop for FlightGuard<T> {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn is_running(&self, key: &str) -> bool {
+        self.in_flight
+            .lock()
+            .is_ok_and(|in_flight| in_flight.contains_key(key))
+    }
+
+    pub async fn run<F, Fut>(&self, key: &str, call: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        loop {
+            let flight = match self.in_flight.lock() {
+                Ok(mut in_flight) => match in_flight.get(key) {
+                    Some(rx) => Flight::Wait(rx.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        in_flight.insert(key.to_string(), rx);
+                        Flight::Lead(tx)
+                    }
+                },
+                // Without the map calls cannot be coalesced, but they can still be made.
+                Err(_) => Flight::Alone,
+            };
+
+            let mut rx = match flight {
+                Flight::Lead(tx) => return self.lead(key, tx, call).await,
+                Flight::Wait(rx) => rx,
+                Flight::Alone => return call().await,
+            };
+            let result = rx
+                .wait_for(Option::is_some)
+                .await
+                .ok()
+                .and_then(|result| result.clone());
+            if let Some(result) = result {
+                return result;
+            }
+            // The call was dropped before finishing, so start another.
+        }
+    }
+
+    async fn lead<F, Fut>(&self, key: &str, tx: watch::Sender<Option<T>>, call: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let guard = FlightGuard {
+            in_flight: self.in_flight.clone(),
+            key: key.to_string(),
+        };
+        let result = call().await;
+        drop(guard);
+        let _ = tx.send(Some(result.clone()));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, future::pending, time::Duration};
+
+    use actix_web::rt::{time, System};
+    use futures_util::future::join;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_callers_share_one_call() {
+        System::new().block_on(async {
+            let flight = SingleFlight::default();
+            let calls = Cell::new(0);
+            let call = || async {
+                calls.set(calls.get() + 1);
+                time::sleep(Duration::from_millis(20)).await;
+                calls.get()
+            };
+
+            let (first, second) = join(flight.run("key", call), flight.run("key", call)).await;
+            assert_eq!((first, second), (1, 1));
+            assert_eq!(calls.get(), 1);
+        });
+    }
+
+    #[test]
+    fn a_waiter_starts_a_new_call_when_the_leader_is_dropped() {
+        System::new().block_on(async {
+            let flight = SingleFlight::default();
+            let leader =
+                time::timeout(Duration::from_millis(20), flight.run("key", pending::<u32>));
+            let waiter = async {
+                // Let the leader claim the key first.
+                time::sleep(Duration::from_millis(5)).await;
+                assert!(flight.is_running("key"));
+                flight.run("key", || async { 7 }).await
+            };
+
+            let (leader, waiter) = join(leader, waiter).await;
+            assert!(leader.is_err());
+            assert_eq!(waiter, 7);
+        });
+    }
+
+    #[test]
+    fn the_key_is_removed_once_the_call_finishes() {
+        System::new().block_on(async {
+            let flight = SingleFlight::default();
+            let result = flight
+                .run("key", || async {
+                    assert!(flight.is_running("key"));
+                    1
+                })
+                .await;
+            assert_eq!(result, 1);
+            assert!(!flight.is_running("key"));
+
+            // The next call runs afresh rather than getting the previous result.
+            assert_eq!(flight.run("key", || async { 2 }).await, 2);
+        });
+    }
+}
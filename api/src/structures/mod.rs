@@ -11,6 +11,14 @@ pub struct CollegeStruct {
     pub zip: String,
     pub geo_point_2d: CollegeCoord,
     pub naics_desc: String,
+    // Admission metrics, known once the college's details have been fetched.
+    // Acceptance rate is a percentage; SAT is the math plus reading/writing average.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acceptance_rate: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sat_avg: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act_avg: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize, std::clone::Clone)]